@@ -0,0 +1,148 @@
+//! Benchmarks parsing and normalizing representative programs.
+//! Parsing is reported in bytes per second and normalizing in reduction steps per second,
+//! normalizing by evaluation in β-reductions per second.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use lamda_calc::{
+    eval::{
+        load_std, reduce_to_normal_form, reduce_to_normal_form_timed, term_hash, Env, Options,
+        Printer,
+    },
+    nbe::normalize_timed,
+    parser::{try_parse_prog, Expr, Term},
+};
+
+/// `(λx0. … λxn-1. x0 … xn-1) x1 … xn`, where every argument is captured by a later binder and must be renamed.
+/// Parsing backtracks at each nested abstraction, so its time grows exponentially with the depth.
+fn nested_abstractions(depth: usize) -> String {
+    let params: String = (0..depth).map(|i| format!("λx{}. ", i)).collect();
+    let body: Vec<String> = (0..depth).map(|i| format!("x{}", i)).collect();
+    let args: Vec<String> = (1..=depth).map(|i| format!("x{}", i)).collect();
+    format!("({}{}) {};", params, body.join(" "), args.join(" "))
+}
+
+fn programs() -> Vec<(&'static str, String)> {
+    vec![
+        ("church_mul", "Mul 3 4; Mul (Mul 2 3) 5;".to_string()),
+        (
+            "std_combinators",
+            "Not (And True False); If (IsZero 0) 1 2; Eq 3 (Add 1 2); Head (Tail (Cons 1 (Cons 2 Nil)));"
+                .to_string(),
+        ),
+        ("nested_abstractions", nested_abstractions(10)),
+    ]
+}
+
+/// The top-level terms of each program
+fn program_terms() -> Vec<(&'static str, Vec<Term>)> {
+    programs()
+        .into_iter()
+        .map(|(name, source)| {
+            let terms = try_parse_prog(&source)
+                .unwrap()
+                .into_iter()
+                .filter_map(|expr| match expr {
+                    Expr::Term(term) => Some(term),
+                    _ => None,
+                })
+                .collect();
+            (name, terms)
+        })
+        .collect()
+}
+
+/// Reduce all terms to normal form, returning the number of steps taken
+fn normalize(terms: &[Term], env: &Env) -> usize {
+    terms
+        .iter()
+        .map(|term| {
+            let (_, timing) = reduce_to_normal_form_timed(term, env, false, Printer::NONE).unwrap();
+            timing.steps
+        })
+        .sum()
+}
+
+/// Normalize all terms by evaluation, returning the number of β-reductions
+fn normalize_by_evaluation(terms: &[Term], env: &Env) -> usize {
+    terms
+        .iter()
+        .map(|term| {
+            normalize_timed(term, env, None, &Options::default())
+                .unwrap()
+                .1
+                .steps
+        })
+        .sum()
+}
+
+fn parsing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, source) in programs() {
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &source, |b, source| {
+            b.iter(|| try_parse_prog(black_box(source)))
+        });
+    }
+    group.finish();
+}
+
+fn normalizing(c: &mut Criterion) {
+    let mut env = Env::new();
+    load_std(&mut env).unwrap();
+    let mut group = c.benchmark_group("normalize");
+    for (name, terms) in program_terms() {
+        group.throughput(Throughput::Elements(normalize(&terms, &env) as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &terms, |b, terms| {
+            b.iter(|| normalize(black_box(terms), &env))
+        });
+    }
+    group.finish();
+}
+
+fn normalizing_by_evaluation(c: &mut Criterion) {
+    let mut env = Env::new();
+    load_std(&mut env).unwrap();
+    let mut group = c.benchmark_group("nbe");
+    for (name, terms) in program_terms() {
+        let steps = normalize_by_evaluation(&terms, &env);
+        group.throughput(Throughput::Elements(steps as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &terms, |b, terms| {
+            b.iter(|| normalize_by_evaluation(black_box(terms), &env))
+        });
+    }
+    group.finish();
+}
+
+/// The check cycle detection makes after each step, on numerals as arithmetic produces them.
+/// Hashing the new term is compared with comparing it structurally to the saved term.
+fn cycle_check(c: &mut Criterion) {
+    let mut env = Env::new();
+    load_std(&mut env).unwrap();
+    let mut group = c.benchmark_group("cycle_check");
+    for source in ["Mul 3 4;", "Mul 10 10;"] {
+        let prog = try_parse_prog(source).unwrap();
+        let Some(Expr::Term(term)) = prog.first() else {
+            unreachable!("{} is a term", source)
+        };
+        let numeral = reduce_to_normal_form(term, &env, false, Printer::NONE).unwrap();
+        let saved = (term_hash(&numeral), numeral.clone());
+        group.bench_with_input(BenchmarkId::new("hash", source), &numeral, |b, term| {
+            b.iter(|| term_hash(black_box(term)) == saved.0)
+        });
+        group.bench_with_input(
+            BenchmarkId::new("structural", source),
+            &numeral,
+            |b, term| b.iter(|| *black_box(term) == saved.1),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    parsing,
+    normalizing,
+    normalizing_by_evaluation,
+    cycle_check
+);
+criterion_main!(benches);
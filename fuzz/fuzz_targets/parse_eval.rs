@@ -0,0 +1,44 @@
+//! Parse arbitrary input and evaluate it with a step limit, which should only ever fail gracefully.
+//! Run with `cargo fuzz run parse_eval`.
+
+#![no_main]
+
+use lamda_calc::{
+    eval::{reduce_with_limit, Env, Strategy},
+    parser::{parse_prog_recover, Expr},
+    run, Config,
+};
+use libfuzzer_sys::fuzz_target;
+
+/// Terms can grow with each step, so only a few are taken
+const MAX_STEPS: usize = 100;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(source) = std::str::from_utf8(data) else {
+        return;
+    };
+    let (prog, errors) = parse_prog_recover(source);
+    if !errors.is_empty() {
+        return;
+    }
+    let config = Config {
+        max_steps: Some(MAX_STEPS),
+        ..Config::default()
+    };
+    let _ = run(source, &config);
+    // Most programs don't type check, so their terms are evaluated without checking as well
+    let mut env = Env::new();
+    for expr in prog {
+        match expr {
+            Expr::Assignment(name, _, term, _) => {
+                env.insert(name, term);
+            }
+            Expr::Term(term) => {
+                for strategy in [Strategy::Normal, Strategy::Whnf, Strategy::Lazy] {
+                    let _ = reduce_with_limit(&term, &env, strategy, MAX_STEPS);
+                }
+            }
+            _ => {}
+        }
+    }
+});
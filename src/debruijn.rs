@@ -0,0 +1,272 @@
+//! Nameless terms using de Bruijn indices, where a bound variable is the number of binders between it and its abstraction.
+//! β-reduction on nameless terms only shifts indices, variables can never be captured so nothing is renamed.
+
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    eval::{self, env_var, Env, EvalError, FreshNames, Options, Stopwatch, Timing},
+    parser::{LineInfo, Term},
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Reduce terms to normal form using de Bruijn indices instead of named substitution
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Check if reduction with de Bruijn indices is enabled
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// A term with bound variables replaced by de Bruijn indices
+#[derive(Debug, Clone, PartialEq)]
+pub enum Nameless {
+    Var(usize),                 // Bound variable, 0 refers to the innermost binder
+    Free(String),               // Free variable, possibly defined in the environment
+    Abs(String, Box<Nameless>), // Abstraction, keeping its parameter name for printing
+    App(Box<Nameless>, Box<Nameless>),
+    Fix(Box<Nameless>),
+    Unit,
+}
+
+/// Convert a term to its nameless form, type annotations and ascriptions are erased
+pub fn to_nameless(term: &Term) -> Nameless {
+    fn convert<'a>(term: &'a Term, binders: &mut Vec<&'a str>) -> Nameless {
+        match term {
+            Term::Abstraction(param, _, body, _) => {
+                binders.push(param);
+                let body = convert(body, binders);
+                binders.pop();
+                Nameless::Abs(param.clone(), Box::new(body))
+            }
+            Term::Application(e1, e2, _) => Nameless::App(
+                Box::new(convert(e1, binders)),
+                Box::new(convert(e2, binders)),
+            ),
+            Term::Variable(name, _, _) => match binders.iter().rev().position(|b| b == name) {
+                Some(index) => Nameless::Var(index),
+                None => Nameless::Free(name.clone()),
+            },
+            Term::Fix(f, _) => Nameless::Fix(Box::new(convert(f, binders))),
+            Term::Unit(_) => Nameless::Unit,
+            Term::Ascription(e, _, _) => convert(e, binders),
+        }
+    }
+    convert(term, &mut Vec::new())
+}
+
+/// Convert a nameless term back to a named term.
+/// Parameters keep their original names unless that would capture a variable, then they are freshened.
+pub fn from_nameless(term: &Nameless) -> Term {
+    from_nameless_fresh(term, eval::fresh_names())
+}
+
+/// Convert a nameless term back to a named term, freshening parameters with the given scheme
+pub fn from_nameless_fresh(term: &Nameless, scheme: FreshNames) -> Term {
+    fn convert(term: &Nameless, binders: &mut Vec<String>, scheme: FreshNames) -> Term {
+        let info = LineInfo(0, 0);
+        match term {
+            Nameless::Var(index) => {
+                let name = binders[binders.len() - 1 - index].clone();
+                Term::Variable(name, None, info)
+            }
+            Nameless::Free(name) => Term::Variable(name.clone(), None, info),
+            Nameless::Abs(hint, body) => {
+                let mut free = HashSet::new();
+                let mut outer = HashSet::new();
+                names_used(body, 0, &mut free, &mut outer);
+                // The name must not be free in the body, nor belong to an enclosing binder used in it
+                let taken = |name: &str| {
+                    free.contains(name)
+                        || outer
+                            .iter()
+                            .any(|index| binders[binders.len() - 1 - index] == name)
+                };
+                let name = if taken(hint) {
+                    scheme.fresh(hint, taken)
+                } else {
+                    hint.clone()
+                };
+                binders.push(name.clone());
+                let body = convert(body, binders, scheme);
+                binders.pop();
+                Term::Abstraction(name, None, Box::new(body), info)
+            }
+            Nameless::App(e1, e2) => Term::Application(
+                Box::new(convert(e1, binders, scheme)),
+                Box::new(convert(e2, binders, scheme)),
+                info,
+            ),
+            Nameless::Fix(f) => Term::Fix(Box::new(convert(f, binders, scheme)), info),
+            Nameless::Unit => Term::Unit(info),
+        }
+    }
+    convert(term, &mut Vec::new(), scheme)
+}
+
+/// Collect the free names of an abstraction body, and the enclosing binders it refers to
+/// (0 is the binder just outside the abstraction)
+fn names_used(
+    term: &Nameless,
+    depth: usize,
+    free: &mut HashSet<String>,
+    outer: &mut HashSet<usize>,
+) {
+    match term {
+        Nameless::Var(index) if *index > depth => {
+            outer.insert(index - depth - 1);
+        }
+        Nameless::Var(_) | Nameless::Unit => {}
+        Nameless::Free(name) => {
+            free.insert(name.clone());
+        }
+        Nameless::Abs(_, body) => names_used(body, depth + 1, free, outer),
+        Nameless::App(e1, e2) => {
+            names_used(e1, depth, free, outer);
+            names_used(e2, depth, free, outer);
+        }
+        Nameless::Fix(f) => names_used(f, depth, free, outer),
+    }
+}
+
+/// Add `by` to every index referring to a binder outside the term, those at or above `cutoff`
+fn shift(term: &Nameless, by: usize, cutoff: usize) -> Nameless {
+    match term {
+        Nameless::Var(index) if *index >= cutoff => Nameless::Var(index + by),
+        Nameless::Var(_) | Nameless::Free(_) | Nameless::Unit => term.clone(),
+        Nameless::Abs(name, body) => {
+            Nameless::Abs(name.clone(), Box::new(shift(body, by, cutoff + 1)))
+        }
+        Nameless::App(e1, e2) => Nameless::App(
+            Box::new(shift(e1, by, cutoff)),
+            Box::new(shift(e2, by, cutoff)),
+        ),
+        Nameless::Fix(f) => Nameless::Fix(Box::new(shift(f, by, cutoff))),
+    }
+}
+
+/// β-reduce `(λ. body) value`, replacing the outermost bound variable of the body with the value.
+/// The value is only shifted where it is inserted, and the indices of outer binders drop by one.
+pub fn substitute(body: &Nameless, value: &Nameless) -> Nameless {
+    fn subst(term: &Nameless, depth: usize, value: &Nameless) -> Nameless {
+        match term {
+            Nameless::Var(index) if *index == depth => shift(value, depth, 0),
+            Nameless::Var(index) if *index > depth => Nameless::Var(index - 1),
+            Nameless::Var(_) | Nameless::Free(_) | Nameless::Unit => term.clone(),
+            Nameless::Abs(name, body) => {
+                Nameless::Abs(name.clone(), Box::new(subst(body, depth + 1, value)))
+            }
+            Nameless::App(e1, e2) => Nameless::App(
+                Box::new(subst(e1, depth, value)),
+                Box::new(subst(e2, depth, value)),
+            ),
+            Nameless::Fix(f) => Nameless::Fix(Box::new(subst(f, depth, value))),
+        }
+    }
+    subst(body, 0, value)
+}
+
+/// Perform a single normal order reduction step, the same one as `eval::step` without native numerals
+pub fn step(term: &Nameless, env: &Env) -> Option<Nameless> {
+    beta_step(term, env).or_else(|| {
+        let inlined = inline_vars(term, env);
+        (inlined != *term).then_some(inlined)
+    })
+}
+
+/// Replace free variables defined in the environment with their definitions
+fn inline_vars(term: &Nameless, env: &Env) -> Nameless {
+    match term {
+        Nameless::Free(name) if env.contains_key(name) => {
+            to_nameless(&env_var(name, &None, env, &LineInfo(0, 0)))
+        }
+        Nameless::Var(_) | Nameless::Free(_) | Nameless::Unit => term.clone(),
+        Nameless::Abs(name, body) => Nameless::Abs(name.clone(), Box::new(inline_vars(body, env))),
+        Nameless::App(e1, e2) => Nameless::App(
+            Box::new(inline_vars(e1, env)),
+            Box::new(inline_vars(e2, env)),
+        ),
+        Nameless::Fix(f) => Nameless::Fix(Box::new(inline_vars(f, env))),
+    }
+}
+
+/// Reduce the leftmost-outermost redex of a nameless term
+fn beta_step(term: &Nameless, env: &Env) -> Option<Nameless> {
+    match term {
+        Nameless::Abs(name, body) => {
+            Some(Nameless::Abs(name.clone(), Box::new(beta_step(body, env)?)))
+        }
+        Nameless::App(e1, e2) => {
+            // Only when application is reduced, lookup env variables and substitute
+            if let Nameless::Free(name) = e1.as_ref() {
+                if env.contains_key(name) {
+                    let info = LineInfo(0, 0);
+                    return match to_nameless(&env_var(name, &None, env, &info)) {
+                        Nameless::Abs(_, body) => Some(substitute(&body, e2)),
+                        // Inlining the definition counts as a step on its own
+                        e1 => Some(Nameless::App(Box::new(e1), e2.clone())),
+                    };
+                }
+            }
+            if let Nameless::Abs(_, body) = e1.as_ref() {
+                return Some(substitute(body, e2));
+            }
+            if let Some(e1) = beta_step(e1, env) {
+                return Some(Nameless::App(Box::new(e1), e2.clone()));
+            }
+            Some(Nameless::App(e1.clone(), Box::new(beta_step(e2, env)?)))
+        }
+        Nameless::Var(_) | Nameless::Free(_) | Nameless::Unit => None,
+        // fix f = f (fix f)
+        Nameless::Fix(f) => Some(Nameless::App(f.clone(), Box::new(term.clone()))),
+    }
+}
+
+/// Nesting depth of a nameless term, computed without recursion
+fn depth(term: &Nameless) -> usize {
+    let mut max = 0;
+    let mut stack = vec![(term, 1)];
+    while let Some((term, d)) = stack.pop() {
+        max = max.max(d);
+        match term {
+            Nameless::Abs(_, body) | Nameless::Fix(body) => stack.push((body, d + 1)),
+            Nameless::App(e1, e2) => {
+                stack.push((e1, d + 1));
+                stack.push((e2, d + 1));
+            }
+            Nameless::Var(_) | Nameless::Free(_) | Nameless::Unit => {}
+        }
+    }
+    max
+}
+
+/// Reduce a term to normal form through its nameless form, failing after `max_steps` steps if given.
+/// The depth limit and the scheme freshening parameters of the result come from the options.
+pub fn reduce_to_normal_form_timed(
+    term: &Term,
+    env: &Env,
+    max_steps: Option<usize>,
+    options: &Options,
+) -> Result<(Term, Timing), EvalError> {
+    let start = Stopwatch::start();
+    let mut term = to_nameless(term);
+    let mut steps = 0;
+    while let Some(next) = step(&term, env) {
+        if let Some(max_steps) = max_steps.filter(|max| steps == *max) {
+            return Err(EvalError::StepLimit(max_steps));
+        }
+        if depth(&next) > options.max_depth {
+            return Err(EvalError::DepthExceeded(options.max_depth));
+        }
+        term = next;
+        steps += 1;
+    }
+    let term = from_nameless_fresh(&term, options.fresh_names);
+    let elapsed = start.elapsed();
+    Ok((term, Timing { elapsed, steps }))
+}
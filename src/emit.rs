@@ -0,0 +1,239 @@
+//! Machine readable output formats for parsed programs and errors
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{
+    eval::Env,
+    parser::{LineInfo, ParseError, Term},
+    print::{self, Palette},
+    types::{Ctx, TypeError},
+};
+
+/// Serialize a program, term or type to pretty-printed JSON
+#[cfg(feature = "json")]
+pub fn to_json<T: serde::Serialize + ?Sized>(value: &T) -> Result<String, String> {
+    serde_json::to_string_pretty(value).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "json"))]
+pub fn to_json<T: ?Sized>(_value: &T) -> Result<String, String> {
+    Err("JSON output requires building with `--features json`".to_string())
+}
+
+/// Deserialize a program, term or type previously emitted by `to_json`
+#[cfg(feature = "json")]
+pub fn from_json<T: serde::de::DeserializeOwned>(json: &str) -> Result<T, String> {
+    serde_json::from_str(json).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "json"))]
+pub fn from_json<T>(_json: &str) -> Result<T, String> {
+    Err("JSON input requires building with `--features json`".to_string())
+}
+
+/// The definitions and types of a REPL session, stored as the terms and types themselves
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct Session {
+    pub env: Env,
+    pub ctx: Ctx,
+}
+
+/// Write the environment and type context to a file as JSON.
+/// Unlike `print::env_program` nothing is printed, so loading the session restores the exact same terms.
+pub fn save_session(path: &str, env: &Env, ctx: &Ctx) -> Result<(), String> {
+    let session = Session {
+        env: env.clone(),
+        ctx: ctx.clone(),
+    };
+    std::fs::write(path, to_json(&session)?)
+        .map_err(|err| format!("Error writing file {}: {}", path, err))
+}
+
+/// Read an environment and type context saved by `save_session`
+pub fn load_session(path: &str) -> Result<Session, String> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|err| format!("Error reading file {}: {}", path, err))?;
+    from_json(&json).map_err(|err| format!("Error loading session {}: {}", path, err))
+}
+
+/// Render the syntax tree of a term as a Graphviz DOT graph
+pub fn to_dot(t: &Term) -> String {
+    /// Add the node for `t` and its children, returning the ID of `t`
+    fn node(t: &Term, next_id: &mut usize, out: &mut String) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+        let (label, children): (String, Vec<&Term>) = match t {
+            Term::Abstraction(param, Some(ty), body, _) => {
+                (format!("λ{} : {}", param, ty), vec![body])
+            }
+            Term::Abstraction(param, None, body, _) => (format!("λ{}", param), vec![body]),
+            Term::Application(f, x, _) => ("@".to_string(), vec![f, x]),
+            Term::Variable(name, Some(ty), _) => (format!("{} : {}", name, ty), vec![]),
+            Term::Variable(name, None, _) => (name.clone(), vec![]),
+            Term::Fix(f, _) => ("fix".to_string(), vec![f]),
+            Term::Unit(_) => ("()".to_string(), vec![]),
+            Term::Ascription(term, ty, _) => (format!(": {}", ty), vec![term]),
+        };
+        let label = label.replace('\\', "\\\\").replace('"', "\\\"");
+        out.push_str(&format!("    n{} [label=\"{}\"];\n", id, label));
+        for child in children {
+            let child_id = node(child, next_id, out);
+            out.push_str(&format!("    n{} -> n{};\n", id, child_id));
+        }
+        id
+    }
+
+    let mut out = "digraph term {\n".to_string();
+    node(t, &mut 0, &mut out);
+    out.push('}');
+    out
+}
+
+/// How errors are reported
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ErrorFormat {
+    /// Colored messages for people
+    #[default]
+    Human,
+    /// One JSON object per error, for editors
+    Json,
+}
+
+impl ErrorFormat {
+    pub fn from_name(name: &str) -> Option<ErrorFormat> {
+        match name {
+            "human" => Some(ErrorFormat::Human),
+            "json" => Some(ErrorFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+static JSON_ERRORS: AtomicBool = AtomicBool::new(false);
+
+/// Set how type and parse errors are reported
+pub fn set_error_format(format: ErrorFormat) {
+    JSON_ERRORS.store(format == ErrorFormat::Json, Ordering::Relaxed);
+}
+
+pub fn error_format() -> ErrorFormat {
+    if JSON_ERRORS.load(Ordering::Relaxed) {
+        ErrorFormat::Json
+    } else {
+        ErrorFormat::Human
+    }
+}
+
+/// The parts of an error an editor needs, the message has no colors
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+pub struct ErrorReport {
+    /// The kind of error, like `mismatch` or `parse`
+    pub code: &'static str,
+    pub message: String,
+    pub line: usize,
+    pub col: usize,
+    /// The types of a mismatch, `None` for other errors
+    pub expected: Option<String>,
+    pub found: Option<String>,
+}
+
+impl From<&TypeError> for ErrorReport {
+    fn from(err: &TypeError) -> Self {
+        let report = |code, message, info: &LineInfo| ErrorReport {
+            code,
+            message,
+            line: info.0,
+            col: info.1,
+            expected: None,
+            found: None,
+        };
+        match err {
+            TypeError::Mismatch {
+                expected,
+                found,
+                info,
+            } => {
+                let (expected, found) = (print::plain_type(expected), print::plain_type(found));
+                ErrorReport {
+                    expected: Some(expected.clone()),
+                    found: Some(found.clone()),
+                    ..report(
+                        "mismatch",
+                        format!("expected {} but found {}", expected, found),
+                        info,
+                    )
+                }
+            }
+            TypeError::NotAFunction(ty, info) => report(
+                "not_a_function",
+                format!("{} is not a function type", print::plain_type(ty)),
+                info,
+            ),
+            TypeError::Unbound(name, info) => {
+                report("unbound", format!("unbound variable `{}`", name), info)
+            }
+            TypeError::AnnotationConflict {
+                name,
+                first,
+                second,
+                info,
+            } => report(
+                "annotation_conflict",
+                format!(
+                    "`{}` is annotated {} where it is bound but {}",
+                    name,
+                    print::plain_type(first),
+                    print::plain_type(second)
+                ),
+                info,
+            ),
+        }
+    }
+}
+
+impl From<&ParseError> for ErrorReport {
+    fn from(err: &ParseError) -> Self {
+        let (line, col) = match err.line_col {
+            pest::error::LineColLocation::Pos(pos) => pos,
+            pest::error::LineColLocation::Span(start, _) => start,
+        };
+        ErrorReport {
+            code: "parse",
+            message: err.variant.message().to_string(),
+            line,
+            col,
+            expected: None,
+            found: None,
+        }
+    }
+}
+
+/// Print a type error in the selected error format
+pub fn type_error(err: TypeError, ctx: Option<&Ctx>, p: &Palette) -> String {
+    match error_format() {
+        ErrorFormat::Human => print::ty_err(err, ctx, p),
+        ErrorFormat::Json => report_json(&ErrorReport::from(&err)),
+    }
+}
+
+/// Print a parse error in the selected error format
+pub fn parse_error(err: &ParseError) -> String {
+    match error_format() {
+        ErrorFormat::Human => err.to_string(),
+        ErrorFormat::Json => report_json(&ErrorReport::from(err)),
+    }
+}
+
+/// Serialize an error report to JSON on a single line
+#[cfg(feature = "json")]
+pub fn report_json(report: &ErrorReport) -> String {
+    serde_json::to_string(report).unwrap_or_else(|e| e.to_string())
+}
+
+/// Without JSON support only the message is reported
+#[cfg(not(feature = "json"))]
+pub fn report_json(report: &ErrorReport) -> String {
+    report.message.clone()
+}
@@ -1,39 +1,209 @@
 use std::{
     borrow::Borrow,
-    collections::{HashMap, HashSet},
+    cell::RefCell,
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    fmt::Display,
+    hash::{Hash, Hasher},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Duration,
 };
 
 use crate::{
-    parser::{parse_prog, Expr, LineInfo, Program, Term, Type},
-    print,
-    types::{self, Ctx},
+    debruijn, emit, lazy, lint, nbe,
+    parser::{parse_prog, parse_prog_recover, Expr, LineInfo, Program, Term, Type},
+    print::{self, Palette, PLAIN},
+    types::{self, Ctx, TypeError},
 };
 
 /// Environment mapping variable names to terms
 pub type Env = HashMap<String, Term>;
 
+/// Errors that can occur while reducing a term
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    /// The term is nested deeper than the maximum depth, reducing it could overflow the stack
+    DepthExceeded(usize),
+    /// No normal form was reached within the given number of steps
+    StepLimit(usize),
+    /// A free variable is not defined in the environment, only reported in strict mode
+    Unbound(String, LineInfo),
+    /// Only terms and assignments are evaluated, the other expressions are handled before evaluation
+    NotEvaluated(&'static str),
+    /// The reduction came back to an earlier term after the given number of steps, so it never ends
+    Cycle(usize),
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalError::DepthExceeded(max) => {
+                write!(
+                    f,
+                    "Evaluation error: term is nested deeper than {} levels",
+                    max
+                )
+            }
+            EvalError::StepLimit(max) => {
+                write!(f, "Evaluation error: no normal form within {} steps", max)
+            }
+            EvalError::Unbound(name, info) => {
+                write!(f, "{}", print::unbound_err(name, info, &PLAIN))
+            }
+            EvalError::NotEvaluated(exprs) => write!(
+                f,
+                "Evaluation error: {} are not evaluated, only terms and assignments",
+                exprs
+            ),
+            EvalError::Cycle(steps) => write!(
+                f,
+                "Evaluation error: no normal form, the reduction loops after {} steps",
+                steps
+            ),
+        }
+    }
+}
+
+/// Default maximum nesting depth of terms, see `set_max_depth`
+pub const DEFAULT_MAX_DEPTH: usize = 5_000;
+
+static MAX_DEPTH: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_DEPTH);
+
+/// Set the maximum nesting depth of terms being reduced.
+/// Substitution and the other term traversals are recursive, so this bounds their stack usage.
+pub fn set_max_depth(depth: usize) {
+    MAX_DEPTH.store(depth, Ordering::Relaxed);
+}
+
+/// Nesting depth of a term, computed without recursion so it is safe on any input
+pub fn depth(term: &Term) -> usize {
+    let mut max = 0;
+    let mut stack = vec![(term, 1)];
+    while let Some((term, d)) = stack.pop() {
+        max = max.max(d);
+        match term {
+            Term::Abstraction(_, _, body, _) => stack.push((body, d + 1)),
+            Term::Application(e1, e2, _) => {
+                stack.push((e1, d + 1));
+                stack.push((e2, d + 1));
+            }
+            Term::Variable(_, _, _) | Term::Unit(_) => {}
+            Term::Fix(f, _) | Term::Ascription(f, _, _) => stack.push((f, d + 1)),
+        }
+    }
+    max
+}
+
+/// The maximum nesting depth of terms being reduced, see `set_max_depth`
+pub fn max_depth() -> usize {
+    MAX_DEPTH.load(Ordering::Relaxed)
+}
+
+/// Fail if a term is nested deeper than `max`
+fn check_depth(term: &Term, max: usize) -> Result<(), EvalError> {
+    if depth(term) > max {
+        return Err(EvalError::DepthExceeded(max));
+    }
+    Ok(())
+}
+
+/// How binders are renamed when substitution has to avoid capturing a variable.
+/// Every scheme produces names that can be parsed again.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum FreshNames {
+    /// Append primes: `x'`, `x''`, `x'''`, ...
+    #[default]
+    Primes,
+    /// Append a counter: `x0`, `x1`, `x2`, ...
+    Numbered,
+}
+
+impl FreshNames {
+    /// Look up a scheme by its command line name
+    pub fn from_name(name: &str) -> Option<FreshNames> {
+        match name {
+            "primes" => Some(FreshNames::Primes),
+            "numbered" => Some(FreshNames::Numbered),
+            _ => None,
+        }
+    }
+
+    /// The first name derived from `base` that is not taken
+    pub fn fresh(self, base: &str, taken: impl Fn(&str) -> bool) -> String {
+        (0..)
+            .map(|n| match self {
+                FreshNames::Primes => format!("{}{}", base, "'".repeat(n + 1)),
+                FreshNames::Numbered => format!("{}{}", base, n),
+            })
+            .find(|name| !taken(name))
+            .unwrap()
+    }
+}
+
+static FRESH_NAMES: AtomicU8 = AtomicU8::new(FreshNames::Primes as u8);
+
+/// Set the naming scheme used for fresh variables during substitution
+pub fn set_fresh_names(scheme: FreshNames) {
+    FRESH_NAMES.store(scheme as u8, Ordering::Relaxed);
+}
+
+/// The naming scheme for fresh variables, see `set_fresh_names`
+pub fn fresh_names() -> FreshNames {
+    match FRESH_NAMES.load(Ordering::Relaxed) {
+        1 => FreshNames::Numbered,
+        _ => FreshNames::Primes,
+    }
+}
+
 /// Substitute a variable in a term with another term
 /// This is used in β-reduction.
 ///
 /// See https://en.wikipedia.org/wiki/Lambda_calculus#Substitution.
 pub fn substitute(term: &Term, var: &str, value: &Term) -> Term {
+    substitute_fresh(term, var, value, fresh_names())
+}
+
+/// Substitute a variable in a term with another term, renaming binders with the given scheme
+pub fn substitute_fresh(term: &Term, var: &str, value: &Term, scheme: FreshNames) -> Term {
+    // The free variables of the value are the same everywhere, so they are only collected once
+    substitute_with(term, var, value, &free_vars(value), scheme)
+}
+
+fn substitute_with(
+    term: &Term,
+    var: &str,
+    value: &Term,
+    value_vars: &HashSet<String>,
+    scheme: FreshNames,
+) -> Term {
     match term {
         // (e1 e2)[var := value] = (e1[var := value]) (e2[var := value])
         Term::Application(e1, e2, info) => Term::Application(
-            Box::new(substitute(e1, var, value)),
-            Box::new(substitute(e2, var, value)),
+            Box::new(substitute_with(e1, var, value, value_vars, scheme)),
+            Box::new(substitute_with(e2, var, value, value_vars, scheme)),
             info.clone(),
         ),
         // (λx. e)[var := value] = λx. e  (x == var)
         Term::Abstraction(s, _, _, _) if s == var => term.clone(), // Bound variable, no substitution needed
-        // (λx. e)[var := value] = λx. e  (x in free_vars(value))
-        Term::Abstraction(s, ty, body, info) if free_vars(value).contains(s) => {
-            // Avoid variable capture collisions by generating a fresh variable name
-            let mut s_new = s.clone();
-            while free_vars(value).contains(&s_new) {
-                s_new.push('\'');
-            }
-            let new_body = substitute(&rename_var(body, s, &s_new), var, value);
+        // (λx. e)[var := value] = λx'. e[x := x'][var := value]  (x in free_vars(value))
+        Term::Abstraction(s, ty, body, info) if value_vars.contains(s) => {
+            let body_vars = free_vars(body);
+            // Nothing is substituted in the body, so the binder can't capture anything
+            if !body_vars.contains(var) {
+                return term.clone();
+            }
+            // Avoid variable capture collisions by generating a fresh variable name,
+            // it must not be free in the value nor in the body or it would be captured,
+            // and not bound in the body or the renamed occurrences would be captured there
+            let body_binders = bound_vars(body);
+            let s_new = scheme.fresh(s, |name| {
+                value_vars.contains(name) || body_vars.contains(name) || body_binders.contains(name)
+            });
+            let new_body =
+                substitute_with(&rename_var(body, s, &s_new), var, value, value_vars, scheme);
             Term::Abstraction(s_new, ty.clone(), Box::new(new_body), info.clone())
         }
         // (λx. e)[var := value] = λx. e[var := value]  (x != var and x not in free_vars(value))
@@ -42,14 +212,25 @@ pub fn substitute(term: &Term, var: &str, value: &Term) -> Term {
             Term::Abstraction(
                 s.clone(),
                 ty.clone(),
-                Box::new(substitute(body, var, value)),
+                Box::new(substitute_with(body, var, value, value_vars, scheme)),
                 info.clone(),
             )
         }
         // var[var := value] = value
         Term::Variable(v, _, _) if v == var => value.clone(),
         // x[var := value] = x   (x != var)
-        Term::Variable(_, _, _) => term.clone(),
+        Term::Variable(_, _, _) | Term::Unit(_) => term.clone(),
+        // (e : T)[var := value] = (e[var := value] : T)
+        Term::Ascription(e, ty, info) => Term::Ascription(
+            Box::new(substitute_with(e, var, value, value_vars, scheme)),
+            ty.clone(),
+            info.clone(),
+        ),
+        // (fix e)[var := value] = fix e[var := value]
+        Term::Fix(f, info) => Term::Fix(
+            Box::new(substitute_with(f, var, value, value_vars, scheme)),
+            info.clone(),
+        ),
     }
 }
 
@@ -76,9 +257,89 @@ pub fn free_vars(term: &Term) -> HashSet<String> {
             set.insert(s.clone());
             set
         }
+        // free_vars(fix e) = free_vars(e)
+        Term::Fix(f, _) => free_vars(f),
+        // free_vars((e : T)) = free_vars(e)
+        Term::Ascription(e, _, _) => free_vars(e),
+        // free_vars(()) = {}
+        Term::Unit(_) => HashSet::new(),
     }
 }
 
+/// Collect the variables bound by abstractions in a term, whether or not they are used
+pub fn bound_vars(term: &Term) -> HashSet<String> {
+    match term {
+        // bound_vars(λx. e) = bound_vars(e) + {x}
+        Term::Abstraction(s, _, body, _) => {
+            let mut set = bound_vars(body);
+            set.insert(s.clone());
+            set
+        }
+        // bound_vars(e1 e2) = bound_vars(e1) + bound_vars(e2)
+        Term::Application(e1, e2, _) => {
+            let mut set = bound_vars(e1);
+            set.extend(bound_vars(e2));
+            set
+        }
+        // bound_vars(fix e) = bound_vars(e)
+        Term::Fix(f, _) => bound_vars(f),
+        // bound_vars((e : T)) = bound_vars(e)
+        Term::Ascription(e, _, _) => bound_vars(e),
+        // bound_vars(x) = bound_vars(()) = {}
+        Term::Variable(_, _, _) | Term::Unit(_) => HashSet::new(),
+    }
+}
+
+/// Rename the bound variables of a term to `a`, `b`, … `z`, `a1`, `b1`, … in the order of their binders from left to right.
+/// Names of free variables are skipped, so α-equivalent terms are renamed to the same term.
+pub fn canonicalize_names(term: &Term) -> Term {
+    fn canonical(
+        term: &Term,
+        scope: &mut Vec<(String, String)>,
+        names: &mut impl Iterator<Item = String>,
+    ) -> Term {
+        match term {
+            Term::Abstraction(param, ty, body, info) => {
+                let name = names.next().unwrap();
+                scope.push((param.clone(), name.clone()));
+                let body = canonical(body, scope, names);
+                scope.pop();
+                Term::Abstraction(name, ty.clone(), Box::new(body), info.clone())
+            }
+            Term::Application(f, x, info) => Term::Application(
+                Box::new(canonical(f, scope, names)),
+                Box::new(canonical(x, scope, names)),
+                info.clone(),
+            ),
+            // The innermost binder of a variable gives its name
+            Term::Variable(name, ty, info) => match scope.iter().rev().find(|(old, _)| old == name)
+            {
+                Some((_, new)) => Term::Variable(new.clone(), ty.clone(), info.clone()),
+                None => term.clone(),
+            },
+            Term::Fix(f, info) => Term::Fix(Box::new(canonical(f, scope, names)), info.clone()),
+            Term::Ascription(e, ty, info) => Term::Ascription(
+                Box::new(canonical(e, scope, names)),
+                ty.clone(),
+                info.clone(),
+            ),
+            Term::Unit(_) => term.clone(),
+        }
+    }
+
+    let free = free_vars(term);
+    let mut names = (0..)
+        .map(|i: usize| {
+            let letter = (b'a' + (i % 26) as u8) as char;
+            match i / 26 {
+                0 => letter.to_string(),
+                round => format!("{}{}", letter, round),
+            }
+        })
+        .filter(|name| !free.contains(name));
+    canonical(term, &mut Vec::new(), &mut names)
+}
+
 // Rename a variable in a term
 pub fn rename_var(term: &Term, old_var: &str, new_var: &str) -> Term {
     match term {
@@ -103,66 +364,939 @@ pub fn rename_var(term: &Term, old_var: &str, new_var: &str) -> Term {
         Term::Variable(s, t, info) if s == old_var => {
             Term::Variable(new_var.to_string(), t.clone(), info.clone())
         }
-        Term::Variable(_, _, _) => term.clone(),
+        Term::Variable(_, _, _) | Term::Unit(_) => term.clone(),
+        Term::Fix(f, info) => Term::Fix(Box::new(rename_var(f, old_var, new_var)), info.clone()),
+        Term::Ascription(e, ty, info) => Term::Ascription(
+            Box::new(rename_var(e, old_var, new_var)),
+            ty.clone(),
+            info.clone(),
+        ),
     }
 }
 
-// Perform β-reduction on a lambda calculus term
-pub fn beta_reduce(term: &Term, env: &Env, mut bound_vars: HashSet<String>) -> Term {
+/// Unfold a fixpoint once, fix f = f (fix f)
+fn unfold(f: &Term, info: &LineInfo) -> Term {
+    Term::Application(
+        Box::new(f.clone()),
+        Box::new(Term::Fix(Box::new(f.clone()), info.clone())),
+        info.clone(),
+    )
+}
+
+/// Reduce only the leftmost-outermost redex of a term (normal order).
+/// Returns `None` if the term contains no redex.
+pub fn beta_step(term: &Term, env: &Env, bound_vars: HashSet<String>) -> Option<Term> {
+    beta_step_fresh(term, env, bound_vars, fresh_names())
+}
+
+/// Reduce the leftmost-outermost redex, renaming binders with the given scheme
+fn beta_step_fresh(
+    term: &Term,
+    env: &Env,
+    mut bound_vars: HashSet<String>,
+    scheme: FreshNames,
+) -> Option<Term> {
     match term {
         Term::Abstraction(var, ty, body, info) => {
             bound_vars.insert(var.clone());
-            Term::Abstraction(
+            let body = beta_step_fresh(body, env, bound_vars, scheme)?;
+            Some(Term::Abstraction(
                 var.clone(),
                 ty.clone(),
-                Box::new(beta_reduce(body, env, bound_vars)),
+                Box::new(body),
                 info.clone(),
-            )
+            ))
         }
         Term::Application(e1, e2, info1) => {
             // Only when application is reduced, lookup env variables and substitute
-            let e1 = if let Term::Variable(var, ty, info2) = e1.borrow() {
-                if !bound_vars.contains(var) {
-                    env_var(var, ty, env, info2)
-                } else {
-                    *e1.clone()
+            if let Term::Variable(var, ty, info2) = e1.borrow() {
+                if !bound_vars.contains(var) && env.contains_key(var) {
+                    let e1 = env_var(var, ty, env, info2);
+                    if let Term::Abstraction(var, _, body, _) = e1.borrow() {
+                        return Some(substitute_fresh(body, var, e2, scheme));
+                    }
+                    // Inlining the definition counts as a step on its own
+                    return Some(Term::Application(Box::new(e1), e2.clone(), info1.clone()));
                 }
-            } else {
-                *e1.clone()
-            };
+            }
             if let Term::Abstraction(var, _, body, _) = e1.borrow() {
-                substitute(body, var, e2)
+                return Some(substitute_fresh(body, var, e2, scheme));
+            }
+            if let Some(e1) = beta_step_fresh(e1, env, bound_vars.clone(), scheme) {
+                return Some(Term::Application(Box::new(e1), e2.clone(), info1.clone()));
+            }
+            let e2 = beta_step_fresh(e2, env, bound_vars, scheme)?;
+            Some(Term::Application(e1.clone(), Box::new(e2), info1.clone()))
+        }
+        Term::Variable(_, _, _) | Term::Unit(_) => None,
+        // Unfolding never ends on its own, the depth guard stops runaway recursion
+        Term::Fix(f, info) => Some(unfold(f, info)),
+        // Types are only used for checking, erasing them counts as a step
+        Term::Ascription(e, _, _) => Some(e.as_ref().clone()),
+    }
+}
+
+/// Check if a term contains no β-redex, an application with an abstraction in function position.
+/// If `eta` is set, η-redexes `λx. f x` where `x` is not free in `f` are not allowed either.
+pub fn is_normal_form(term: &Term, eta: bool) -> bool {
+    match term {
+        Term::Abstraction(var, _, body, _) => {
+            if let (true, Term::Application(f, x, _)) = (eta, body.as_ref()) {
+                if matches!(x.as_ref(), Term::Variable(v, _, _) if v == var)
+                    && !free_vars(f).contains(var)
+                {
+                    return false;
+                }
+            }
+            is_normal_form(body, eta)
+        }
+        Term::Application(f, x, _) => {
+            !matches!(f.as_ref(), Term::Abstraction(_, _, _, _))
+                && is_normal_form(f, eta)
+                && is_normal_form(x, eta)
+        }
+        Term::Variable(_, _, _) | Term::Unit(_) => true,
+        Term::Fix(_, _) | Term::Ascription(_, _, _) => false,
+    }
+}
+
+static NATIVE_NUMERALS: AtomicBool = AtomicBool::new(false);
+
+/// Compute `Succ`, `Add`, `Mul`, `IsZero`, `Leq` and `Eq` of Church numerals natively
+/// instead of by β-reduction
+pub fn set_native_numerals(enabled: bool) {
+    NATIVE_NUMERALS.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether numerals are computed natively, see `set_native_numerals`
+pub fn native_numerals() -> bool {
+    NATIVE_NUMERALS.load(Ordering::Relaxed)
+}
+
+/// Decode a Church numeral `λf. λx. f (f ... x)`.
+/// Numeric names not bound in the environment, such as `1000`, are numerals too.
+pub fn decode_numeral(term: &Term, env: &Env) -> Option<usize> {
+    match term {
+        Term::Variable(name, _, _) if env.contains_key(name) => {
+            decode_numeral(&env_var(name, &None, env, term.info()), env)
+        }
+        Term::Variable(name, _, _) => name.parse().ok(),
+        Term::Abstraction(f, _, body, _) => {
+            let Term::Abstraction(x, _, body, _) = body.as_ref() else {
+                return None;
+            };
+            if f == x {
+                return None;
+            }
+            let mut n = 0;
+            let mut body = body.as_ref();
+            while let Term::Application(g, arg, _) = body {
+                if !matches!(g.as_ref(), Term::Variable(v, _, _) if v == f) {
+                    return None;
+                }
+                n += 1;
+                body = arg;
+            }
+            matches!(body, Term::Variable(v, _, _) if v == x).then_some(n)
+        }
+        _ => None,
+    }
+}
+
+/// Encode a number as the Church numeral `λf. λx. f (f ... x)`
+pub fn encode_numeral(n: usize, info: &LineInfo) -> Term {
+    let var = |name: &str| Term::Variable(name.to_string(), None, info.clone());
+    let mut body = var("x");
+    for _ in 0..n {
+        body = Term::Application(Box::new(var("f")), Box::new(body), info.clone());
+    }
+    let inner = Term::Abstraction("x".to_string(), None, Box::new(body), info.clone());
+    Term::Abstraction("f".to_string(), None, Box::new(inner), info.clone())
+}
+
+/// Encode a boolean as the Church boolean `λtrue. λfalse. true` or `λtrue. λfalse. false`
+pub fn encode_bool(b: bool, info: &LineInfo) -> Term {
+    let var = |name: &str| Term::Variable(name.to_string(), None, info.clone());
+    let body = var(if b { "true" } else { "false" });
+    let inner = Term::Abstraction("false".to_string(), None, Box::new(body), info.clone());
+    Term::Abstraction("true".to_string(), None, Box::new(inner), info.clone())
+}
+
+/// The standard library combinators on numerals that are computed natively
+#[derive(Clone, Copy)]
+enum StdOp {
+    Succ,
+    Add,
+    Mul,
+    IsZero,
+    Leq,
+    Eq,
+}
+
+thread_local! {
+    /// Same definitions as in std.lc, in the order of `StdOp`. Parsed once, as they are
+    /// compared against every application while reducing. Thread local because types hold `Rc`s.
+    static STD_OPS: [Term; 6] = [
+        "λn.λf.λx.(f ((n f) x))",
+        "λm.λn.λf.λx.((m f) ((n f) x))",
+        "λm.λn.λf.λx.((m (n f)) x)",
+        "λn.((n λx.False) True)",
+        "λm.λn.(IsZero ((Sub m) n))",
+        "λm.λn.((And ((Leq m) n)) ((Leq n) m))",
+    ]
+    .map(|definition| match parse_prog(definition).remove(0) {
+        Expr::Term(op) => op,
+        _ => unreachable!("Standard library operations are terms"),
+    });
+}
+
+/// Check if a term is the standard library definition of a combinator on numerals
+fn is_std_op(term: &Term, op: StdOp, env: &Env) -> bool {
+    /// α-equivalence where free variables of the environment may be unfolded on either side,
+    /// at most `fuel` times along a path so recursive definitions can't loop
+    fn eq(
+        a: &Term,
+        b: &Term,
+        env: &Env,
+        bound: (&mut Vec<String>, &mut Vec<String>),
+        fuel: usize,
+    ) -> bool {
+        let (bound_a, bound_b) = bound;
+        let free = |term: &Term, bound: &[String]| match term {
+            Term::Variable(name, ty, info) if !bound.contains(name) => {
+                env.contains_key(name).then(|| env_var(name, ty, env, info))
+            }
+            _ => None,
+        };
+        match (a, b) {
+            (Term::Abstraction(x, _, body_a, _), Term::Abstraction(y, _, body_b, _)) => {
+                bound_a.push(x.clone());
+                bound_b.push(y.clone());
+                let result = eq(body_a, body_b, env, (bound_a, bound_b), fuel);
+                bound_a.pop();
+                bound_b.pop();
+                return result;
+            }
+            (Term::Application(f_a, x_a, _), Term::Application(f_b, x_b, _)) => {
+                return eq(f_a, f_b, env, (bound_a, bound_b), fuel)
+                    && eq(x_a, x_b, env, (bound_a, bound_b), fuel);
+            }
+            (Term::Variable(x, _, _), Term::Variable(y, _, _)) => {
+                match (
+                    bound_a.iter().rposition(|v| v == x),
+                    bound_b.iter().rposition(|v| v == y),
+                ) {
+                    (None, None) if x == y => return true,
+                    (None, None) => {}
+                    (i, j) => return i == j,
+                }
+            }
+            (Term::Ascription(a, _, _), _) => return eq(a, b, env, (bound_a, bound_b), fuel),
+            (_, Term::Ascription(b, _, _)) => return eq(a, b, env, (bound_a, bound_b), fuel),
+            _ => {}
+        }
+        if fuel == 0 {
+            return false;
+        }
+        if let Some(a) = free(a, bound_a) {
+            return eq(&a, b, env, (bound_a, bound_b), fuel - 1);
+        }
+        if let Some(b) = free(b, bound_b) {
+            return eq(a, &b, env, (bound_a, bound_b), fuel - 1);
+        }
+        false
+    }
+    // They may be inlined so names can't be relied on
+    STD_OPS.with(|ops| {
+        eq(
+            term,
+            &ops[op as usize],
+            env,
+            (&mut Vec::new(), &mut Vec::new()),
+            8,
+        )
+    })
+}
+
+/// Compute an application of `Succ`, `Add`, `Mul`, `IsZero`, `Leq` or `Eq` to numerals,
+/// if the term is one. The result is a Church numeral or boolean.
+/// Numerals of `max_depth` or more are left to reduction.
+fn native_op(term: &Term, env: &Env, max_depth: usize) -> Option<Term> {
+    let Term::Application(f, n, info) = term else {
+        return None;
+    };
+    let n = decode_numeral(n, env)?;
+    let result = match f.as_ref() {
+        Term::Application(op, m, _) => {
+            let m = decode_numeral(m, env)?;
+            if is_std_op(op, StdOp::Add, env) {
+                m.checked_add(n)
+            } else if is_std_op(op, StdOp::Mul, env) {
+                m.checked_mul(n)
+            } else if is_std_op(op, StdOp::Leq, env) {
+                return Some(encode_bool(m <= n, info));
+            } else if is_std_op(op, StdOp::Eq, env) {
+                return Some(encode_bool(m == n, info));
             } else {
-                Term::Application(
-                    Box::new(beta_reduce(&e1, env, bound_vars.clone())),
-                    Box::new(beta_reduce(e2, env, bound_vars)),
-                    info1.clone(),
-                )
+                None
             }
         }
-        Term::Variable(_, _, _) => term.clone(),
+        _ if is_std_op(f, StdOp::Succ, env) => n.checked_add(1),
+        _ if is_std_op(f, StdOp::IsZero, env) => return Some(encode_bool(n == 0, info)),
+        _ => None,
+    }?;
+    // Leave numerals too large to represent to the depth guard of pure reduction
+    (result < max_depth).then(|| encode_numeral(result, info))
+}
+
+/// Replace the leftmost-outermost arithmetic or comparison on numerals with its result.
+/// Returns `None` if there is nothing to compute natively.
+pub fn native_step(term: &Term, env: &Env) -> Option<Term> {
+    native_step_with(term, env, max_depth())
+}
+
+/// Compute like `native_step` with the given maximum depth instead of the global one
+fn native_step_with(term: &Term, env: &Env, max_depth: usize) -> Option<Term> {
+    if let Some(result) = native_op(term, env, max_depth) {
+        return Some(result);
+    }
+    let step = |term: &Term| native_step_with(term, env, max_depth);
+    match term {
+        Term::Abstraction(var, ty, body, info) => Some(Term::Abstraction(
+            var.clone(),
+            ty.clone(),
+            Box::new(step(body)?),
+            info.clone(),
+        )),
+        Term::Application(f, x, info) => match step(f) {
+            Some(f) => Some(Term::Application(Box::new(f), x.clone(), info.clone())),
+            None => Some(Term::Application(
+                f.clone(),
+                Box::new(step(x)?),
+                info.clone(),
+            )),
+        },
+        Term::Fix(f, info) => Some(Term::Fix(Box::new(step(f)?), info.clone())),
+        Term::Ascription(e, ty, info) => Some(Term::Ascription(
+            Box::new(step(e)?),
+            ty.clone(),
+            info.clone(),
+        )),
+        Term::Variable(_, _, _) | Term::Unit(_) => None,
+    }
+}
+
+/// Perform a single reduction step, inlining environment variables once no redex is left.
+/// Returns `None` if the term is in normal form.
+pub fn step(term: &Term, env: &Env) -> Option<Term> {
+    step_with(term, env, &Options::current())
+}
+
+/// Perform a single reduction step with the given options instead of the global ones
+fn step_with(term: &Term, env: &Env, options: &Options) -> Option<Term> {
+    if options.native_numerals {
+        if let Some(next) = native_step_with(term, env, options.max_depth) {
+            return Some(next);
+        }
+    }
+    beta_step_fresh(term, env, HashSet::new(), options.fresh_names).or_else(|| {
+        let inlined = inline_vars(term, env);
+        (inlined != *term).then_some(inlined)
+    })
+}
+
+/// Measures how long a reduction takes.
+/// Browsers have no clock for `Instant`, so on wasm the time is always zero.
+pub(crate) struct Stopwatch {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant,
+}
+
+impl Stopwatch {
+    pub(crate) fn start() -> Self {
+        Stopwatch {
+            #[cfg(not(target_arch = "wasm32"))]
+            start: std::time::Instant::now(),
+        }
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        #[cfg(not(target_arch = "wasm32"))]
+        return self.start.elapsed();
+        #[cfg(target_arch = "wasm32")]
+        Duration::ZERO
     }
 }
 
-/// Reduce a term to normal form by repeatedly applying β-reduction
-pub fn reduce_to_normal_form(term: &Term, env: &Env, verbose: bool, printer: PrinterFn) -> Term {
+/// Time spent reducing a term and the number of steps it took
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Timing {
+    pub elapsed: Duration,
+    pub steps: usize,
+}
+
+/// Reduce a term to normal form by repeatedly reducing the leftmost-outermost redex
+pub fn reduce_to_normal_form(
+    term: &Term,
+    env: &Env,
+    verbose: bool,
+    printer: Printer,
+) -> Result<Term, EvalError> {
+    reduce_to_normal_form_timed(term, env, verbose, printer).map(|(term, _)| term)
+}
+
+/// Reduce a term to normal form, measuring how long it takes
+pub fn reduce_to_normal_form_timed(
+    term: &Term,
+    env: &Env,
+    verbose: bool,
+    mut printer: Printer,
+) -> Result<(Term, Timing), EvalError> {
+    let result = if verbose {
+        reduce_to_normal_form_observed(term, env, &mut printer)
+    } else {
+        reduce_to_normal_form_observed(term, env, &mut ())
+    }?;
+    if lint::warn_stuck() {
+        warn_stuck(&result.0, env, printer);
+    }
+    Ok(result)
+}
+
+/// Print a warning for each application in a normal form that is stuck on a free variable
+fn warn_stuck(term: &Term, env: &Env, printer: Printer) {
+    for stuck in lint::stuck(term, env) {
+        printer.print(print::stuck_warning(stuck, printer.palette));
+    }
+}
+
+/// Reduce a term to normal form, showing every intermediate term to the observer
+pub fn reduce_to_normal_form_observed(
+    term: &Term,
+    env: &Env,
+    observer: &mut dyn ReductionObserver,
+) -> Result<(Term, Timing), EvalError> {
+    reduce_observed(term, env, Strategy::Normal, None, observer)
+}
+
+/// Reduce a term with the given strategy, showing every intermediate term to the observer.
+/// Fails if the term is not reduced within `max_steps` steps when a limit is given.
+pub fn reduce_observed(
+    term: &Term,
+    env: &Env,
+    strategy: Strategy,
+    max_steps: Option<usize>,
+    observer: &mut dyn ReductionObserver,
+) -> Result<(Term, Timing), EvalError> {
+    reduce_observed_with(
+        term,
+        env,
+        strategy,
+        max_steps,
+        observer,
+        &Options::current(),
+    )
+}
+
+/// Reduce a term like `reduce_observed`, with the given options instead of the global ones
+pub fn reduce_observed_with(
+    term: &Term,
+    env: &Env,
+    strategy: Strategy,
+    max_steps: Option<usize>,
+    observer: &mut dyn ReductionObserver,
+    options: &Options,
+) -> Result<(Term, Timing), EvalError> {
+    check_depth(term, options.max_depth)?;
+    let empty = Env::new();
+    let env = if options.inline { env } else { &empty };
+    if strategy == Strategy::Lazy {
+        // Call-by-need shares reductions between steps, so it is not a sequence of rewritten terms
+        return lazy::reduce(term, env, max_steps, options, observer);
+    }
+    let next_step = |term: &Term| match strategy {
+        Strategy::Normal => step_with(term, env, options),
+        Strategy::Whnf | Strategy::Lazy => head_step_fresh(term, env, options.fresh_names),
+    };
+    let start = Stopwatch::start();
     let mut term = term.clone();
-    loop {
-        let mut next = beta_reduce(&term, env, HashSet::new());
-        if next == term {
-            // Try to inline variables in the term
-            next = inline_vars(&next, env);
-            if next == term {
-                return term;
-            }
+    let mut steps = 0;
+    // Without a step limit a term that reduces back to itself would loop forever, so each term is
+    // compared with one saved after 1, 2, 4, ... steps (Brent's algorithm) by hash first
+    let mut saved = (term_hash(&term), term.clone());
+    while let Some(next) = next_step(&term) {
+        if let Some(max_steps) = max_steps.filter(|max| steps == *max) {
+            return Err(EvalError::StepLimit(max_steps));
         }
+        check_depth(&next, options.max_depth)?;
         term = next;
-        if verbose {
-            printer(print::term(&term));
+        steps += 1;
+        observer.on_step(steps, &term);
+        if max_steps.is_none() {
+            let hash = term_hash(&term);
+            if hash == saved.0 && alpha_eq(&term, &saved.1) {
+                return Err(EvalError::Cycle(steps));
+            }
+            if steps.is_power_of_two() {
+                saved = (hash, term.clone());
+            }
+        }
+    }
+    let elapsed = start.elapsed();
+    Ok((term, Timing { elapsed, steps }))
+}
+
+/// Maximum number of steps spent normalizing each side of an equality
+pub const EQ_MAX_STEPS: usize = 10_000;
+
+/// Remove all η-redexes of a term, `λx. f x` becomes `f` when `x` is not free in `f`
+pub fn eta_reduce(term: &Term) -> Term {
+    match term {
+        Term::Abstraction(var, ty, body, info) => {
+            let body = eta_reduce(body);
+            if let Term::Application(f, x, _) = &body {
+                if matches!(x.as_ref(), Term::Variable(v, _, _) if v == var)
+                    && !free_vars(f).contains(var)
+                {
+                    return f.as_ref().clone();
+                }
+            }
+            Term::Abstraction(var.clone(), ty.clone(), Box::new(body), info.clone())
+        }
+        Term::Application(f, x, info) => Term::Application(
+            Box::new(eta_reduce(f)),
+            Box::new(eta_reduce(x)),
+            info.clone(),
+        ),
+        Term::Fix(f, info) => Term::Fix(Box::new(eta_reduce(f)), info.clone()),
+        Term::Ascription(e, _, _) => eta_reduce(e),
+        Term::Variable(_, _, _) | Term::Unit(_) => term.clone(),
+    }
+}
+
+/// Expand a term into η-long form guided by its type, every subterm of function type
+/// becomes an abstraction, so `f : A -> B` becomes `λx. f x`. The types of variables
+/// are taken from the binders they are bound by, or from their own annotation
+pub fn to_eta_long(term: &Term, ty: &Type) -> Term {
+    fn long(term: &Term, ty: &Type, bound: &mut Vec<(String, Type)>) -> Term {
+        match (term, ty) {
+            (Term::Ascription(e, _, _), _) => long(e, ty, bound),
+            (Term::Abstraction(var, ann, body, info), Type::Abstraction(a, b)) => {
+                bound.push((var.clone(), a.as_ref().clone()));
+                let body = long(body, b, bound);
+                bound.pop();
+                Term::Abstraction(var.clone(), ann.clone(), Box::new(body), info.clone())
+            }
+            (_, Type::Abstraction(a, b)) => {
+                let free = free_vars(term);
+                let var = if free.contains("x") {
+                    fresh_names().fresh("x", |n| free.contains(n))
+                } else {
+                    "x".to_string()
+                };
+                bound.push((var.clone(), a.as_ref().clone()));
+                let arg = long(&Term::var(&var), a, bound);
+                let body = long(&Term::app(term.clone(), arg), b, bound);
+                bound.pop();
+                Term::abs(&var, body)
+            }
+            _ => {
+                let mut args = Vec::new();
+                let mut head = term;
+                while let Term::Application(f, x, info) = head {
+                    args.push((x.as_ref(), info));
+                    head = f;
+                }
+                let mut head_ty = match head {
+                    Term::Variable(name, ann, _) => bound
+                        .iter()
+                        .rev()
+                        .find(|(v, _)| v == name)
+                        .map(|(_, ty)| ty.clone())
+                        .or_else(|| ann.clone()),
+                    _ => None,
+                };
+                args.into_iter()
+                    .rev()
+                    .fold(head.clone(), |f, (x, info)| match head_ty.take() {
+                        Some(Type::Abstraction(a, b)) => {
+                            head_ty = Some(b.as_ref().clone());
+                            let x = long(x, &a, bound);
+                            Term::Application(Box::new(f), Box::new(x), info.clone())
+                        }
+                        _ => Term::Application(Box::new(f), Box::new(x.clone()), info.clone()),
+                    })
+            }
+        }
+    }
+    long(term, ty, &mut Vec::new())
+}
+
+/// Check if two terms are equal up to renaming of bound variables, ignoring type annotations
+pub fn alpha_eq(a: &Term, b: &Term) -> bool {
+    fn eq<'a>(
+        a: &'a Term,
+        b: &'a Term,
+        bound_a: &mut Vec<&'a str>,
+        bound_b: &mut Vec<&'a str>,
+    ) -> bool {
+        match (a, b) {
+            (Term::Abstraction(x, _, body_a, _), Term::Abstraction(y, _, body_b, _)) => {
+                bound_a.push(x);
+                bound_b.push(y);
+                let result = eq(body_a, body_b, bound_a, bound_b);
+                bound_a.pop();
+                bound_b.pop();
+                result
+            }
+            (Term::Application(f_a, x_a, _), Term::Application(f_b, x_b, _)) => {
+                eq(f_a, f_b, bound_a, bound_b) && eq(x_a, x_b, bound_a, bound_b)
+            }
+            (Term::Variable(x, _, _), Term::Variable(y, _, _)) => {
+                // Bound variables must refer to the same binder, free ones must have the same name
+                match (
+                    bound_a.iter().rposition(|v| v == x),
+                    bound_b.iter().rposition(|v| v == y),
+                ) {
+                    (None, None) => x == y,
+                    (i, j) => i == j,
+                }
+            }
+            (Term::Fix(f_a, _), Term::Fix(f_b, _)) => eq(f_a, f_b, bound_a, bound_b),
+            (Term::Unit(_), Term::Unit(_)) => true,
+            (Term::Ascription(a, _, _), _) => eq(a, b, bound_a, bound_b),
+            (_, Term::Ascription(b, _, _)) => eq(a, b, bound_a, bound_b),
+            _ => false,
+        }
+    }
+    eq(a, b, &mut Vec::new(), &mut Vec::new())
+}
+
+/// Hash a term up to renaming of bound variables, ignoring positions and types.
+/// Where ascriptions are is hashed, so removing one changes the hash unlike for `alpha_eq`.
+/// α-equivalent terms with the same ascriptions have the same hash, so terms with different hashes differ.
+pub fn term_hash(term: &Term) -> u64 {
+    fn hash<'a>(term: &'a Term, bound: &mut Vec<&'a str>, state: &mut DefaultHasher) {
+        match term {
+            Term::Abstraction(x, _, body, _) => {
+                0u8.hash(state);
+                bound.push(x);
+                hash(body, bound, state);
+                bound.pop();
+            }
+            Term::Application(f, x, _) => {
+                1u8.hash(state);
+                hash(f, bound, state);
+                hash(x, bound, state);
+            }
+            // Bound variables by the number of binders up to their own, free ones by name
+            Term::Variable(v, _, _) => match bound.iter().rev().position(|b| b == v) {
+                Some(i) => (2u8, i).hash(state),
+                None => (3u8, v).hash(state),
+            },
+            Term::Fix(f, _) => {
+                4u8.hash(state);
+                hash(f, bound, state);
+            }
+            Term::Unit(_) => 5u8.hash(state),
+            Term::Ascription(e, _, _) => {
+                6u8.hash(state);
+                hash(e, bound, state);
+            }
+        }
+    }
+    let mut state = DefaultHasher::new();
+    hash(term, &mut Vec::new(), &mut state);
+    state.finish()
+}
+
+/// Check if two terms are βη-equal by comparing their βη-normal forms up to α-equivalence.
+/// Fails if either side has no normal form within `EQ_MAX_STEPS` steps, the result is then unknown.
+pub fn beta_eta_equal(a: &Term, b: &Term, env: &Env) -> Result<bool, EvalError> {
+    let normalize =
+        |term| reduce_with_limit(term, env, Strategy::Normal, EQ_MAX_STEPS).map(|t| eta_reduce(&t));
+    Ok(alpha_eq(&normalize(a)?, &normalize(b)?))
+}
+
+/// Reduce a term with the given strategy, giving up if it takes more than `max_steps` steps
+pub fn reduce_with_limit(
+    term: &Term,
+    env: &Env,
+    strategy: Strategy,
+    max_steps: usize,
+) -> Result<Term, EvalError> {
+    reduce_observed(term, env, strategy, Some(max_steps), &mut ()).map(|(term, _)| term)
+}
+
+/// The terms of a reduction with a strategy, one for each step, ending at a normal form or after `max_steps` steps.
+/// Call-by-need shares reductions instead of rewriting the term, so it is stepped like head reduction.
+pub struct ReductionIter<'a> {
+    term: Term,
+    env: &'a Env,
+    next_step: fn(&Term, &Env) -> Option<Term>,
+    steps: usize,
+    max_steps: Option<usize>,
+}
+
+impl<'a> ReductionIter<'a> {
+    pub fn new(term: &Term, env: &'a Env, strategy: Strategy, max_steps: Option<usize>) -> Self {
+        ReductionIter {
+            term: term.clone(),
+            env,
+            next_step: match strategy {
+                Strategy::Normal => step,
+                Strategy::Whnf | Strategy::Lazy => head_step,
+            },
+            steps: 0,
+            max_steps,
         }
     }
 }
 
+impl Iterator for ReductionIter<'_> {
+    type Item = Term;
+
+    fn next(&mut self) -> Option<Term> {
+        if self.max_steps.is_some_and(|max| self.steps == max) {
+            return None;
+        }
+        let next = (self.next_step)(&self.term, self.env)?;
+        self.steps += 1;
+        self.term = next.clone();
+        Some(next)
+    }
+}
+
+/// Find the subterm the next reduction step with the given strategy and options reduces, the same one `step` or `head_step` picks.
+/// Returns `None` if the term is already reduced.
+pub fn redex<'a>(
+    term: &'a Term,
+    env: &Env,
+    strategy: Strategy,
+    options: &Options,
+) -> Option<&'a Term> {
+    subterm(term, &redex_path(term, env, strategy, options)?)
+}
+
+/// The path from the root of the term to the subterm the next reduction step with the given strategy reduces, see `redex`.
+/// Returns `None` if the term is already reduced.
+pub fn redex_path(
+    term: &Term,
+    env: &Env,
+    strategy: Strategy,
+    options: &Options,
+) -> Option<Vec<usize>> {
+    // Follow a child of the term, keeping its index in the path only if the redex is found there
+    fn descend(
+        path: &mut Vec<usize>,
+        child: usize,
+        find: impl FnOnce(&mut Vec<usize>) -> bool,
+    ) -> bool {
+        path.push(child);
+        let found = find(path);
+        if !found {
+            path.pop();
+        }
+        found
+    }
+    fn native(term: &Term, env: &Env, options: &Options, path: &mut Vec<usize>) -> bool {
+        if native_op(term, env, options.max_depth).is_some() {
+            return true;
+        }
+        match term {
+            Term::Abstraction(_, _, e, _) | Term::Fix(e, _) | Term::Ascription(e, _, _) => {
+                descend(path, 0, |path| native(e, env, options, path))
+            }
+            Term::Application(f, x, _) => {
+                descend(path, 0, |path| native(f, env, options, path))
+                    || descend(path, 1, |path| native(x, env, options, path))
+            }
+            Term::Variable(_, _, _) | Term::Unit(_) => false,
+        }
+    }
+    fn beta<'a>(
+        term: &'a Term,
+        env: &Env,
+        bound: &mut Vec<&'a str>,
+        path: &mut Vec<usize>,
+    ) -> bool {
+        match term {
+            Term::Abstraction(var, _, body, _) => {
+                bound.push(var);
+                let found = descend(path, 0, |path| beta(body, env, bound, path));
+                bound.pop();
+                found
+            }
+            Term::Application(f, x, _) => match f.as_ref() {
+                Term::Variable(var, _, _)
+                    if !bound.contains(&var.as_str()) && env.contains_key(var) =>
+                {
+                    true
+                }
+                Term::Abstraction(_, _, _, _) => true,
+                _ => {
+                    descend(path, 0, |path| beta(f, env, bound, path))
+                        || descend(path, 1, |path| beta(x, env, bound, path))
+                }
+            },
+            Term::Variable(_, _, _) | Term::Unit(_) => false,
+            Term::Fix(_, _) | Term::Ascription(_, _, _) => true,
+        }
+    }
+    // Without a redex, `step` inlines the free variables defined in the environment
+    fn inlined<'a>(
+        term: &'a Term,
+        env: &Env,
+        bound: &mut Vec<&'a str>,
+        path: &mut Vec<usize>,
+    ) -> bool {
+        match term {
+            Term::Abstraction(var, _, body, _) => {
+                bound.push(var);
+                let found = descend(path, 0, |path| inlined(body, env, bound, path));
+                bound.pop();
+                found
+            }
+            Term::Application(f, x, _) => {
+                descend(path, 0, |path| inlined(f, env, bound, path))
+                    || descend(path, 1, |path| inlined(x, env, bound, path))
+            }
+            Term::Variable(var, _, _) => !bound.contains(&var.as_str()) && env.contains_key(var),
+            Term::Fix(e, _) | Term::Ascription(e, _, _) => {
+                descend(path, 0, |path| inlined(e, env, bound, path))
+            }
+            Term::Unit(_) => false,
+        }
+    }
+    fn head(term: &Term, env: &Env, path: &mut Vec<usize>) -> bool {
+        match term {
+            Term::Application(f, _, _) => match f.as_ref() {
+                Term::Abstraction(_, _, _, _) => true,
+                Term::Variable(var, _, _) if env.contains_key(var) => {
+                    path.push(0);
+                    true
+                }
+                _ => descend(path, 0, |path| head(f, env, path)),
+            },
+            Term::Variable(var, _, _) => env.contains_key(var),
+            Term::Fix(_, _) | Term::Ascription(_, _, _) => true,
+            _ => false,
+        }
+    }
+    let mut path = Vec::new();
+    let found = match strategy {
+        Strategy::Normal => {
+            (options.native_numerals && native(term, env, options, &mut path))
+                || beta(term, env, &mut Vec::new(), &mut path)
+                || inlined(term, env, &mut Vec::new(), &mut path)
+        }
+        Strategy::Whnf | Strategy::Lazy => head(term, env, &mut path),
+    };
+    found.then_some(path)
+}
+
+/// Follow a path of child indices from the root of a term, as `redex_path` returns.
+/// The body of an abstraction, the function of an application and the term of `fix` or an ascription are child 0,
+/// the argument of an application is child 1. Returns `None` if the path leaves the term.
+pub fn subterm<'a>(term: &'a Term, path: &[usize]) -> Option<&'a Term> {
+    path.iter()
+        .try_fold(term, |term, child| match (term, child) {
+            (Term::Abstraction(_, _, e, _) | Term::Fix(e, _) | Term::Ascription(e, _, _), 0) => {
+                Some(e.as_ref())
+            }
+            (Term::Application(f, _, _), 0) => Some(f.as_ref()),
+            (Term::Application(_, x, _), 1) => Some(x.as_ref()),
+            _ => None,
+        })
+}
+
+/// Every step of a reduction, as the number of the step, the term after it,
+/// and the location and `redex_path` of the redex it reduced in the term before it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReductionTrace {
+    pub steps: Vec<(usize, Term, LineInfo, Vec<usize>)>,
+}
+
+/// Records the steps of a reduction, locating each redex in the term before the step
+struct Tracer<'a> {
+    env: &'a Env,
+    strategy: Strategy,
+    options: Options,
+    previous: Term,
+    trace: ReductionTrace,
+}
+
+impl ReductionObserver for Tracer<'_> {
+    fn on_step(&mut self, step: usize, term: &Term) {
+        let path =
+            redex_path(&self.previous, self.env, self.strategy, &self.options).unwrap_or_default();
+        let location = subterm(&self.previous, &path)
+            .unwrap_or(&self.previous)
+            .info()
+            .clone();
+        self.trace.steps.push((step, term.clone(), location, path));
+        self.previous = term.clone();
+    }
+}
+
+/// Reduce a term with the given strategy, recording every step in a trace
+pub fn reduce_with_trace(
+    term: &Term,
+    env: &Env,
+    strategy: Strategy,
+    max_steps: Option<usize>,
+) -> Result<(Term, ReductionTrace), EvalError> {
+    let options = Options::current();
+    let mut tracer = Tracer {
+        env,
+        strategy,
+        options,
+        previous: term.clone(),
+        trace: ReductionTrace::default(),
+    };
+    let (term, _) = reduce_observed_with(term, env, strategy, max_steps, &mut tracer, &options)?;
+    Ok((term, tracer.trace))
+}
+
+/// Reduce only the head redex of a term, without descending into abstraction bodies or arguments.
+/// Returns `None` if the term is in weak head normal form.
+fn head_step(term: &Term, env: &Env) -> Option<Term> {
+    head_step_fresh(term, env, fresh_names())
+}
+
+/// Reduce the head redex, renaming binders with the given scheme
+fn head_step_fresh(term: &Term, env: &Env, scheme: FreshNames) -> Option<Term> {
+    match term {
+        Term::Application(f, x, info) => match f.as_ref() {
+            Term::Abstraction(var, _, body, _) => Some(substitute_fresh(body, var, x, scheme)),
+            Term::Variable(var, ty, var_info) if env.contains_key(var) => Some(Term::Application(
+                Box::new(env_var(var, ty, env, var_info)),
+                x.clone(),
+                info.clone(),
+            )),
+            _ => {
+                let f = head_step_fresh(f, env, scheme)?;
+                Some(Term::Application(Box::new(f), x.clone(), info.clone()))
+            }
+        },
+        Term::Variable(var, ty, info) if env.contains_key(var) => Some(env_var(var, ty, env, info)),
+        Term::Fix(f, info) => Some(unfold(f, info)),
+        Term::Ascription(e, _, _) => Some(e.as_ref().clone()),
+        _ => None,
+    }
+}
+
+/// Reduce a term to weak head normal form, stopping once it is an abstraction
+/// or an application of a free variable.
+pub fn reduce_to_whnf(term: &Term, env: &Env) -> Result<Term, EvalError> {
+    reduce_to_whnf_timed(term, env).map(|(term, _)| term)
+}
+
+/// Reduce a term to weak head normal form, measuring how long it takes
+pub fn reduce_to_whnf_timed(term: &Term, env: &Env) -> Result<(Term, Timing), EvalError> {
+    reduce_observed(term, env, Strategy::Whnf, None, &mut ())
+}
+
 /// Inline a free variable in env into a term
 pub fn env_var(var: &str, ty: &Option<Type>, env: &Env, info: &LineInfo) -> Term {
     if let Some(expr) = env.get(var) {
@@ -180,6 +1314,90 @@ pub fn env_var(var: &str, ty: &Option<Type>, env: &Env, info: &LineInfo) -> Term
     Term::Variable(var.to_string(), ty.clone(), info.clone())
 }
 
+static INLINE: AtomicBool = AtomicBool::new(true);
+
+/// Unfold variables defined in the environment during reduction, when disabled they are left free
+pub fn set_inline(enabled: bool) {
+    INLINE.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether reduction unfolds environment definitions, see `set_inline`
+pub fn inline() -> bool {
+    INLINE.load(Ordering::Relaxed)
+}
+
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+/// Report free variables missing from the environment as errors instead of leaving them stuck
+pub fn set_strict(enabled: bool) {
+    STRICT.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether unbound variables are errors, see `set_strict`
+pub fn strict() -> bool {
+    STRICT.load(Ordering::Relaxed)
+}
+
+/// Free variables of a term that are not defined in the environment, in order of appearance
+pub fn unbound_vars(term: &Term, env: &Env) -> Vec<(String, LineInfo)> {
+    unbound_vars_with(term, env, native_numerals())
+}
+
+/// Free variables of a term missing from the environment, numeric names are bound if `native_numerals` is set
+fn unbound_vars_with(term: &Term, env: &Env, native_numerals: bool) -> Vec<(String, LineInfo)> {
+    fn collect<'a>(
+        term: &'a Term,
+        env: &Env,
+        native_numerals: bool,
+        bound: &mut Vec<&'a str>,
+        unbound: &mut Vec<(String, LineInfo)>,
+    ) {
+        match term {
+            Term::Abstraction(param, _, body, _) => {
+                bound.push(param);
+                collect(body, env, native_numerals, bound, unbound);
+                bound.pop();
+            }
+            Term::Application(e1, e2, _) => {
+                collect(e1, env, native_numerals, bound, unbound);
+                collect(e2, env, native_numerals, bound, unbound);
+            }
+            Term::Variable(name, _, info) => {
+                let numeral = native_numerals && name.parse::<usize>().is_ok();
+                if !bound.contains(&name.as_str())
+                    && !env.contains_key(name)
+                    && !numeral
+                    && !unbound.iter().any(|(n, _)| n == name)
+                {
+                    unbound.push((name.clone(), info.clone()));
+                }
+            }
+            Term::Fix(f, _) | Term::Ascription(f, _, _) => {
+                collect(f, env, native_numerals, bound, unbound)
+            }
+            Term::Unit(_) => {}
+        }
+    }
+    let mut unbound = Vec::new();
+    collect(term, env, native_numerals, &mut Vec::new(), &mut unbound);
+    unbound
+}
+
+/// Fail on the first free variable of a term that is not defined in the environment
+pub fn check_bound(term: &Term, env: &Env) -> Result<(), EvalError> {
+    check_bound_with(term, env, native_numerals())
+}
+
+fn check_bound_with(term: &Term, env: &Env, native_numerals: bool) -> Result<(), EvalError> {
+    match unbound_vars_with(term, env, native_numerals)
+        .into_iter()
+        .next()
+    {
+        Some((name, info)) => Err(EvalError::Unbound(name, info)),
+        None => Ok(()),
+    }
+}
+
 /// Inline variables in a term using the given environment
 pub fn inline_vars(term: &Term, env: &Env) -> Term {
     match &term {
@@ -195,64 +1413,642 @@ pub fn inline_vars(term: &Term, env: &Env) -> Term {
             info.clone(),
         ),
         Term::Variable(var, ty, info) => env_var(var, ty, env, info),
+        Term::Fix(f, info) => Term::Fix(Box::new(inline_vars(f, env)), info.clone()),
+        // Types are erased before evaluation
+        Term::Ascription(e, _, _) => inline_vars(e, env),
+        Term::Unit(_) => term.clone(),
     }
 }
 
-pub fn eval_expr(expr: &Expr, env: &mut Env, verbose: bool, printer: PrinterFn) -> Term {
+/// Settings of a reduction, library callers choose them per run while the interpreter uses the globals
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Options {
+    /// Compute arithmetic on numerals natively, see `set_native_numerals`
+    pub native_numerals: bool,
+    /// Report unbound variables as errors, see `set_strict`
+    pub strict: bool,
+    /// Unfold environment definitions, see `set_inline`
+    pub inline: bool,
+    /// How binders are renamed to avoid capture, see `set_fresh_names`
+    pub fresh_names: FreshNames,
+    /// Fail on terms nested deeper than this, see `set_max_depth`
+    pub max_depth: usize,
+    /// Reduce to normal form through de Bruijn indices, see `debruijn::set_enabled`
+    pub debruijn: bool,
+    /// Normalize by evaluation, see `nbe::set_enabled`
+    pub nbe: bool,
+    /// Warn about normal forms stuck on a free variable, see `lint::set_warn_stuck`
+    pub warn_stuck: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            native_numerals: false,
+            strict: false,
+            inline: true,
+            fresh_names: FreshNames::default(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            debruijn: false,
+            nbe: false,
+            warn_stuck: false,
+        }
+    }
+}
+
+impl Options {
+    /// The options set with the global setters
+    pub fn current() -> Self {
+        Options {
+            native_numerals: native_numerals(),
+            strict: strict(),
+            inline: inline(),
+            fresh_names: fresh_names(),
+            max_depth: max_depth(),
+            debruijn: debruijn::enabled(),
+            nbe: nbe::enabled(),
+            warn_stuck: lint::warn_stuck(),
+        }
+    }
+}
+
+pub fn eval_expr(
+    expr: &Expr,
+    env: &mut Env,
+    verbose: bool,
+    printer: Printer,
+) -> Result<Term, EvalError> {
+    eval_expr_timed(expr, env, verbose, printer).map(|(term, _)| term)
+}
+
+/// Evaluate an expression on top of a base environment without changing it.
+/// Returns the result and a copy of the base with the expression's assignment, if it is one.
+pub fn eval_expr_in(
+    expr: &Expr,
+    base: &Env,
+    verbose: bool,
+    printer: Printer,
+) -> Result<(Term, Env), EvalError> {
+    let mut env = base.clone();
+    let term = eval_expr(expr, &mut env, verbose, printer)?;
+    Ok((term, env))
+}
+
+/// Evaluate an expression, measuring how long reducing it takes
+pub fn eval_expr_timed(
+    expr: &Expr,
+    env: &mut Env,
+    verbose: bool,
+    printer: Printer,
+) -> Result<(Term, Timing), EvalError> {
     match expr {
-        Expr::Assignment(name, ty, val) => {
+        Expr::Assignment(name, ty, val, _) => {
             if verbose {
-                printer(print::assign(name, ty, val));
+                printer.print(print::assign(name, ty, val, printer.palette));
             }
             // Explicitly DON'T apply beta reduction here!
             // We want recursive combinators to not be evaluated until they are used
             env.insert(name.clone(), val.clone());
-            val.clone()
+            Ok((val.clone(), Timing::default()))
         }
-        Expr::TypeDef(_, _) => {
-            unreachable!("Type definitions should not be evaluated, only used for type checking")
+        // Type definitions are only used for type checking, imports are resolved and pragmas applied before
+        Expr::TypeDef(_, _) => Err(EvalError::NotEvaluated("type definitions")),
+        Expr::Import(_, _) => Err(EvalError::NotEvaluated("imports")),
+        Expr::Pragma(_, _) => Err(EvalError::NotEvaluated("pragmas")),
+        Expr::Term(term) => eval_term(
+            term,
+            env,
+            verbose,
+            printer,
+            Strategy::Normal,
+            None,
+            &Options::current(),
+        ),
+    }
+}
+
+/// Reduce a top-level term with the given strategy and options, within `max_steps` steps if given
+pub(crate) fn eval_term(
+    term: &Term,
+    env: &Env,
+    verbose: bool,
+    mut printer: Printer,
+    strategy: Strategy,
+    max_steps: Option<usize>,
+    options: &Options,
+) -> Result<(Term, Timing), EvalError> {
+    check_depth(term, options.max_depth)?;
+    if options.strict {
+        check_bound_with(term, env, options.native_numerals)?;
+    }
+    let empty = Env::new();
+    let env = if options.inline { env } else { &empty };
+    let term = inline_vars(term, env);
+    let quiet = strategy == Strategy::Normal && !verbose && !options.native_numerals;
+    let result = if options.debruijn && quiet {
+        // Nameless terms are only named again for the result, so their steps are not printed
+        debruijn::reduce_to_normal_form_timed(&term, env, max_steps, options)
+    } else if options.nbe && quiet {
+        // Values have no intermediate terms to print either
+        nbe::normalize_timed(&term, env, max_steps, options)
+    } else if verbose && strategy == Strategy::Normal {
+        // Only reduction to normal form prints its steps
+        printer.print(print::term(&term, printer.palette));
+        reduce_observed_with(&term, env, strategy, max_steps, &mut printer, options)
+    } else {
+        reduce_observed_with(&term, env, strategy, max_steps, &mut (), options)
+    }?;
+    if strategy == Strategy::Normal && options.warn_stuck {
+        warn_stuck(&result.0, env, printer);
+    }
+    Ok(result)
+}
+
+static STD_PATH: Mutex<Option<String>> = Mutex::new(None);
+
+/// Use the file at `path` as the standard library instead of the bundled one, or the bundled one again for `None`
+pub fn set_std_path(path: Option<String>) {
+    *STD_PATH.lock().unwrap() = path;
+}
+
+/// The file used as the standard library, `None` if it is the bundled one
+pub fn std_path() -> Option<String> {
+    STD_PATH.lock().unwrap().clone()
+}
+
+/// Get the source of the standard library from the file at `path`, or the bundled one without a path
+pub fn std_source(path: Option<&str>) -> Result<String, String> {
+    match path {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|err| format!("Cannot read standard library {}: {}", path, err)),
+        None => Ok(include_str!("./std.lc").to_string()),
+    }
+}
+
+/// Get the source of a module, `std` is the standard library at `std`, or the bundled one without a path
+fn module_source(module: &str, std: Option<&str>) -> Result<String, String> {
+    match module {
+        "std" => std_source(std),
+        _ => std::fs::read_to_string(format!("{}.lc", module))
+            .map_err(|err| format!("Cannot import module `{}`: {}", module, err)),
+    }
+}
+
+/// Evaluated module: its environment, type aliases, and the annotated type of each definition
+type Module = (Env, Ctx, HashMap<String, Option<Type>>);
+
+thread_local! {
+    /// The modules being loaded by canonical path and name, the innermost last
+    static LOADING: RefCell<Vec<(String, String)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Run `load` while `path` is marked as being loaded under the given name.
+/// Fails naming the cycle if the file is already being loaded, instead of loading it again forever.
+fn loading<T>(
+    path: &str,
+    name: &str,
+    load: impl FnOnce() -> Result<T, String>,
+) -> Result<T, String> {
+    // `a.lc` and `./a.lc` are the same file, files that don't exist keep their path
+    let key =
+        std::fs::canonicalize(path).map_or_else(|_| path.to_string(), |p| p.display().to_string());
+    let cycle = LOADING.with_borrow(|loading| {
+        let start = loading.iter().position(|(loaded, _)| *loaded == key)?;
+        let names: Vec<&str> = loading[start..]
+            .iter()
+            .map(|(_, name)| name.as_str())
+            .collect();
+        Some(format!("{} -> {}", names.join(" -> "), name))
+    });
+    if let Some(cycle) = cycle {
+        return Err(format!("Cyclic import: {}", cycle));
+    }
+    LOADING.with_borrow_mut(|loading| loading.push((key, name.to_string())));
+    let result = load();
+    LOADING.with_borrow_mut(|loading| loading.pop());
+    result
+}
+
+/// Evaluate the definitions of a module in a fresh environment
+fn load_module(module: &str, std: Option<&str>) -> Result<Module, String> {
+    let path = match module {
+        "std" => std.unwrap_or("std").to_string(),
+        _ => format!("{}.lc", module),
+    };
+    loading(&path, module, || {
+        eval_module(module, &module_source(module, std)?, std)
+    })
+}
+
+/// Evaluate the definitions in the source of a module in a fresh environment, importing `std` from the path `std`
+fn eval_module(module: &str, source: &str, std: Option<&str>) -> Result<Module, String> {
+    let (prog, errors) = parse_prog_recover(source.replace("\r", "").trim());
+    if !errors.is_empty() {
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        return Err(format!(
+            "Error parsing module `{}`:\n{}",
+            module,
+            errors.join("\n")
+        ));
+    }
+    let mut module_env = Env::new();
+    let mut module_ctx = Ctx::new();
+    let mut annotations = HashMap::new();
+    for expr in &prog {
+        match expr {
+            Expr::Assignment(name, ty, _, _) => {
+                eval_expr(expr, &mut module_env, false, Printer::NONE)
+                    .map_err(|err| err.to_string())?;
+                annotations.insert(name.clone(), ty.clone());
+            }
+            Expr::TypeDef(_, _) => {
+                types::check_expr(&mut module_ctx, expr)
+                    .map_err(|err| print::ty_err(err, Some(&module_ctx), &PLAIN))?;
+            }
+            Expr::Import(names, module) => {
+                import_from(names, module, std, &mut module_env, &mut module_ctx)?
+            }
+            // Pragmas only configure the program they are written in
+            Expr::Pragma(_, _) => {}
+            // Top-level terms of a module are not evaluated when importing
+            Expr::Term(_) => {}
         }
-        Expr::Term(term) => {
-            let term = inline_vars(term, env);
-            if verbose {
-                printer(print::term(&term));
+    }
+    Ok((module_env, module_ctx, annotations))
+}
+
+/// Load every definition of the standard library selected with `set_std_path` into the environment
+pub fn load_std(env: &mut Env) -> Result<(), String> {
+    load_std_from(std_path().as_deref(), env)
+}
+
+/// Load every definition of the standard library at `path` into the environment, or of the bundled one without a path
+pub fn load_std_from(path: Option<&str>, env: &mut Env) -> Result<(), String> {
+    let (std_env, _, _) = eval_module("std", &std_source(path)?, path)?;
+    env.extend(std_env);
+    Ok(())
+}
+
+/// Import the given names from a module into the environment and type context.
+///
+/// The module is evaluated in a temporary environment and only the requested bindings are copied.
+/// Other definitions of the module they depend on are substituted into them, so they don't leak into scope.
+/// Imported names get their annotated type, or `*` if they have none.
+pub fn import(names: &[String], module: &str, env: &mut Env, ctx: &mut Ctx) -> Result<(), String> {
+    import_from(names, module, std_path().as_deref(), env, ctx)
+}
+
+/// Import the given names from a module like `import`, with `std` being the standard library at the path `std`
+pub fn import_from(
+    names: &[String],
+    module: &str,
+    std: Option<&str>,
+    env: &mut Env,
+    ctx: &mut Ctx,
+) -> Result<(), String> {
+    /// Substitute the module definitions a term depends on, except for imported and recursive ones
+    fn resolve_deps(
+        term: &Term,
+        module_env: &Env,
+        names: &[String],
+        visiting: &mut Vec<String>,
+    ) -> Term {
+        let mut term = term.clone();
+        for var in free_vars(&term) {
+            if names.contains(&var) || visiting.contains(&var) {
+                continue;
+            }
+            if let Some(def) = module_env.get(&var) {
+                visiting.push(var.clone());
+                let def = resolve_deps(def, module_env, names, visiting);
+                visiting.pop();
+                term = substitute(&term, &var, &def);
             }
-            reduce_to_normal_form(&term, env, verbose, printer)
         }
+        term
     }
+
+    let (module_env, module_ctx, annotations) = load_module(module, std)?;
+    if let Some(missing) = names.iter().find(|name| !module_env.contains_key(*name)) {
+        return Err(format!(
+            "Module `{}` has no definition `{}`",
+            module, missing
+        ));
+    }
+    for name in names {
+        let term = resolve_deps(
+            &module_env[name],
+            &module_env,
+            names,
+            &mut vec![name.clone()],
+        );
+        let ty = match module_ctx.get(name) {
+            Some(ty) => ty.clone(),
+            None => match annotations.get(name).cloned().flatten() {
+                Some(ty) => Rc::new(types::resolve_type(&module_ctx, &ty)),
+                None => Rc::new(Type::Any),
+            },
+        };
+        env.insert(name.clone(), term);
+        ctx.insert(name.clone(), ty);
+    }
+    Ok(())
 }
 
-/// Run the given input program in the given environment
-pub fn eval_prog(input: String, env: &mut Env, verbose: bool, printer: PrinterFn) {
-    let mut terms: Program = parse_prog(input.replace("\r", "").trim());
-    if terms.is_empty() {
-        return;
+/// How far terms of a program are reduced
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Strategy {
+    /// Normal order reduction to normal form
+    #[default]
+    Normal,
+    /// Head reduction to weak head normal form
+    Whnf,
+    /// Call-by-need reduction to weak head normal form, reducing each argument at most once
+    Lazy,
+}
+
+/// Bring definitions of the environment without a known type into the type context as `*`
+pub(crate) fn bind_env(env: &Env, ctx: &mut Ctx) {
+    for name in env.keys() {
+        ctx.entry(name.clone())
+            .or_insert_with(|| Rc::new(Type::Any));
     }
-    let mut ctx = Ctx::new();
-    if let Err(err) = types::check_program(&mut ctx, &mut terms) {
-        printer(print::ty_err(err));
-        return;
+}
+
+/// Bind the numeric names used in a program as untyped, they stand for numerals when computed natively
+pub(crate) fn bind_numerals(prog: &Program, ctx: &mut Ctx) {
+    for expr in prog.iter() {
+        if let Expr::Assignment(_, _, term, _) | Expr::Term(term) = expr {
+            for var in free_vars(term) {
+                if var.parse::<usize>().is_ok() {
+                    ctx.entry(var).or_insert_with(|| Rc::new(Type::Any));
+                }
+            }
+        }
     }
-    if verbose {
-        printer(print::ctx(&ctx));
+}
+
+/// Infer the type of an expression in the given environment and type context, without evaluating it
+pub fn type_of(expr: &Expr, env: &Env, ctx: &Ctx) -> Result<Rc<Type>, TypeError> {
+    let mut ctx = ctx.clone();
+    bind_env(env, &mut ctx);
+    types::check_expr(&mut ctx, expr)
+}
+
+/// Find the definitions in the environment with a type compatible with `query`, sorted by name.
+/// Annotated definitions have the type in the context, the others are inferred.
+pub fn search(query: &Type, env: &Env, ctx: &Ctx) -> Vec<(String, Rc<Type>)> {
+    let query = types::resolve_type(ctx, query);
+    let mut found: Vec<(String, Rc<Type>)> = env
+        .iter()
+        .filter_map(|(name, term)| {
+            let ty = match ctx.get(name) {
+                Some(ty) if **ty != Type::Any => ty.clone(),
+                _ => type_of(&Expr::Term(term.clone()), env, ctx).ok()?,
+            };
+            types::compare_types(&query, &types::resolve_type(ctx, &ty)).then(|| (name.clone(), ty))
+        })
+        .collect();
+    found.sort_by(|(a, _), (b, _)| a.cmp(b));
+    found
+}
+
+/// Which term of a program is evaluated, counting from 1, 0 evaluates all of them
+static ONLY: AtomicUsize = AtomicUsize::new(0);
+
+/// Only evaluate and print the `n`th term of programs, 0 evaluates all of them
+pub fn set_only(n: usize) {
+    ONLY.store(n, Ordering::Relaxed);
+}
+
+/// Keep the `n`th term of a program, counting from 1, and the definitions, imports and pragmas before it
+pub fn only_term(mut prog: Program, n: usize) -> Result<Program, String> {
+    let terms = prog
+        .iter()
+        .filter(|expr| matches!(expr, Expr::Term(_)))
+        .count();
+    if n == 0 || n > terms {
+        return Err(format!(
+            "Can't evaluate term {}, the program has {} terms",
+            n, terms
+        ));
     }
-    for (i, expr) in terms.iter().enumerate() {
-        let term = eval_expr(expr, env, verbose, printer);
-        if matches!(expr, Expr::Assignment(_, _, _)) {
-            continue;
+    let mut seen = 0;
+    prog.retain(|expr| {
+        if seen == n {
+            return false;
         }
-        if verbose {
-            // Print all terms and their reduction steps
-            // println!("{}", print::term(&term));
-            if i < terms.len() - 1 {
-                print::line(20);
+        match expr {
+            Expr::Term(_) => {
+                seen += 1;
+                seen == n
             }
+            _ => true,
         }
-        if !verbose && i == terms.len() - 1 {
+    });
+    Ok(prog)
+}
+
+static RESULTS: AtomicBool = AtomicBool::new(false);
+
+/// Print the result of every term of a program instead of only the last one, unless verbose
+pub fn set_results(enabled: bool) {
+    RESULTS.store(enabled, Ordering::Relaxed);
+}
+
+/// Run the given input program in the given environment and type context.
+/// The type context is only updated if the whole program type checks.
+/// If `time` is set, the time and number of steps taken are printed after each result.
+/// Parse, import, type and evaluation errors stop the program and are returned for the caller to report.
+/// With `--results` every term is printed like in `eval_prog_results`.
+pub fn eval_prog(
+    input: String,
+    env: &mut Env,
+    ctx: &mut Ctx,
+    verbose: bool,
+    printer: Printer,
+    strategy: Strategy,
+    time: bool,
+) -> Result<(), String> {
+    let each = !verbose && RESULTS.load(Ordering::Relaxed);
+    eval_prog_results(input, env, ctx, verbose, printer, strategy, time, each)
+}
+
+/// Run a program like `eval_prog`, printing the result of each term separated by a line if `each` is set
+#[allow(clippy::too_many_arguments)]
+pub fn eval_prog_results(
+    input: String,
+    env: &mut Env,
+    ctx: &mut Ctx,
+    verbose: bool,
+    printer: Printer,
+    strategy: Strategy,
+    time: bool,
+    each: bool,
+) -> Result<(), String> {
+    let options = Options::current();
+    let config = crate::Config {
+        strategy,
+        max_steps: None,
+        use_std: false,
+        std_path: std_path(),
+        inline: options.inline,
+        only: match ONLY.load(Ordering::Relaxed) {
+            0 => None,
+            n => Some(n),
+        },
+        native_numerals: options.native_numerals,
+        strict: options.strict,
+        fresh_names: options.fresh_names,
+        max_depth: options.max_depth,
+        debruijn: options.debruijn,
+        nbe: options.nbe,
+        warn_shadow: lint::warn_shadow(),
+        lint_linear: lint::lint_linear(),
+        warn_stuck: options.warn_stuck,
+    };
+    let mut result = crate::RunResult::default();
+    let error = crate::execute(&input, &config, env, ctx, verbose, printer, &mut result).err();
+    for warning in result.warnings.iter() {
+        printer.print(warning.clone());
+    }
+    for (i, term) in result.terms.iter().enumerate() {
+        let last = i == result.terms.len() - 1 && error.is_none();
+        if each {
+            if i > 0 {
+                print::line(20, printer.palette);
+            }
+            printer.print(print::term(term, printer.palette));
+        } else if !verbose && last {
             // Always print the last term if not in verbose mode
-            printer(print::term(&term));
+            printer.print(print::term(term, printer.palette));
+        }
+        if time && (verbose || each || last) {
+            let timing = Timing {
+                elapsed: result.elapsed[i],
+                steps: result.steps[i],
+            };
+            printer.print(print::timing(&timing, printer.palette));
+        }
+    }
+    // The type context is kept once the program type checks, even if evaluating it fails
+    match error {
+        None => {
+            *ctx = result.ctx;
+            Ok(())
+        }
+        Some(crate::Error::Parse(errors)) => {
+            let errors: Vec<String> = errors.iter().map(emit::parse_error).collect();
+            Err(errors.join("\n"))
+        }
+        Some(crate::Error::Type(err)) => {
+            Err(emit::type_error(err, Some(&result.ctx), printer.palette))
+        }
+        Some(crate::Error::Import(err) | crate::Error::Only(err)) => Err(err),
+        Some(crate::Error::Eval(err)) => {
+            *ctx = result.ctx;
+            Err(err.to_string())
         }
     }
 }
 
-pub type PrinterFn = fn(String);
+/// Doc comments of definitions by name
+pub type Docs = HashMap<String, String>;
+
+/// The doc comments of the documented definitions in a program
+pub fn docs(prog: &Program) -> Docs {
+    prog.iter()
+        .filter_map(|expr| match expr {
+            Expr::Assignment(name, _, _, Some(doc)) => Some((name.clone(), doc.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Read and run each file in order in the same environment and type context, like `eval_prog`.
+/// Returns the doc comments of the definitions in the files.
+/// Stops at the first file that can't be read or parsed, with an error naming it, or that fails to run.
+pub fn load_files(
+    paths: &[&str],
+    env: &mut Env,
+    ctx: &mut Ctx,
+    verbose: bool,
+    printer: Printer,
+    strategy: Strategy,
+    time: bool,
+) -> Result<Docs, String> {
+    let mut loaded = Docs::new();
+    for path in paths {
+        let input = std::fs::read_to_string(path)
+            .map_err(|err| format!("Error reading file {}: {}", path, err))?;
+        let (prog, errors) = parse_prog_recover(input.replace("\r", "").trim());
+        if !errors.is_empty() {
+            let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            return Err(format!(
+                "Error parsing file {}:\n{}",
+                path,
+                errors.join("\n")
+            ));
+        }
+        loaded.extend(docs(&prog));
+        loading(path, path, || {
+            eval_prog(input, env, ctx, verbose, printer, strategy, time)
+        })?;
+    }
+    Ok(loaded)
+}
+
+/// Apply a `#key value` pragma to the evaluation of a program, returning false if it is unknown
+pub(crate) fn apply_pragma(
+    key: &str,
+    value: &str,
+    strategy: &mut Strategy,
+    max_steps: &mut Option<usize>,
+) -> bool {
+    match (key, value) {
+        ("strategy", "normal") => *strategy = Strategy::Normal,
+        ("strategy", "whnf") => *strategy = Strategy::Whnf,
+        ("strategy", "lazy") => *strategy = Strategy::Lazy,
+        ("max-steps", n) if n.parse::<usize>().is_ok() => *max_steps = n.parse().ok(),
+        _ => return false,
+    }
+    true
+}
+
+/// Where evaluation output goes, and the palette it is printed with
+#[derive(Clone, Copy)]
+pub struct Printer {
+    /// Called with each printed line and the palette, for anything it prints itself
+    pub out: fn(String, &Palette),
+    pub palette: &'static Palette,
+}
+
+impl Printer {
+    /// Print nothing
+    pub const NONE: Printer = Printer {
+        out: |_, _| {},
+        palette: &PLAIN,
+    };
+
+    pub fn print(&self, line: String) {
+        (self.out)(line, self.palette)
+    }
+}
+
+/// Observes each intermediate term of a reduction
+pub trait ReductionObserver {
+    /// Called after each reduction step with the number of steps taken so far
+    fn on_step(&mut self, step: usize, term: &Term);
+}
+
+/// Printers observe a reduction by printing every step
+impl ReductionObserver for Printer {
+    fn on_step(&mut self, _step: usize, term: &Term) {
+        self.print(print::term(term, self.palette));
+    }
+}
+
+/// Ignore all steps
+impl ReductionObserver for () {
+    fn on_step(&mut self, _step: usize, _term: &Term) {}
+}
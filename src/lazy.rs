@@ -0,0 +1,282 @@
+//! Call-by-need evaluation to weak head normal form.
+//! Arguments are passed as shared thunks, so each one is reduced at most once however often it is used.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use crate::{
+    eval::{
+        free_vars, rename_var, Env, EvalError, FreshNames, Options, ReductionObserver, Stopwatch,
+        Timing,
+    },
+    parser::Term,
+};
+
+/// A term together with the thunks of the variables bound around it
+#[derive(Clone)]
+struct Closure {
+    term: Term,
+    scope: Scope,
+}
+
+/// Variables bound by the abstractions a closure is under, innermost first
+type Scope = Option<Rc<Binding>>;
+
+struct Binding {
+    name: String,
+    thunk: Thunk,
+    next: Scope,
+}
+
+/// An argument shared by all uses of its variable, it is reduced the first time it is needed
+type Thunk = Rc<RefCell<State>>;
+
+#[derive(Clone)]
+enum State {
+    Delayed(Closure),
+    /// Reduced to weak head normal form
+    Forced(Closure),
+}
+
+fn lookup(scope: &Scope, name: &str) -> Option<Thunk> {
+    let mut scope = scope;
+    while let Some(binding) = scope {
+        if binding.name == name {
+            return Some(binding.thunk.clone());
+        }
+        scope = &binding.next;
+    }
+    None
+}
+
+fn bind(scope: Scope, name: String, thunk: Thunk) -> Scope {
+    Some(Rc::new(Binding {
+        name,
+        thunk,
+        next: scope,
+    }))
+}
+
+struct Machine<'a> {
+    env: &'a Env,
+    /// Definitions of the environment are shared too, once per reduction
+    globals: HashMap<String, Thunk>,
+    /// How binders of terms read back are renamed, and how deep evaluation may nest
+    options: Options,
+    steps: usize,
+    max_steps: Option<usize>,
+    observer: &'a mut dyn ReductionObserver,
+}
+
+impl Machine<'_> {
+    /// Count a step contracting the given redex
+    fn tick(&mut self, redex: &Term) -> Result<(), EvalError> {
+        if let Some(max_steps) = self.max_steps.filter(|max| self.steps == *max) {
+            return Err(EvalError::StepLimit(max_steps));
+        }
+        self.steps += 1;
+        self.observer.on_step(self.steps, redex);
+        Ok(())
+    }
+
+    fn global(&mut self, name: &str) -> Option<Thunk> {
+        let term = self.env.get(name)?;
+        let thunk = self.globals.entry(name.to_string()).or_insert_with(|| {
+            Rc::new(RefCell::new(State::Delayed(Closure {
+                term: term.clone(),
+                scope: None,
+            })))
+        });
+        Some(thunk.clone())
+    }
+
+    /// Reduce a thunk, updating it so later uses get the result directly
+    fn force(&mut self, thunk: &Thunk, depth: usize) -> Result<Closure, EvalError> {
+        let state = thunk.borrow().clone();
+        match state {
+            State::Forced(value) => Ok(value),
+            State::Delayed(closure) => {
+                let value = self.eval(closure, depth + 1)?;
+                *thunk.borrow_mut() = State::Forced(value.clone());
+                Ok(value)
+            }
+        }
+    }
+
+    /// Reduce a closure to weak head normal form
+    fn eval(&mut self, closure: Closure, depth: usize) -> Result<Closure, EvalError> {
+        // Evaluation is recursive, so nesting is bounded like the depth of terms
+        if depth > self.options.max_depth {
+            return Err(EvalError::DepthExceeded(self.options.max_depth));
+        }
+        let Closure { term, scope } = closure;
+        match term {
+            Term::Abstraction(_, _, _, _) | Term::Unit(_) => Ok(Closure { term, scope }),
+            Term::Variable(ref name, _, _) => {
+                match lookup(&scope, name).or_else(|| self.global(name)) {
+                    Some(thunk) => self.force(&thunk, depth),
+                    // Free variables are stuck
+                    None => Ok(Closure { term, scope }),
+                }
+            }
+            Term::Application(ref f, ref arg, ref info) => {
+                let f = self.eval(
+                    Closure {
+                        term: f.as_ref().clone(),
+                        scope: scope.clone(),
+                    },
+                    depth + 1,
+                )?;
+                let arg = Closure {
+                    term: arg.as_ref().clone(),
+                    scope,
+                };
+                match f.term {
+                    Term::Abstraction(ref param, _, ref body, _) => {
+                        self.tick(&Term::Application(
+                            Box::new(f.term.clone()),
+                            Box::new(arg.term.clone()),
+                            info.clone(),
+                        ))?;
+                        let thunk = Rc::new(RefCell::new(State::Delayed(arg)));
+                        let body = Closure {
+                            term: body.as_ref().clone(),
+                            scope: bind(f.scope.clone(), param.clone(), thunk),
+                        };
+                        self.eval(body, depth + 1)
+                    }
+                    // A stuck function is applied to its argument, which is left unreduced
+                    _ => Ok(Closure {
+                        term: Term::Application(
+                            Box::new(read_back(&f, self.options.fresh_names)),
+                            Box::new(read_back(&arg, self.options.fresh_names)),
+                            info.clone(),
+                        ),
+                        scope: None,
+                    }),
+                }
+            }
+            // fix f = f (fix f)
+            Term::Fix(ref f, ref info) => {
+                self.tick(&term)?;
+                let unfolded = Term::Application(f.clone(), Box::new(term.clone()), info.clone());
+                self.eval(
+                    Closure {
+                        term: unfolded,
+                        scope,
+                    },
+                    depth + 1,
+                )
+            }
+            // Types are only used for checking, erasing them counts as a step
+            Term::Ascription(ref e, _, _) => {
+                self.tick(&term)?;
+                let term = e.as_ref().clone();
+                self.eval(Closure { term, scope }, depth + 1)
+            }
+        }
+    }
+}
+
+/// Turn a closure back into a term by substituting the variables of its scope
+fn read_back(closure: &Closure, scheme: FreshNames) -> Term {
+    let mut values = HashMap::new();
+    for name in free_vars(&closure.term) {
+        if let Some(thunk) = lookup(&closure.scope, &name) {
+            let value = match &*thunk.borrow() {
+                State::Delayed(closure) | State::Forced(closure) => read_back(closure, scheme),
+            };
+            values.insert(name, value);
+        }
+    }
+    let value_vars: HashSet<String> = values.values().flat_map(free_vars).collect();
+    substitute_all(&closure.term, &values, &value_vars, scheme)
+}
+
+/// Substitute all variables at once, renaming binders that would capture a free variable of a value
+fn substitute_all(
+    term: &Term,
+    values: &HashMap<String, Term>,
+    value_vars: &HashSet<String>,
+    scheme: FreshNames,
+) -> Term {
+    if values.is_empty() {
+        return term.clone();
+    }
+    match term {
+        Term::Variable(name, _, _) => values.get(name).unwrap_or(term).clone(),
+        Term::Abstraction(param, ty, body, info) => {
+            let mut values = values.clone();
+            values.remove(param);
+            let (param, body) = if value_vars.contains(param) {
+                let body_vars = free_vars(body);
+                let fresh = scheme.fresh(param, |name| {
+                    value_vars.contains(name) || body_vars.contains(name)
+                });
+                let body = rename_var(body, param, &fresh);
+                (fresh, body)
+            } else {
+                (param.clone(), body.as_ref().clone())
+            };
+            Term::Abstraction(
+                param,
+                ty.clone(),
+                Box::new(substitute_all(&body, &values, value_vars, scheme)),
+                info.clone(),
+            )
+        }
+        Term::Application(e1, e2, info) => Term::Application(
+            Box::new(substitute_all(e1, values, value_vars, scheme)),
+            Box::new(substitute_all(e2, values, value_vars, scheme)),
+            info.clone(),
+        ),
+        Term::Fix(f, info) => Term::Fix(
+            Box::new(substitute_all(f, values, value_vars, scheme)),
+            info.clone(),
+        ),
+        Term::Ascription(e, ty, info) => Term::Ascription(
+            Box::new(substitute_all(e, values, value_vars, scheme)),
+            ty.clone(),
+            info.clone(),
+        ),
+        Term::Unit(_) => term.clone(),
+    }
+}
+
+/// Reduce a term to weak head normal form by call-by-need, failing after `max_steps` steps if given.
+/// The observer is shown the redex contracted by each step, as written in the program,
+/// the options bound the nesting of evaluation and rename binders of the result.
+pub fn reduce(
+    term: &Term,
+    env: &Env,
+    max_steps: Option<usize>,
+    options: &Options,
+    observer: &mut dyn ReductionObserver,
+) -> Result<(Term, Timing), EvalError> {
+    let start = Stopwatch::start();
+    let mut machine = Machine {
+        env,
+        globals: HashMap::new(),
+        options: *options,
+        steps: 0,
+        max_steps,
+        observer,
+    };
+    let closure = Closure {
+        term: term.clone(),
+        scope: None,
+    };
+    let value = machine.eval(closure, 1)?;
+    let term = read_back(&value, options.fresh_names);
+    let elapsed = start.elapsed();
+    Ok((
+        term,
+        Timing {
+            elapsed,
+            steps: machine.steps,
+        },
+    ))
+}
@@ -0,0 +1,310 @@
+//! Parser, evaluator and type checker for the lambda calculus, used by the interpreter in `main.rs`.
+//!
+//! [`run`] type checks and evaluates a whole program, the modules expose each stage on its own.
+//! [`run_in`] evaluates it on top of an existing environment without changing it.
+//!
+//! ```
+//! use lamda_calc::{print, run, Config};
+//!
+//! let source = "Twice : (* -> *) -> * -> * = λf. λx. f (f x); Twice (λy. y);";
+//! let terms = run(source, &Config::default()).into_result().unwrap();
+//! assert_eq!(print::plain_term(&terms[0]), "λx. x");
+//! ```
+
+use std::{fmt::Display, time::Duration};
+
+pub mod debruijn;
+pub mod emit;
+pub mod eval;
+mod lazy;
+pub mod lint;
+pub mod nbe;
+pub mod parser;
+pub mod print;
+pub mod types;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use eval::{reduce_to_normal_form, Env, EvalError, FreshNames, Strategy};
+pub use parser::{parse_prog_recover, try_parse_prog, Expr, ParseError, Program, Term, Type};
+pub use types::{check_program, Ctx, TypeError};
+
+/// How `run` evaluates a program, pragmas in the program take precedence
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// How far terms are reduced
+    pub strategy: Strategy,
+    /// Fail if a term is not reduced within this many steps
+    pub max_steps: Option<usize>,
+    /// Load the standard library before running the program
+    pub use_std: bool,
+    /// The file used as the standard library, by `use_std` and imports from `std`, the bundled one if `None`
+    pub std_path: Option<String>,
+    /// Unfold assigned variables during reduction, otherwise they are left free
+    pub inline: bool,
+    /// Only evaluate the term with this number, counting from 1, after the expressions before it
+    pub only: Option<usize>,
+    /// Compute arithmetic of numerals natively, numeric names are numerals
+    pub native_numerals: bool,
+    /// Fail on free variables that are not defined instead of leaving them stuck
+    pub strict: bool,
+    /// How binders are renamed when substitution would capture a variable
+    pub fresh_names: FreshNames,
+    /// Fail on terms nested deeper than this
+    pub max_depth: usize,
+    /// Reduce to normal form through de Bruijn indices
+    pub debruijn: bool,
+    /// Normalize by evaluation instead of reducing step by step
+    pub nbe: bool,
+    /// Warn about binders shadowing an enclosing binder
+    pub warn_shadow: bool,
+    /// Warn about bound variables that are not used exactly once
+    pub lint_linear: bool,
+    /// Warn about results applying a variable that is not defined
+    pub warn_stuck: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            strategy: Strategy::default(),
+            max_steps: None,
+            use_std: false,
+            std_path: None,
+            inline: true,
+            only: None,
+            native_numerals: false,
+            strict: false,
+            fresh_names: FreshNames::default(),
+            max_depth: eval::DEFAULT_MAX_DEPTH,
+            debruijn: false,
+            nbe: false,
+            warn_shadow: false,
+            lint_linear: false,
+            warn_stuck: false,
+        }
+    }
+}
+
+impl Config {
+    /// The options reduction runs with, instead of the globals the interpreter sets
+    fn options(&self) -> eval::Options {
+        eval::Options {
+            native_numerals: self.native_numerals,
+            strict: self.strict,
+            inline: self.inline,
+            fresh_names: self.fresh_names,
+            max_depth: self.max_depth,
+            debruijn: self.debruijn,
+            nbe: self.nbe,
+            // `execute` collects the warnings instead of printing them
+            warn_stuck: false,
+        }
+    }
+}
+
+/// Errors that can occur while running a program
+#[derive(Debug)]
+pub enum Error {
+    Parse(Vec<ParseError>),
+    Import(String),
+    Type(TypeError),
+    Eval(EvalError),
+    /// The program has no term with the number given in `Config::only`
+    Only(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Parse(errors) => {
+                let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", errors.join("\n"))
+            }
+            Error::Import(err) => write!(f, "{}", err),
+            Error::Type(err) => write!(f, "{}", print::ty_err(err.clone(), None, &print::PLAIN)),
+            Error::Eval(err) => write!(f, "{}", err),
+            Error::Only(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Everything a run produced, for callers that render the results, warnings and errors themselves
+#[derive(Debug, Default)]
+pub struct RunResult {
+    /// The result of each term evaluated before the run stopped, in order
+    pub terms: Vec<Term>,
+    /// The number of reduction steps each result took
+    pub steps: Vec<usize>,
+    /// How long each result took to reduce
+    pub elapsed: Vec<Duration>,
+    /// Warnings about ignored pragmas, stuck normal forms and the lints enabled in the `Config`
+    pub warnings: Vec<String>,
+    /// The type context the program was checked in, with its type definitions and annotated definitions
+    pub ctx: Ctx,
+    /// The error that stopped the run, if any
+    pub error: Option<Error>,
+}
+
+impl RunResult {
+    /// The results of all terms, or the error that stopped the run
+    pub fn into_result(self) -> Result<Vec<Term>, Error> {
+        match self.error {
+            Some(err) => Err(err),
+            None => Ok(self.terms),
+        }
+    }
+}
+
+/// Type check and evaluate a program in a fresh environment, without printing anything.
+/// Assignments only extend the environment, and unknown pragmas are reported as warnings.
+pub fn run(source: &str, config: &Config) -> RunResult {
+    let mut result = RunResult::default();
+    let printer = eval::Printer::NONE;
+    if let Err(err) = execute(
+        source,
+        config,
+        &mut Env::new(),
+        &Ctx::new(),
+        false,
+        printer,
+        &mut result,
+    ) {
+        result.error = Some(err);
+    }
+    result
+}
+
+/// Type check and evaluate a program on top of a base environment, like `run`.
+/// The base is left unchanged, the returned environment is a copy of it with the new definitions.
+pub fn run_in(source: &str, config: &Config, base: &Env) -> Result<(Vec<Term>, Env), Error> {
+    let mut env = base.clone();
+    let mut result = RunResult::default();
+    let printer = eval::Printer::NONE;
+    execute(
+        source,
+        config,
+        &mut env,
+        &Ctx::new(),
+        false,
+        printer,
+        &mut result,
+    )?;
+    Ok((result.terms, env))
+}
+
+/// Run a program in the environment on top of a type context, collecting what it produces until an error stops it.
+/// The context the program is checked in is returned in `result.ctx`, for the caller to keep if it type checks.
+/// Warnings are colored with the printer's palette. With `verbose` the context, assignments and
+/// reduction steps are printed as they happen, the results are left to the caller.
+pub(crate) fn execute(
+    source: &str,
+    config: &Config,
+    env: &mut Env,
+    ctx: &Ctx,
+    verbose: bool,
+    printer: eval::Printer,
+    result: &mut RunResult,
+) -> Result<(), Error> {
+    let p = printer.palette;
+    result.ctx = ctx.clone();
+    let (mut prog, errors) = parser::parse_prog_recover(source.replace("\r", "").trim());
+    if !errors.is_empty() {
+        return Err(Error::Parse(errors));
+    }
+    // An empty program has no term to pick
+    if prog.is_empty() {
+        return Ok(());
+    }
+    if let Some(n) = config.only {
+        prog = eval::only_term(prog, n).map_err(Error::Only)?;
+    }
+    let ctx = &mut result.ctx;
+    // The standard library and imports are staged in a copy of the environment, kept once the program type checks
+    let mut imported = env.clone();
+    if config.use_std {
+        eval::load_std_from(config.std_path.as_deref(), &mut imported).map_err(Error::Import)?;
+    }
+    // Definitions from earlier programs are in scope, even if their types are unknown
+    eval::bind_env(&imported, ctx);
+    let mut strategy = config.strategy;
+    let mut max_steps = config.max_steps;
+    for expr in prog.iter() {
+        match expr {
+            Expr::Import(names, module) => eval::import_from(
+                names,
+                module,
+                config.std_path.as_deref(),
+                &mut imported,
+                ctx,
+            )
+            .map_err(Error::Import)?,
+            Expr::Pragma(key, value)
+                if !eval::apply_pragma(key, value, &mut strategy, &mut max_steps) =>
+            {
+                result.warnings.push(print::pragma_warning(key, value, p));
+            }
+            _ => {}
+        }
+    }
+    prog.retain(|expr| !matches!(expr, Expr::Import(_, _) | Expr::Pragma(_, _)));
+    if config.warn_shadow {
+        for (name, info) in lint::shadowed(&prog) {
+            result.warnings.push(print::shadow_warning(&name, &info, p));
+        }
+    }
+    if config.lint_linear {
+        for (name, uses, info) in lint::nonlinear(&prog) {
+            result
+                .warnings
+                .push(print::linear_warning(&name, uses, &info, p));
+        }
+    }
+    let ctx = &mut result.ctx;
+    if config.native_numerals {
+        eval::bind_numerals(&prog, ctx);
+    }
+    check_program(ctx, &mut prog).map_err(Error::Type)?;
+    *env = imported;
+    if verbose {
+        printer.print(print::ctx(ctx, p));
+    }
+    for (i, expr) in prog.iter().enumerate() {
+        match expr {
+            Expr::Assignment(name, ty, term, _) => {
+                if verbose {
+                    printer.print(print::assign(name, ty, term, p));
+                }
+                env.insert(name.clone(), term.clone());
+            }
+            Expr::Term(term) => {
+                let (term, timing) = eval::eval_term(
+                    term,
+                    env,
+                    verbose,
+                    printer,
+                    strategy,
+                    max_steps,
+                    &config.options(),
+                )
+                .map_err(Error::Eval)?;
+                // Normal forms stuck on a free variable are warned about like in `eval::eval_term`
+                if config.warn_stuck && strategy == Strategy::Normal {
+                    for stuck in lint::stuck(&term, env) {
+                        result.warnings.push(print::stuck_warning(stuck, p));
+                    }
+                }
+                result.terms.push(term);
+                result.steps.push(timing.steps);
+                result.elapsed.push(timing.elapsed);
+                if verbose && i < prog.len() - 1 {
+                    print::line(20, p);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
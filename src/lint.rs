@@ -0,0 +1,150 @@
+//! Optional checks that warn about suspicious but valid programs
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{
+    eval::Env,
+    parser::{Expr, LineInfo, Program, Term},
+};
+
+static WARN_SHADOW: AtomicBool = AtomicBool::new(false);
+static WARN_STUCK: AtomicBool = AtomicBool::new(false);
+static LINT_LINEAR: AtomicBool = AtomicBool::new(false);
+
+/// Enable or disable warnings for binders shadowing an enclosing binder
+pub fn set_warn_shadow(enabled: bool) {
+    WARN_SHADOW.store(enabled, Ordering::Relaxed);
+}
+
+/// Check if shadowing warnings are enabled
+pub fn warn_shadow() -> bool {
+    WARN_SHADOW.load(Ordering::Relaxed)
+}
+
+/// Enable or disable warnings for normal forms applying a free variable
+pub fn set_warn_stuck(enabled: bool) {
+    WARN_STUCK.store(enabled, Ordering::Relaxed);
+}
+
+/// Check if stuck term warnings are enabled
+pub fn warn_stuck() -> bool {
+    WARN_STUCK.load(Ordering::Relaxed)
+}
+
+/// Enable or disable warnings for bound variables that are not used exactly once
+pub fn set_lint_linear(enabled: bool) {
+    LINT_LINEAR.store(enabled, Ordering::Relaxed);
+}
+
+/// Check if linearity warnings are enabled
+pub fn lint_linear() -> bool {
+    LINT_LINEAR.load(Ordering::Relaxed)
+}
+
+/// Count the free occurrences of a variable in a term, occurrences under a binder of the same name are not counted
+pub fn usage_count(term: &Term, var: &str) -> usize {
+    match term {
+        Term::Variable(name, _, _) => (name == var) as usize,
+        Term::Abstraction(param, _, _, _) if param == var => 0,
+        Term::Abstraction(_, _, body, _) => usage_count(body, var),
+        Term::Application(f, x, _) => usage_count(f, var) + usage_count(x, var),
+        Term::Fix(f, _) | Term::Ascription(f, _, _) => usage_count(f, var),
+        Term::Unit(_) => 0,
+    }
+}
+
+/// Find all binders whose variable is not used exactly once in the body, as in the linear lambda calculus.
+/// Returns the name, how many times it is used and where the abstraction is.
+pub fn nonlinear(prog: &Program) -> Vec<(String, usize, LineInfo)> {
+    fn walk(term: &Term, found: &mut Vec<(String, usize, LineInfo)>) {
+        match term {
+            Term::Abstraction(param, _, body, info) => {
+                let uses = usage_count(body, param);
+                if uses != 1 {
+                    found.push((param.clone(), uses, info.clone()));
+                }
+                walk(body, found);
+            }
+            Term::Application(f, x, _) => {
+                walk(f, found);
+                walk(x, found);
+            }
+            Term::Fix(f, _) | Term::Ascription(f, _, _) => walk(f, found),
+            Term::Variable(_, _, _) | Term::Unit(_) => {}
+        }
+    }
+
+    let mut found = Vec::new();
+    for expr in prog {
+        if let Expr::Assignment(_, _, term, _) | Expr::Term(term) = expr {
+            walk(term, &mut found);
+        }
+    }
+    found
+}
+
+/// Find all applications headed by a variable that is neither bound nor in the environment, like `undefined y`.
+/// They can't be reduced, so in a normal form they are often a misspelled name.
+pub fn stuck<'a>(term: &'a Term, env: &Env) -> Vec<&'a Term> {
+    fn walk<'a>(term: &'a Term, env: &Env, binders: &mut Vec<&'a str>, found: &mut Vec<&'a Term>) {
+        match term {
+            Term::Abstraction(param, _, body, _) => {
+                binders.push(param);
+                walk(body, env, binders, found);
+                binders.pop();
+            }
+            Term::Application(_, _, _) => {
+                // Report the whole application `f x y`, not also `f x`
+                let mut head = term;
+                while let Term::Application(f, x, _) = head {
+                    walk(x, env, binders, found);
+                    head = f;
+                }
+                match head {
+                    Term::Variable(name, _, _)
+                        if !binders.contains(&name.as_str()) && !env.contains_key(name) =>
+                    {
+                        found.push(term)
+                    }
+                    _ => walk(head, env, binders, found),
+                }
+            }
+            Term::Fix(f, _) | Term::Ascription(f, _, _) => walk(f, env, binders, found),
+            Term::Variable(_, _, _) | Term::Unit(_) => {}
+        }
+    }
+
+    let mut found = Vec::new();
+    walk(term, env, &mut Vec::new(), &mut found);
+    found
+}
+
+/// Find all binders that shadow a binder of an enclosing abstraction, like the inner `x` in `λx. λx. x`
+pub fn shadowed(prog: &Program) -> Vec<(String, LineInfo)> {
+    fn walk<'a>(term: &'a Term, binders: &mut Vec<&'a str>, found: &mut Vec<(String, LineInfo)>) {
+        match term {
+            Term::Abstraction(param, _, body, info) => {
+                if binders.contains(&param.as_str()) {
+                    found.push((param.clone(), info.clone()));
+                }
+                binders.push(param);
+                walk(body, binders, found);
+                binders.pop();
+            }
+            Term::Application(f, x, _) => {
+                walk(f, binders, found);
+                walk(x, binders, found);
+            }
+            Term::Fix(f, _) | Term::Ascription(f, _, _) => walk(f, binders, found),
+            Term::Variable(_, _, _) | Term::Unit(_) => {}
+        }
+    }
+
+    let mut found = Vec::new();
+    for expr in prog {
+        if let Expr::Assignment(_, _, term, _) | Expr::Term(term) = expr {
+            walk(term, &mut Vec::new(), &mut found);
+        }
+    }
+    found
+}
@@ -1,44 +1,247 @@
-mod eval;
-mod parser;
-mod print;
 mod test;
-mod types;
 
-use eval::{eval_prog, Env, PrinterFn};
-use parser::Term;
+use emit::ErrorFormat;
+use eval::{
+    beta_eta_equal, bound_vars, check_bound, eval_prog, free_vars, inline_vars, load_files,
+    reduce_with_trace, step, type_of, Docs, Env, FreshNames, Printer, Strategy,
+};
+use lamda_calc::{debruijn, emit, eval, lint, nbe, parser, print, types};
+use parser::{
+    parse_prog_recover, parse_status, Expr, ParseError, ParseStatus, Program, Term, Type,
+};
+use print::Palette;
+use rustyline::{error::ReadlineError, DefaultEditor};
+use std::{
+    collections::{HashMap, VecDeque},
+    io::Read,
+    path::PathBuf,
+    rc::Rc,
+};
+use types::Ctx;
 
-pub const PRINT_NONE: PrinterFn = |_| {};
-pub const PRINT_OUT: PrinterFn = |t| println!("{}", t);
-pub const PRINT_DBG: PrinterFn = |t| {
-    println!("{}", t);
-    print::pause("Paused: Enter to step");
+pub const PRINT_NONE: Printer = Printer::NONE;
+pub const PRINT_OUT: Printer = Printer {
+    out: |t, _| println!("{}", t),
+    palette: &print::COLORED,
+};
+pub const PRINT_DBG: Printer = Printer {
+    out: |t, p| {
+        println!("{}", t);
+        print::pause("Paused: Enter to step", p);
+    },
+    palette: &print::COLORED,
 };
 
 fn main() {
     let mut env = Env::new();
+    let mut ctx = Ctx::new();
     // If one argument is given, read that file, otherwise run REPL
     let mut args: Vec<String> = std::env::args().collect();
-    // Remove --verbose flag if present
+    // Remove --emit <format> if present
+    let emit_format = match args.iter().position(|x| x == "--emit") {
+        Some(i) if i + 1 < args.len() => {
+            args.remove(i);
+            Some(args.remove(i))
+        }
+        Some(_) => {
+            eprintln!("Usage: lambda --emit <format> [file]");
+            return;
+        }
+        None => None,
+    };
+    // Remove --max-depth <depth> if present
+    if let Some(i) = args.iter().position(|x| x == "--max-depth") {
+        match args.get(i + 1).and_then(|depth| depth.parse().ok()) {
+            Some(depth) => eval::set_max_depth(depth),
+            None => {
+                eprintln!("Usage: lambda --max-depth <depth> [file]");
+                return;
+            }
+        }
+        args.drain(i..i + 2);
+    }
+    // Remove --width <columns> if present
+    if let Some(i) = args.iter().position(|x| x == "--width") {
+        match args.get(i + 1).and_then(|width| width.parse().ok()) {
+            Some(width) => print::set_width(width),
+            None => {
+                eprintln!("Usage: lambda --width <columns> [file]");
+                return;
+            }
+        }
+        args.drain(i..i + 2);
+    }
+    // Remove --print-depth <depth> and --print-max-nodes <nodes> if present
+    for (flag, set) in [
+        ("--print-depth", print::set_print_depth as fn(usize)),
+        ("--print-max-nodes", print::set_print_max_nodes),
+    ] {
+        if let Some(i) = args.iter().position(|x| x == flag) {
+            match args.get(i + 1).and_then(|limit| limit.parse().ok()) {
+                Some(limit) => set(limit),
+                None => {
+                    eprintln!("Usage: lambda {} <n> [file]", flag);
+                    return;
+                }
+            }
+            args.drain(i..i + 2);
+        }
+    }
+    // Remove --std <path> if present, it loads that file instead of the bundled standard library
+    let std_path = match args.iter().position(|x| x == "--std") {
+        Some(i) if i + 1 < args.len() => {
+            args.remove(i);
+            Some(args.remove(i))
+        }
+        Some(_) => {
+            eprintln!("Usage: lambda --std <path> [file]");
+            return;
+        }
+        None => None,
+    };
+    // Remove --prompt <str> if present
+    let prompt = match args.iter().position(|x| x == "--prompt") {
+        Some(i) if i + 1 < args.len() => {
+            args.remove(i);
+            Some(args.remove(i))
+        }
+        Some(_) => {
+            eprintln!("Usage: lambda --prompt <str>");
+            return;
+        }
+        None => None,
+    };
+    // Remove --only <n> if present
+    if let Some(i) = args.iter().position(|x| x == "--only") {
+        match args.get(i + 1).and_then(|n| n.parse().ok()) {
+            Some(n @ 1..) => eval::set_only(n),
+            _ => {
+                eprintln!("Usage: lambda --only <n> [file]");
+                return;
+            }
+        }
+        args.drain(i..i + 2);
+    }
+    // Remove --fresh-names <scheme> if present
+    if let Some(i) = args.iter().position(|x| x == "--fresh-names") {
+        match args.get(i + 1).and_then(|name| FreshNames::from_name(name)) {
+            Some(scheme) => eval::set_fresh_names(scheme),
+            None => {
+                eprintln!("Usage: lambda --fresh-names <primes|numbered> [file]");
+                return;
+            }
+        }
+        args.drain(i..i + 2);
+    }
+    // Remove --error-format <format> if present
+    if let Some(i) = args.iter().position(|x| x == "--error-format") {
+        match args
+            .get(i + 1)
+            .and_then(|name| ErrorFormat::from_name(name))
+        {
+            Some(ErrorFormat::Json) if !cfg!(feature = "json") => {
+                eprintln!("JSON errors require building with `--features json`");
+                return;
+            }
+            Some(format) => emit::set_error_format(format),
+            None => {
+                eprintln!("Usage: lambda --error-format <human|json> [file]");
+                return;
+            }
+        }
+        args.drain(i..i + 2);
+    }
+    // Remove --verbose, --whnf, --lazy, --no-color, --use-std, --parse-only, --time and --dump-env flags if present
     let mut verbose = false;
+    let mut palette = Palette::from_env();
+    let mut parse_only = false;
+    let mut time = false;
+    let mut use_std = std_path.is_some();
+    eval::set_std_path(std_path);
+    let mut dump_env = false;
+    let mut strategy = Strategy::Normal;
     args.retain(|x| {
         match x.as_str() {
             "--help" | "-h" => help(),
             "--verbose" | "-v" => verbose = true,
+            "--whnf" => strategy = Strategy::Whnf,
+            "--lazy" => strategy = Strategy::Lazy,
+            "--no-color" => palette = &print::PLAIN,
+            "--use-std" => use_std = true,
+            "--time" => time = true,
+            "--parse-only" => parse_only = true,
+            "--native-numerals" => eval::set_native_numerals(true),
+            "--warn-shadow" => lint::set_warn_shadow(true),
+            "--warn-stuck" => lint::set_warn_stuck(true),
+            "--lint-linear" => lint::set_lint_linear(true),
+            "--strict" => eval::set_strict(true),
+            "--trace-types" => types::set_trace(true),
+            "--no-inline" => eval::set_inline(false),
+            "--debruijn" => debruijn::set_enabled(true),
+            "--nbe" => nbe::set_enabled(true),
+            "--right-assoc" => parser::set_associativity(parser::Associativity::Right),
+            "--show-types" => print::set_type_mode(print::TypeMode::ShowTypes),
+            "--hide-types" => print::set_type_mode(print::TypeMode::HideTypes),
+            "--canonical" => print::set_canonical(true),
+            "--flat-binders" => print::set_binder_mode(print::BinderMode::Flat),
+            "--dump-env" => dump_env = true,
+            "--results" => eval::set_results(true),
+            "--quiet" | "-q" => print::set_quiet(true),
             _ => return true,
         }
         false
     });
-    if args.contains(&"--expr".into()) || args.contains(&"-e".into()) {
-        expr(&args, verbose);
+    let out = Printer {
+        palette,
+        ..PRINT_OUT
+    };
+    if use_std {
+        if let Err(err) = eval::load_std(&mut env) {
+            fail(&err);
+        }
+    }
+    if parse_only {
+        let input = if args.contains(&"--expr".into()) || args.contains(&"-e".into()) {
+            args[2..].join(" ")
+        } else if args.len() == 2 {
+            read_source(&args[1]).unwrap_or_else(|err| fail(&err))
+        } else {
+            eprintln!("Usage: lambda --parse-only [file]");
+            return;
+        };
+        match parse(&input, palette) {
+            Ok(output) => println!("{}", output),
+            Err(errors) => {
+                for err in errors {
+                    eprintln!("{}", emit::parse_error(&err));
+                }
+                std::process::exit(1);
+            }
+        }
+    } else if let Some(format) = emit_format {
+        if args.contains(&"--expr".into()) || args.contains(&"-e".into()) {
+            emit(&format, &args[2..].join(" "), palette);
+        } else if args.len() == 2 {
+            let input = read_source(&args[1]).unwrap_or_else(|err| fail(&err));
+            emit(&format, &input, palette);
+        } else {
+            eprintln!("Usage: lambda --emit <format> [file]");
+        }
+    } else if args.contains(&"--expr".into()) || args.contains(&"-e".into()) {
+        expr(&args, &mut env, &mut ctx, verbose, out, strategy, time);
     } else if args.len() == 2 {
-        eval_prog(
-            std::fs::read_to_string(&args[1]).unwrap(),
-            &mut env,
-            verbose,
-            PRINT_OUT,
-        );
+        let input = read_source(&args[1]).unwrap_or_else(|err| fail(&err));
+        report(eval_prog(
+            input, &mut env, &mut ctx, verbose, out, strategy, time,
+        ));
+        if dump_env && !env.is_empty() {
+            println!("{}", print::env(&env, palette));
+        }
     } else {
-        repl(&mut env, verbose)
+        let prompts = prompts(prompt.as_deref(), print::quiet());
+        repl(
+            &mut env, &mut ctx, verbose, palette, strategy, time, &prompts,
+        )
     }
 }
 
@@ -49,32 +252,341 @@ fn help() -> ! {
     println!("Options:");
     println!("  -h, --help     Print this help message");
     println!("  -v, --verbose  Print debug information");
-    println!("  [file]         File to read lambda calculus program from");
+    println!("  -q, --quiet    Only print results, warnings and errors, without a REPL prompt");
+    println!("  --whnf         Only reduce terms to weak head normal form");
+    println!("  --lazy         Reduce to weak head normal form by call-by-need");
+    println!("  --no-color     Disable colored output (also respects NO_COLOR)");
+    println!("  --use-std      Load the standard library before evaluating");
+    println!("  --std <path>   Load this file as the standard library instead of the bundled one");
+    println!("  --time         Print how long each reduction takes");
+    println!("  --results      Print the result of every term, not only the last one");
+    println!("  --parse-only   Print the parsed program without evaluating it");
+    println!("  --native-numerals Compute Succ, Add, Mul, IsZero, Leq and Eq of Church numerals natively");
+    println!("  --warn-shadow  Warn about binders shadowing an enclosing binder");
+    println!("  --warn-stuck   Warn about results applying a variable that is not defined");
+    println!("  --lint-linear  Warn about bound variables that are not used exactly once");
+    println!("  --strict       Report variables missing from the environment as errors");
+    println!("  --trace-types  Print what the type checker does while checking terms");
+    println!("  --no-inline    Leave variables defined in the environment unfolded");
+    println!("  --debruijn     Reduce with de Bruijn indices instead of named substitution");
+    println!("  --nbe          Normalize by evaluation instead of step by step reduction");
+    println!("  --right-assoc  Parse `f x y` as `f (x y)` instead of `(f x) y`");
+    println!("  --show-types   Print type annotations of terms (default)");
+    println!("  --hide-types   Print terms without their type annotations");
+    println!("  --canonical    Print bound variables renamed to a, b, c, ... from left to right");
+    println!("  --flat-binders Print nested abstractions like λx y. x instead of λx. λy. x");
+    println!("  --width <n>    Wrap printed terms wider than n columns");
+    println!("  --print-depth <n> Print subterms nested deeper than n as …");
+    println!("  --print-max-nodes <n> Print at most n subterms of a term, the rest as …");
+    println!("  --only <n>     Only evaluate the nth term, after the definitions before it");
+    println!("  --prompt <str> Prompt of the REPL (default \"> \")");
+    println!("  --dump-env     Print the environment after running a file, like :env");
+    println!(
+        "  --fresh-names <scheme> Rename captured binders as x', x'' (primes) or x0, x1 (numbered)"
+    );
+    println!(
+        "  --emit <fmt>   Print the parsed program as json, dot or lc instead of evaluating it"
+    );
+    println!("  --error-format <fmt> Report type and parse errors as human readable text or json");
+    println!(
+        "  --max-depth <n> Maximum nesting depth of terms (default {})",
+        eval::DEFAULT_MAX_DEPTH
+    );
+    println!(
+        "  [file]         File to read lambda calculus program from, - or --stdin reads stdin"
+    );
     println!();
     println!("If no file is given, the program will run in REPL mode");
     std::process::exit(0);
 }
 
-fn expr(args: &[String], verbose: bool) {
+/// Evaluate the program given after `--expr`, assignments stay in scope for the following expressions
+fn expr(
+    args: &[String],
+    env: &mut Env,
+    ctx: &mut Ctx,
+    verbose: bool,
+    printer: Printer,
+    strategy: Strategy,
+    time: bool,
+) {
     if args.len() < 3 {
         eprintln!("Usage: lambda --expr <expression>");
         return;
     }
     let expr = args[2..].join(" ");
-    let mut env = Env::new();
-    eval_prog(expr, &mut env, verbose, PRINT_OUT);
+    report(eval_prog(expr, env, ctx, verbose, printer, strategy, time));
+}
+
+/// Parse a program from the command line or the REPL, printing its parse errors
+fn parse_input(input: &str) -> Program {
+    let (prog, errors) = parse_prog_recover(input);
+    if errors.is_empty() {
+        return prog;
+    }
+    for err in &errors {
+        eprintln!("{}", emit::parse_error(err));
+    }
+    Program::new()
+}
+
+/// Print the error a program stopped with, if any
+fn report(result: Result<(), String>) {
+    if let Err(err) = result {
+        eprintln!("{}", err);
+    }
+}
+
+/// Print the parsed program in the given format instead of evaluating it
+fn emit(format: &str, input: &str, palette: &Palette) {
+    let prog = parse_input(input.replace("\r", "").trim());
+    let output = match format {
+        "json" => emit::to_json(&prog),
+        "lc" => Ok(print::program(&prog, palette).trim_end().to_string()),
+        "dot" => Ok(prog
+            .iter()
+            .filter_map(|expr| match expr {
+                Expr::Assignment(_, _, term, _) | Expr::Term(term) => Some(emit::to_dot(term)),
+                Expr::TypeDef(_, _) | Expr::Import(_, _) | Expr::Pragma(_, _) => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n")),
+        _ => Err(format!(
+            "Unknown emit format: {}, expected json, dot or lc",
+            format
+        )),
+    };
+    match output {
+        Ok(output) => println!("{}", output),
+        Err(err) => eprintln!("{}", err),
+    }
+}
+
+/// Parse the program without evaluating it, printing it back or returning all parse errors
+fn parse(input: &str, palette: &Palette) -> Result<String, Vec<ParseError>> {
+    let (prog, errors) = parse_prog_recover(input.replace("\r", "").trim());
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(print::program(&prog, palette).trim_end().to_string())
+}
+
+/// Record a non-empty line in the REPL history and append it to the history file
+fn add_history(editor: &mut DefaultEditor, history: &Option<PathBuf>, line: &str) {
+    if line.trim().is_empty() {
+        return;
+    }
+    let _ = editor.add_history_entry(line);
+    if let Some(path) = history {
+        let _ = editor.append_history(path);
+    }
+}
+
+/// Read the program given on the command line, or a message naming the file if it can't be read
+fn read_source(path: &str) -> Result<String, String> {
+    source(path, std::io::stdin()).map_err(|err| format!("Error reading file {}: {}", path, err))
+}
+
+/// Print an error and exit with a non-zero status
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1)
+}
+
+/// Read a program from a file, or all of `stdin` if the path is `-` or `--stdin`
+fn source(path: &str, mut stdin: impl Read) -> std::io::Result<String> {
+    match path {
+        "-" | "--stdin" => {
+            let mut input = String::new();
+            stdin.read_to_string(&mut input)?;
+            Ok(input)
+        }
+        _ => std::fs::read_to_string(path),
+    }
+}
+
+/// Split a line of REPL input into a command and its arguments, or `None` if the line is blank
+fn repl_args(input: &str) -> Option<Vec<&str>> {
+    let args: Vec<&str> = input.split_whitespace().collect();
+    (!args.is_empty()).then_some(args)
+}
+
+/// File the REPL history is persisted to, `~/.tlc_history`
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".tlc_history"))
+}
+
+/// Most inputs that can be undone with `:undo`
+const MAX_UNDO: usize = 100;
+
+/// Bindings changed by a REPL input, with what they were bound to before or `None` if they were unbound
+#[derive(Debug, Default)]
+struct Change {
+    env: Vec<(String, Option<Term>)>,
+    ctx: Vec<(String, Option<Rc<Type>>)>,
+}
+
+impl Change {
+    /// The bindings that differ between `before` and `after`, with their values in `before`
+    fn diff<V: Clone + PartialEq>(
+        before: &HashMap<String, V>,
+        after: &HashMap<String, V>,
+    ) -> Vec<(String, Option<V>)> {
+        let changed = before
+            .iter()
+            .filter(|(name, value)| after.get(*name) != Some(*value))
+            .map(|(name, value)| (name.clone(), Some(value.clone())));
+        let added = after
+            .keys()
+            .filter(|name| !before.contains_key(*name))
+            .map(|name| (name.clone(), None));
+        changed.chain(added).collect()
+    }
+
+    /// Bind the names to their recorded values, returning the change that reverts it
+    fn apply(self, env: &mut Env, ctx: &mut Ctx) -> Change {
+        fn restore<V>(
+            map: &mut HashMap<String, V>,
+            bindings: Vec<(String, Option<V>)>,
+        ) -> Vec<(String, Option<V>)> {
+            bindings
+                .into_iter()
+                .map(|(name, value)| {
+                    let current = match value {
+                        Some(value) => map.insert(name.clone(), value),
+                        None => map.remove(&name),
+                    };
+                    (name, current)
+                })
+                .collect()
+        }
+        Change {
+            env: restore(env, self.env),
+            ctx: restore(ctx, self.ctx),
+        }
+    }
+}
+
+/// Changes to the environment and type context made by REPL inputs, for `:undo` and `:redo`.
+/// Only the changed bindings of each input are kept, and at most `MAX_UNDO` inputs.
+#[derive(Debug, Default)]
+struct UndoHistory {
+    undo: VecDeque<Change>,
+    redo: Vec<Change>,
+}
+
+impl UndoHistory {
+    /// Record the bindings an input changed, given the environment and type context from before it
+    fn record(&mut self, env_before: &Env, env: &Env, ctx_before: &Ctx, ctx: &Ctx) {
+        let change = Change {
+            env: Change::diff(env_before, env),
+            ctx: Change::diff(ctx_before, ctx),
+        };
+        if change.env.is_empty() && change.ctx.is_empty() {
+            return;
+        }
+        if self.undo.len() == MAX_UNDO {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(change);
+        self.redo.clear();
+    }
+
+    /// Revert the last recorded input, returns `false` if there is nothing to undo
+    fn undo(&mut self, env: &mut Env, ctx: &mut Ctx) -> bool {
+        let Some(change) = self.undo.pop_back() else {
+            return false;
+        };
+        self.redo.push(change.apply(env, ctx));
+        true
+    }
+
+    /// Apply the last undone input again, returns `false` if there is nothing to redo
+    fn redo(&mut self, env: &mut Env, ctx: &mut Ctx) -> bool {
+        let Some(change) = self.redo.pop() else {
+            return false;
+        };
+        self.undo.push_back(change.apply(env, ctx));
+        true
+    }
+}
+
+/// The REPL prompt and the prompt for continued input, both empty when output is quiet
+fn prompts(prompt: Option<&str>, quiet: bool) -> (String, String) {
+    if quiet {
+        return (String::new(), String::new());
+    }
+    (prompt.unwrap_or("> ").to_string(), "... ".to_string())
 }
 
-fn repl(env: &mut Env, verbose: bool) {
-    use std::io::Write;
+fn repl(
+    env: &mut Env,
+    ctx: &mut Ctx,
+    verbose: bool,
+    palette: &'static Palette,
+    strategy: Strategy,
+    time: bool,
+    (prompt, continued): &(String, String),
+) {
+    let out = Printer {
+        palette,
+        ..PRINT_OUT
+    };
+    let dbg = Printer {
+        palette,
+        ..PRINT_DBG
+    };
+    let mut editor = DefaultEditor::new()
+        .unwrap_or_else(|err| fail(&format!("Error starting the REPL: {}", err)));
+    let history = history_path();
+    if let Some(path) = &history {
+        // The history file does not exist on the first run
+        let _ = editor.load_history(path);
+    }
+    // The term currently being reduced with :step
+    let mut stepping: Option<Term> = None;
+    let mut undo = UndoHistory::default();
+    // Doc comments of the definitions entered or loaded so far
+    let mut docs = Docs::new();
+    // The environment and type context before the last input, to record what it changed
+    let mut before: Option<(Env, Ctx)> = None;
     loop {
-        print!("> ");
-        std::io::stdout().flush().unwrap();
-        let mut input = String::new();
-        std::io::stdin().read_line(&mut input).unwrap();
-        let args: Vec<&str> = input.trim().split(' ').collect::<Vec<&str>>();
-        match *args.first().unwrap_or(&"") {
+        let mut input = match editor.readline(prompt) {
+            Ok(input) => input,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                eprintln!("Error reading input: {}", err);
+                break;
+            }
+        };
+        if let Some((env_before, ctx_before)) = before.take() {
+            undo.record(&env_before, env, &ctx_before, ctx);
+        }
+        add_history(&mut editor, &history, &input);
+        // Blank lines are skipped without parsing anything
+        let Some(args) = repl_args(&input) else {
+            continue;
+        };
+        if !matches!(args[0], ":undo" | ":redo") {
+            before = Some((env.clone(), ctx.clone()));
+        }
+        match args[0] {
             ":q" | ":quit" => break,
+            ":undo" => {
+                if !undo.undo(env, ctx) {
+                    eprintln!("Nothing to undo");
+                }
+                continue;
+            }
+            ":redo" => {
+                if !undo.redo(env, ctx) {
+                    eprintln!("Nothing to redo");
+                }
+                continue;
+            }
             ":cls" | ":clear" => {
                 print!("{esc}[2J{esc}[1;1H", esc = 27 as char);
                 continue;
@@ -82,33 +594,246 @@ fn repl(env: &mut Env, verbose: bool) {
             ":env" => {
                 if args.len() == 2 && args[1] == "clear" {
                     env.clear();
+                } else if !env.is_empty() {
+                    println!("{}", print::env(env, palette));
+                }
+                continue;
+            }
+            ":ctx" => {
+                if args.len() == 2 && args[1] == "clear" {
+                    ctx.clear();
                 } else {
-                    for (name, term) in env.iter() {
-                        println!("{} = {}", name, print::term(term));
+                    println!("{}", print::ctx(ctx, palette));
+                }
+                continue;
+            }
+            ":type" => {
+                let prog = parse_input(&args[1..].join(" "));
+                let Some(expr) = prog.last() else {
+                    eprintln!("Usage: :type <expr>");
+                    continue;
+                };
+                match type_of(expr, env, ctx) {
+                    Ok(ty) => println!("{}", print::r#type(&ty, palette)),
+                    Err(err) => println!("{}", emit::type_error(err, Some(ctx), palette)),
+                }
+                continue;
+            }
+            ":ast" => {
+                if args.len() < 2 {
+                    eprintln!("Usage: :ast <expr>");
+                    continue;
+                }
+                match parser::parse_term_str(&args[1..].join(" ")) {
+                    Ok(term) => println!("{}", print::ast_tree(&term, palette)),
+                    Err(err) => eprintln!("{}", emit::parse_error(&err)),
+                }
+                continue;
+            }
+            ":search" => {
+                let query = match parser::parse_type_str(&args[1..].join(" ")) {
+                    Ok(query) if args.len() > 1 => query,
+                    _ => {
+                        eprintln!("Usage: :search <type>");
+                        continue;
                     }
+                };
+                for (name, ty) in eval::search(&query, env, ctx) {
+                    println!("{} : {}", name, print::r#type(&ty, palette));
                 }
                 continue;
             }
+            ":reset" => {
+                env.clear();
+                ctx.clear();
+                docs.clear();
+                stepping = None;
+                println!("Environment and type context reset");
+                continue;
+            }
             ":std" => {
-                eval_prog(include_str!("./std.lc").into(), env, verbose, PRINT_OUT);
+                // The given file, or the standard library selected with --std
+                let path = args
+                    .get(1)
+                    .map(|path| path.to_string())
+                    .or_else(eval::std_path);
+                match eval::std_source(path.as_deref()) {
+                    Ok(source) => report(eval_prog(source, env, ctx, verbose, out, strategy, time)),
+                    Err(err) => eprintln!("{}", err),
+                }
                 continue;
             }
             ":load" => {
+                if args.len() < 2 {
+                    eprintln!("Usage: :load <files>");
+                    continue;
+                }
+                let files = &args[1..];
+                match load_files(files, env, ctx, verbose, out, strategy, time) {
+                    Ok(loaded) => docs.extend(loaded),
+                    Err(err) => eprintln!("{}", err),
+                }
+                continue;
+            }
+            ":save" => {
                 let Some(file) = args.get(1) else {
-                    eprintln!("Usage: :load <file>");
+                    eprintln!("Usage: :save <file>");
                     continue;
                 };
-                if let std::io::Result::Ok(content) = std::fs::read_to_string(file) {
-                    eval_prog(content, env, verbose, PRINT_OUT);
-                } else {
-                    eprintln!("Error reading file");
+                if let Err(err) = std::fs::write(file, print::env_program(env)) {
+                    eprintln!("Error writing file {}: {}", file, err);
+                }
+                continue;
+            }
+            ":session" => {
+                match (args.get(1).copied(), args.get(2)) {
+                    (Some("save"), Some(file)) => {
+                        if let Err(err) = emit::save_session(file, env, ctx) {
+                            eprintln!("{}", err);
+                        }
+                    }
+                    (Some("load"), Some(file)) => match emit::load_session(file) {
+                        Ok(session) => {
+                            *env = session.env;
+                            *ctx = session.ctx;
+                        }
+                        Err(err) => eprintln!("{}", err),
+                    },
+                    _ => eprintln!("Usage: :session save|load <file>"),
                 }
                 continue;
             }
             ":dbg" => {
                 // Step through the program evaluation
                 let input = args[1..].join(" ");
-                eval_prog(input, env, verbose, PRINT_DBG);
+                report(eval_prog(input, env, ctx, verbose, dbg, strategy, time));
+                continue;
+            }
+            ":whnf" => {
+                let input = args[1..].join(" ");
+                report(eval_prog(
+                    input,
+                    env,
+                    ctx,
+                    verbose,
+                    out,
+                    Strategy::Whnf,
+                    time,
+                ));
+                continue;
+            }
+            ":time" => {
+                let input = args[1..].join(" ");
+                report(eval_prog(input, env, ctx, verbose, out, strategy, true));
+                continue;
+            }
+            ":trace-types" => {
+                match args.get(1).copied() {
+                    Some("on") => types::set_trace(true),
+                    Some("off") => types::set_trace(false),
+                    _ => eprintln!("Usage: :trace-types on|off"),
+                }
+                continue;
+            }
+            ":trace" => {
+                let prog = parse_input(&args[1..].join(" "));
+                let Some(Expr::Term(term)) = prog.last() else {
+                    eprintln!("Usage: :trace <expr>");
+                    continue;
+                };
+                if eval::strict() {
+                    if let Err(err) = check_bound(term, env) {
+                        eprintln!("{}", err);
+                        continue;
+                    }
+                }
+                match reduce_with_trace(&inline_vars(term, env), env, strategy, None) {
+                    Ok((_, trace)) => {
+                        for (step, term, location, _) in &trace.steps {
+                            println!("{}", print::trace_step(*step, term, location, palette));
+                        }
+                    }
+                    Err(err) => eprintln!("{}", err),
+                }
+                continue;
+            }
+            ":step" => {
+                if args.len() > 1 {
+                    let prog = parse_input(&args[1..].join(" "));
+                    let Some(Expr::Term(term)) = prog.last() else {
+                        eprintln!("Usage: :step <expr>");
+                        continue;
+                    };
+                    if eval::strict() {
+                        if let Err(err) = check_bound(term, env) {
+                            eprintln!("{}", err);
+                            continue;
+                        }
+                    }
+                    stepping = Some(inline_vars(term, env));
+                }
+                let Some(term) = &stepping else {
+                    eprintln!("Nothing to step, start with :step <expr>");
+                    continue;
+                };
+                match step(term, env) {
+                    Some(next) => {
+                        println!("{}", print::term(&next, palette));
+                        stepping = Some(next);
+                    }
+                    None => {
+                        println!("Normal form reached: {}", print::term(term, palette));
+                        stepping = None;
+                    }
+                }
+                continue;
+            }
+            ":free" | ":bound" => {
+                let prog = parse_input(&args[1..].join(" "));
+                let Some(Expr::Term(term)) = prog.last() else {
+                    eprintln!("Usage: {} <expr>", args[0]);
+                    continue;
+                };
+                let vars = if args[0] == ":free" {
+                    free_vars(term)
+                } else {
+                    bound_vars(term)
+                };
+                println!("{}", print::var_set(&vars, palette));
+                continue;
+            }
+            ":doc" => {
+                match args.get(1).and_then(|name| docs.get(*name)) {
+                    Some(doc) => println!("{}", doc),
+                    None if args.len() == 2 => eprintln!("No documentation for `{}`", args[1]),
+                    None => eprintln!("Usage: :doc <name>"),
+                }
+                continue;
+            }
+            ":eq" => {
+                let prog = parse_input(&args[1..].join(" "));
+                let [Expr::Term(a), Expr::Term(b)] = prog.as_slice() else {
+                    eprintln!("Usage: :eq <expr>; <expr>");
+                    continue;
+                };
+                if eval::strict() {
+                    if let Err(err) = check_bound(a, env).and_then(|_| check_bound(b, env)) {
+                        eprintln!("{}", err);
+                        continue;
+                    }
+                }
+                match beta_eta_equal(a, b, env) {
+                    Ok(equal) => println!("{}", print::var(&equal.to_string(), palette)),
+                    Err(err) => eprintln!("Inconclusive, {}", err),
+                }
+                continue;
+            }
+            ":json" => {
+                emit("json", &args[1..].join(" "), palette);
+                continue;
+            }
+            ":dot" => {
+                emit("dot", &args[1..].join(" "), palette);
                 continue;
             }
             ":help" => {
@@ -117,9 +842,36 @@ fn repl(env: &mut Env, verbose: bool) {
                 println!("  :cls, :clear   Clear the screen");
                 println!("  :env           Print the current environment");
                 println!("  :env clear     Clear the current environment");
-                println!("  :load <file>   Load a file into the environment");
-                println!("  :std           Load the standard library");
+                println!("  :ctx           Print the current type context");
+                println!("  :ctx clear     Clear the current type context");
+                println!("  :type <expr>   Print the type of an expression");
+                println!("  :search <type> List the definitions with a compatible type");
+                println!("  :ast <expr>    Print the syntax tree of an expression");
+                println!(
+                    "  :reset         Clear the environment, type context and any stepping state"
+                );
+                println!("  :load <files>  Load files into the environment in order");
+                println!("  :save <file>   Save the environment to a file");
+                println!(
+                    "  :session save|load <file> Save or restore the environment and types as JSON"
+                );
+                println!("  :doc <name>    Print the doc comment of a definition");
+                println!(
+                    "  :undo          Revert the changes of the last input to the environment"
+                );
+                println!("  :redo          Apply the last undone changes again");
+                println!("  :std [path]    Load the standard library, or the file at path instead");
                 println!("  :dbg <prog>    Step through the evaluation");
+                println!("  :step <expr>   Reduce one step, repeat :step to continue");
+                println!("  :trace <expr>  Print every reduction step and where its redex was");
+                println!("  :trace-types on|off Print what the type checker does while checking");
+                println!("  :whnf <prog>   Reduce to weak head normal form");
+                println!("  :eq <e1>; <e2> Check if two terms are βη-equal");
+                println!("  :free <expr>   Print the free variables of a term");
+                println!("  :bound <expr>  Print the variables bound in a term");
+                println!("  :time <prog>   Print how long the reduction takes");
+                println!("  :json <prog>   Print the parsed program as JSON");
+                println!("  :dot <prog>    Print the syntax tree as a Graphviz DOT graph");
                 println!("  :help          Print this help message");
                 continue;
             }
@@ -129,6 +881,24 @@ fn repl(env: &mut Env, verbose: bool) {
             }
             _ => {}
         }
-        eval_prog(input, env, verbose, PRINT_OUT);
+        // Keep reading lines until the input is no longer cut off
+        while parse_status(&input) == ParseStatus::Incomplete {
+            match editor.readline(continued) {
+                Ok(line) => {
+                    add_history(&mut editor, &history, &line);
+                    // Indent the continued line, a line break at the start of a line would end the statement
+                    input.push_str("\n ");
+                    input.push_str(&line);
+                }
+                // Discard the unfinished input
+                Err(ReadlineError::Interrupted) => input.clear(),
+                Err(_) => break,
+            }
+        }
+        if input.trim().is_empty() {
+            continue;
+        }
+        docs.extend(eval::docs(&parse_prog_recover(input.trim()).0));
+        report(eval_prog(input, env, ctx, verbose, out, strategy, time));
     }
 }
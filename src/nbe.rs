@@ -0,0 +1,271 @@
+//! Normalization by evaluation, terms are evaluated into values where abstractions are closures over the values of their variables.
+//! Reading a value back as a term applies it to fresh variables, so the result is in β-normal form without any substitution.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    eval::{free_vars, Env, EvalError, Options, Stopwatch, Timing},
+    parser::{LineInfo, Term, Type},
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Reduce terms to normal form by evaluation instead of repeated substitution
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Check if normalization by evaluation is enabled
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+#[derive(Clone)]
+enum Value {
+    Abstraction(Rc<Closure>),
+    /// A variable or `()` applied to arguments that are not evaluated yet
+    Neutral(Rc<Head>, Vec<Thunk>),
+    Unit,
+}
+
+/// An abstraction with the values of the variables bound around it
+struct Closure {
+    param: String,
+    ty: Option<Type>,
+    body: Term,
+    scope: Scope,
+}
+
+enum Head {
+    Variable(String),
+    Unit,
+}
+
+/// Variables bound by the abstractions a term is under, innermost first
+type Scope = Option<Rc<Binding>>;
+
+struct Binding {
+    name: String,
+    thunk: Thunk,
+    next: Scope,
+}
+
+/// An argument shared by all uses of its variable, it is evaluated the first time it is needed
+type Thunk = Rc<RefCell<State>>;
+
+enum State {
+    Delayed(Term, Scope),
+    Forced(Value),
+}
+
+fn lookup(scope: &Scope, name: &str) -> Option<Thunk> {
+    let mut scope = scope;
+    while let Some(binding) = scope {
+        if binding.name == name {
+            return Some(binding.thunk.clone());
+        }
+        scope = &binding.next;
+    }
+    None
+}
+
+fn bind(scope: Scope, name: String, thunk: Thunk) -> Scope {
+    Some(Rc::new(Binding {
+        name,
+        thunk,
+        next: scope,
+    }))
+}
+
+fn delay(term: Term, scope: Scope) -> Thunk {
+    Rc::new(RefCell::new(State::Delayed(term, scope)))
+}
+
+fn forced(value: Value) -> Thunk {
+    Rc::new(RefCell::new(State::Forced(value)))
+}
+
+struct Normalizer<'a> {
+    env: &'a Env,
+    /// Definitions of the environment are evaluated once per normalization
+    globals: HashMap<String, Thunk>,
+    /// Free variables of the term and the environment, binders read back must not capture them
+    free: HashSet<String>,
+    /// How binders read back are renamed, and how deep evaluation may nest
+    options: Options,
+    steps: usize,
+    max_steps: Option<usize>,
+}
+
+impl Normalizer<'_> {
+    /// Count a β-reduction
+    fn tick(&mut self) -> Result<(), EvalError> {
+        if let Some(max_steps) = self.max_steps.filter(|max| self.steps == *max) {
+            return Err(EvalError::StepLimit(max_steps));
+        }
+        self.steps += 1;
+        Ok(())
+    }
+
+    fn global(&mut self, name: &str) -> Option<Thunk> {
+        let term = self.env.get(name)?;
+        let thunk = self
+            .globals
+            .entry(name.to_string())
+            .or_insert_with(|| delay(term.clone(), None));
+        Some(thunk.clone())
+    }
+
+    /// Evaluate a thunk, updating it so later uses get the value directly
+    fn force(&mut self, thunk: &Thunk, depth: usize) -> Result<Value, EvalError> {
+        let delayed = match &*thunk.borrow() {
+            State::Forced(value) => return Ok(value.clone()),
+            State::Delayed(term, scope) => (term.clone(), scope.clone()),
+        };
+        let value = self.eval(&delayed.0, &delayed.1, depth + 1)?;
+        *thunk.borrow_mut() = State::Forced(value.clone());
+        Ok(value)
+    }
+
+    fn eval(&mut self, term: &Term, scope: &Scope, depth: usize) -> Result<Value, EvalError> {
+        // Evaluation is recursive, so nesting is bounded like the depth of terms
+        if depth > self.options.max_depth {
+            return Err(EvalError::DepthExceeded(self.options.max_depth));
+        }
+        match term {
+            Term::Variable(name, _, _) => match lookup(scope, name).or_else(|| self.global(name)) {
+                Some(thunk) => self.force(&thunk, depth),
+                None => Ok(Value::Neutral(
+                    Rc::new(Head::Variable(name.clone())),
+                    Vec::new(),
+                )),
+            },
+            Term::Abstraction(param, ty, body, _) => Ok(Value::Abstraction(Rc::new(Closure {
+                param: param.clone(),
+                ty: ty.clone(),
+                body: body.as_ref().clone(),
+                scope: scope.clone(),
+            }))),
+            Term::Application(f, arg, _) => {
+                let f = self.eval(f, scope, depth + 1)?;
+                self.apply(f, delay(arg.as_ref().clone(), scope.clone()), depth + 1)
+            }
+            // fix f = f (fix f)
+            Term::Fix(f, _) => {
+                let f = self.eval(f, scope, depth + 1)?;
+                self.apply(f, delay(term.clone(), scope.clone()), depth + 1)
+            }
+            // Types are only used for checking
+            Term::Ascription(e, _, _) => self.eval(e, scope, depth + 1),
+            Term::Unit(_) => Ok(Value::Unit),
+        }
+    }
+
+    fn apply(&mut self, f: Value, arg: Thunk, depth: usize) -> Result<Value, EvalError> {
+        match f {
+            Value::Abstraction(closure) => {
+                self.tick()?;
+                let scope = bind(closure.scope.clone(), closure.param.clone(), arg);
+                self.eval(&closure.body, &scope, depth + 1)
+            }
+            Value::Neutral(head, mut args) => {
+                args.push(arg);
+                Ok(Value::Neutral(head, args))
+            }
+            Value::Unit => Ok(Value::Neutral(Rc::new(Head::Unit), vec![arg])),
+        }
+    }
+
+    /// Read a value back as a term in normal form, `bound` are the names of the binders it is under
+    fn read_back(
+        &mut self,
+        value: Value,
+        bound: &mut Vec<String>,
+        depth: usize,
+    ) -> Result<Term, EvalError> {
+        if depth > self.options.max_depth {
+            return Err(EvalError::DepthExceeded(self.options.max_depth));
+        }
+        let info = LineInfo(0, 0);
+        match value {
+            Value::Abstraction(closure) => {
+                // Keep the name of the parameter unless it would capture a variable
+                let taken =
+                    |name: &str| self.free.contains(name) || bound.iter().any(|b| b == name);
+                let param = if taken(&closure.param) {
+                    self.options.fresh_names.fresh(&closure.param, taken)
+                } else {
+                    closure.param.clone()
+                };
+                // Evaluate the body with the parameter bound to itself, which is not a β-reduction
+                let var = Value::Neutral(Rc::new(Head::Variable(param.clone())), Vec::new());
+                let scope = bind(closure.scope.clone(), closure.param.clone(), forced(var));
+                let body = self.eval(&closure.body, &scope, depth + 1)?;
+                bound.push(param.clone());
+                let body = self.read_back(body, bound, depth + 1);
+                bound.pop();
+                Ok(Term::Abstraction(
+                    param,
+                    closure.ty.clone(),
+                    Box::new(body?),
+                    info,
+                ))
+            }
+            Value::Neutral(head, args) => {
+                let mut term = match head.as_ref() {
+                    Head::Variable(name) => Term::Variable(name.clone(), None, info.clone()),
+                    Head::Unit => Term::Unit(info.clone()),
+                };
+                for arg in args {
+                    let arg = self.force(&arg, depth + 1)?;
+                    let arg = self.read_back(arg, bound, depth + 1)?;
+                    term = Term::Application(Box::new(term), Box::new(arg), info.clone());
+                }
+                Ok(term)
+            }
+            Value::Unit => Ok(Term::Unit(info)),
+        }
+    }
+}
+
+/// Normalize a term by evaluation, failing after `max_steps` β-reductions if given.
+/// The options bound the nesting of evaluation and rename binders of the result.
+pub fn normalize_timed(
+    term: &Term,
+    env: &Env,
+    max_steps: Option<usize>,
+    options: &Options,
+) -> Result<(Term, Timing), EvalError> {
+    let start = Stopwatch::start();
+    let mut free = free_vars(term);
+    free.extend(env.values().flat_map(free_vars));
+    free.retain(|name| !env.contains_key(name));
+    let mut normalizer = Normalizer {
+        env,
+        globals: HashMap::new(),
+        free,
+        options: *options,
+        steps: 0,
+        max_steps,
+    };
+    let value = normalizer.eval(term, &None, 1)?;
+    let term = normalizer.read_back(value, &mut Vec::new(), 1)?;
+    let elapsed = start.elapsed();
+    Ok((
+        term,
+        Timing {
+            elapsed,
+            steps: normalizer.steps,
+        },
+    ))
+}
+
+/// Normalize a term by evaluation, it has the same normal form as `eval::reduce_to_normal_form` up to renaming
+pub fn normalize_nbe(term: &Term, env: &Env) -> Result<Term, EvalError> {
+    normalize_timed(term, env, None, &Options::current()).map(|(term, _)| term)
+}
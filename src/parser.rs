@@ -1,14 +1,25 @@
-use std::{fmt::Display, rc::Rc};
+use std::{
+    fmt::Display,
+    rc::Rc,
+    sync::atomic::{AtomicU8, Ordering},
+};
 
-use pest::{iterators::Pair, Parser};
+use pest::{
+    error::ErrorVariant,
+    iterators::{Pair, Pairs},
+    Parser, Position, Span,
+};
 use pest_derive::Parser;
 
+use crate::eval::{depth, max_depth};
+
 /// Lambda calculus parser using pest
 #[derive(Parser)]
 #[grammar = "grammar.pest"]
 pub struct LambdaCalcParser;
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
 pub struct LineInfo(pub usize, pub usize);
 
 impl From<pest::Span<'_>> for LineInfo {
@@ -20,9 +31,13 @@ impl From<pest::Span<'_>> for LineInfo {
 
 /// AST for our extended lambda calculus program
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
-    Assignment(String, Option<Type>, Term),
+    /// Definition with an optional type annotation and the `///` doc comment above it
+    Assignment(String, Option<Type>, Term, Option<String>),
     TypeDef(String, Type),
+    Import(Vec<String>, String), // Names to import from a module
+    Pragma(String, String),      // Evaluation setting given in the program, `#key value`
     Term(Term),
 }
 
@@ -33,10 +48,14 @@ pub type Program = Vec<Expr>;
 ///
 /// See https://en.wikipedia.org/wiki/Lambda_calculus#Definition.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
 pub enum Term {
     Abstraction(String, Option<Type>, Box<Term>, LineInfo),
     Application(Box<Term>, Box<Term>, LineInfo),
     Variable(String, Option<Type>, LineInfo), // Variable with optional type annotation
+    Fix(Box<Term>, LineInfo),                 // Fixpoint of a function, fix f = f (fix f)
+    Unit(LineInfo),                           // The only value of the unit type
+    Ascription(Box<Term>, Type, LineInfo),    // Term annotated with its expected type
 }
 
 impl Term {
@@ -46,10 +65,32 @@ impl Term {
             Term::Abstraction(_, _, _, info) => info,
             Term::Application(_, _, info) => info,
             Term::Variable(_, _, info) => info,
+            Term::Fix(_, info) => info,
+            Term::Unit(info) => info,
+            Term::Ascription(_, _, info) => info,
         }
     }
 }
 
+/// Shorthand constructors for building terms outside of the parser,
+/// all located at the synthetic position `LineInfo(0, 0)`
+impl Term {
+    /// An untyped variable `x`
+    pub fn var(name: &str) -> Term {
+        Term::Variable(name.to_string(), None, LineInfo(0, 0))
+    }
+
+    /// An untyped abstraction `λx. body`
+    pub fn abs(param: &str, body: Term) -> Term {
+        Term::Abstraction(param.to_string(), None, Box::new(body), LineInfo(0, 0))
+    }
+
+    /// An application `f x`
+    pub fn app(f: Term, x: Term) -> Term {
+        Term::Application(Box::new(f), Box::new(x), LineInfo(0, 0))
+    }
+}
+
 impl Display for Term {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -72,24 +113,41 @@ impl Display for Term {
                     write!(f, "{}", name)
                 }
             }
+            Term::Fix(term, _) => write!(f, "(fix {})", term),
+            Term::Unit(_) => write!(f, "()"),
+            Term::Ascription(term, ty, _) => write!(f, "({} : {})", term, ty),
         }
     }
 }
 
 /// Type system for lambda calculus
 #[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
 pub enum Type {
     #[default]
     Any, // Any type (used for untyped variables)
     Variable(String), // Type variable
     Abstraction(Rc<Type>, Rc<Type>),
+    Unit,                     // Type of `()`
+    Never,                    // Type of terms that never produce a value, checks against any type
+    Forall(String, Rc<Type>), // `forall a. T`, binds the type variable `a` in `T`
+}
+
+impl Type {
+    /// A function type `a -> b`
+    pub fn arrow(a: Type, b: Type) -> Type {
+        Type::Abstraction(Rc::new(a), Rc::new(b))
+    }
 }
 
 impl Display for Type {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Type::Any => write!(f, "*"),
+            Type::Unit => write!(f, "()"),
+            Type::Never => write!(f, "!"),
             Type::Variable(name) => write!(f, "{}", name),
+            Type::Forall(var, body) => write!(f, "(forall {}. {})", var, body),
             Type::Abstraction(param, ret) => {
                 write!(f, "({} -> {})", param, ret)
             }
@@ -97,113 +155,502 @@ impl Display for Type {
     }
 }
 
-/// Parse a top-level program into a list of terms
-pub fn parse_prog(input: &str) -> Program {
-    /// Transform a Pest pair into our own AST Expr node format
-    fn parse_term(pair: Pair<Rule>) -> Term {
-        match pair.as_rule() {
-            Rule::abstraction => {
-                let span = pair.as_span();
-                let mut inner = pair.into_inner();
-                // let param = inner.next().unwrap().as_str().to_string();
-                let (param, expected) = match inner.next().unwrap() {
-                    // Parse variable with optional type annotation
-                    pair if pair.as_rule() == Rule::variable => {
-                        let mut inner_var = pair.into_inner();
-                        let var_name = inner_var.next().unwrap().as_str().to_string();
-                        let type_annotation = inner_var.next().map(parse_type);
-                        (var_name, type_annotation)
-                    }
-                    // Parse untyped variable
-                    pair if pair.as_rule() == Rule::untyped_variable => {
-                        let var_name = pair.as_str().to_string();
-                        (var_name, None)
-                    }
-                    _ => unreachable!("Expected variable or untyped variable"),
-                };
-                let body = parse_term(inner.next().unwrap());
-                Term::Abstraction(param, expected, Box::new(body), span.into())
-            }
-            // Rule::application => {
-            //     let mut inner = pair.into_inner();
-            //     let lhs = parse_term(inner.next().unwrap());
-            //     let rhs = parse_term(inner.next().unwrap());
-            //     Term::Application(Box::new(lhs), Box::new(rhs))
-            // }
-            // rhs is one or more terms
-            Rule::application => {
-                // Syntax sugar: (e1 e2 e3 ...) -> (e1 (e2 (e3 ...)))
-                // Previous (e1 e2) was only allowed
-                let span = pair.as_span();
-                let mut inner = pair.into_inner();
-                let mut lhs = parse_term(inner.next().unwrap());
-                for rhs in inner {
-                    lhs = Term::Application(Box::new(lhs), Box::new(parse_term(rhs)), span.into());
-                }
-                lhs
+/// Whether some input can be parsed as a program
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParseStatus {
+    Complete,
+    /// The input ended before the program did, more input could make it valid
+    Incomplete,
+    Invalid,
+}
+
+/// Check if the input is a complete program, is cut off, or is invalid
+pub fn parse_status(input: &str) -> ParseStatus {
+    match parse_rule(Rule::program, input) {
+        Ok(_) => ParseStatus::Complete,
+        Err(e) => {
+            let pos = match e.location {
+                pest::error::InputLocation::Pos(pos) => pos,
+                pest::error::InputLocation::Span((start, _)) => start,
+            };
+            // The parser failed on the end of the input, so it expected more
+            if pos >= input.trim_end().len() {
+                ParseStatus::Incomplete
+            } else {
+                ParseStatus::Invalid
             }
-            Rule::variable => {
-                let span = pair.as_span();
-                let mut inner = pair.into_inner();
-                let var_name = inner.next().unwrap().as_str().to_string();
-                let type_annotation = inner.next().map(parse_type);
-                Term::Variable(var_name, type_annotation, span.into())
+        }
+    }
+}
+
+/// Error produced when the input is not a valid program
+pub type ParseError = pest::error::Error<Rule>;
+
+/// Deepest nesting of parentheses, abstractions, `fix` and function types the parser accepts.
+/// The parser is recursive, so deeper input could overflow the stack.
+pub const MAX_NESTING: usize = 250;
+
+/// How the terms of an application `e1 e2 e3` are nested
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Associativity {
+    /// `((e1 e2) e3)`
+    #[default]
+    Left,
+    /// `(e1 (e2 e3))`
+    Right,
+}
+
+static ASSOCIATIVITY: AtomicU8 = AtomicU8::new(Associativity::Left as u8);
+
+/// Set how applications are nested when parsing and printing terms
+pub fn set_associativity(assoc: Associativity) {
+    ASSOCIATIVITY.store(assoc as u8, Ordering::Relaxed);
+}
+
+/// How applications are nested, see `set_associativity`
+pub fn associativity() -> Associativity {
+    match ASSOCIATIVITY.load(Ordering::Relaxed) {
+        1 => Associativity::Right,
+        _ => Associativity::Left,
+    }
+}
+
+/// Largest numeric literal accepted by the parser, larger numerals would create enormous terms
+pub const MAX_LITERAL: usize = 1_000_000;
+
+/// Parse the input with the given rule, unless it is nested too deep to parse safely
+#[allow(clippy::result_large_err)]
+fn parse_rule(rule: Rule, input: &str) -> Result<Pairs<'_, Rule>, ParseError> {
+    if let Some(pos) = nested_too_deep(input) {
+        let message = format!("Input is nested deeper than {} levels", MAX_NESTING);
+        let pos = Position::new(input, pos).unwrap();
+        return Err(ParseError::new_from_pos(
+            ErrorVariant::CustomError { message },
+            pos,
+        ));
+    }
+    let pairs = LambdaCalcParser::parse(rule, input)?;
+    if let Some(pair) = (pairs.clone().flatten())
+        .find(|pair| pair.as_rule() == Rule::number && literal_value(pair.as_str()).is_none())
+    {
+        let message = format!("Numeric literal is larger than {}", MAX_LITERAL);
+        return Err(ParseError::new_from_span(
+            ErrorVariant::CustomError { message },
+            pair.as_span(),
+        ));
+    }
+    Ok(pairs)
+}
+
+/// The value of a numeric literal like `255`, `0xFF` or `1_000`, if it is at most `MAX_LITERAL`
+fn literal_value(literal: &str) -> Option<usize> {
+    let digits = literal.replace('_', "");
+    let value = match digits.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => digits.parse(),
+    };
+    value.ok().filter(|n| *n <= MAX_LITERAL)
+}
+
+/// Find where the input is nested deeper than `MAX_NESTING`, over-approximating how deep the parser recurses.
+/// Abstraction bodies and function types extend to the closing parenthesis, so they are closed with it.
+fn nested_too_deep(input: &str) -> Option<usize> {
+    // Levels opened by each open parenthesis, including itself
+    let mut levels = vec![0];
+    let mut nesting = 0;
+    let mut chars = input.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '-' if matches!(chars.peek(), Some((_, '-'))) => {
+                while chars.next_if(|(_, c)| *c != '\n').is_some() {}
+            }
+            '/' if input[i..].starts_with("///") => {
+                while chars.next_if(|(_, c)| *c != '\n').is_some() {}
+            }
+            '(' => {
+                levels.push(1);
+                nesting += 1;
             }
-            Rule::untyped_variable => {
-                // Variable without type annotation
-                let var_name = pair.as_str().to_string();
-                Term::Variable(var_name, None, pair.as_span().into())
+            ')' if levels.len() > 1 => nesting -= levels.pop().unwrap(),
+            ';' => {
+                levels = vec![0];
+                nesting = 0;
             }
-            r => unreachable!("Rule {:?} not expected", r),
+            // Abstractions, `fix` and the `>` of `->`
+            'λ' | '\\' | '>' => {
+                *levels.last_mut().unwrap() += 1;
+                nesting += 1;
+            }
+            'f' if input[i..].starts_with("fix") => {
+                *levels.last_mut().unwrap() += 1;
+                nesting += 1;
+            }
+            _ => {}
+        }
+        if nesting > MAX_NESTING {
+            return Some(i);
         }
     }
+    None
+}
 
-    fn parse_type(pair: Pair<Rule>) -> Type {
-        match pair.as_rule() {
-            Rule::base_type => match pair.as_str() {
-                "*" => Type::Any, // Represents any type
-                name => Type::Variable(name.to_string()),
-            },
-            Rule::app_type => {
-                let mut inner = pair.into_inner();
-                let base = parse_type(inner.next().unwrap());
-                let next = parse_type(inner.next().unwrap());
-                Type::Abstraction(Rc::new(base), Rc::new(next))
+/// Fail if a parsed term is nested deeper than the maximum depth, it could overflow the stack when it is used
+#[allow(clippy::result_large_err)]
+fn check_depth(term: Term, span: Span) -> Result<Term, ParseError> {
+    if depth(&term) <= max_depth() {
+        return Ok(term);
+    }
+    let message = format!("Term is nested deeper than {} levels", max_depth());
+    Err(ParseError::new_from_span(
+        ErrorVariant::CustomError { message },
+        span,
+    ))
+}
+
+/// Parse a top-level program into a list of terms, or all of its parse errors
+pub fn try_parse_prog(input: &str) -> Result<Program, Vec<ParseError>> {
+    let (prog, errors) = parse_prog_recover(input);
+    if errors.is_empty() {
+        Ok(prog)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Parse a top-level program into a list of terms.
+/// An empty program is returned if there were parse errors, `try_parse_prog` returns them.
+pub(crate) fn parse_prog(input: &str) -> Program {
+    try_parse_prog(input).unwrap_or_default()
+}
+
+/// Split the input after each `;` that is not part of a comment or doc comment,
+/// and at each line break that separates statements like in the grammar
+fn statements(input: &str) -> Vec<(usize, usize)> {
+    // Whether a line ends with a token the grammar allows line breaks after, like `=` or `fix`
+    fn ends_open(code: &str) -> bool {
+        let word = |w: &str| {
+            code.strip_suffix(w)
+                .is_some_and(|rest| !rest.ends_with(|c: char| c.is_alphanumeric() || c == '\''))
+        };
+        ["=", ".", "->", ",", "(", ":", "+", "*"]
+            .iter()
+            .any(|token| code.ends_with(token))
+            || word("fix")
+            || word("in")
+    }
+    // Whether a line continues the statement before it, it is indented or starts with `)`, an operator, `in` or `from`
+    fn continues(line: &str) -> bool {
+        let word = |w: &str| {
+            line.strip_prefix(w)
+                .is_some_and(|rest| !rest.starts_with(|c: char| c.is_alphanumeric() || c == '\''))
+        };
+        line.starts_with([' ', '\t', '\n', ')', '+', '*'])
+            || line.starts_with("==")
+            || word("in")
+            || word("from")
+    }
+    let mut spans = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+    // Whether the last line with code on it leaves the statement open, a doc comment belongs to the next line
+    let mut open = false;
+    for line in input.split_inclusive('\n') {
+        if offset > start && !open && !continues(line) {
+            spans.push((start, offset));
+            start = offset;
+        }
+        let comment = [line.find("--"), line.find("///")]
+            .into_iter()
+            .flatten()
+            .min()
+            .unwrap_or(line.len());
+        for (i, c) in line[..comment].char_indices() {
+            if c == ';' {
+                spans.push((start, offset + i + 1));
+                start = offset + i + 1;
             }
-            r => unreachable!("Rule {:?} not expected", r),
         }
+        let code = line[..comment].trim();
+        if line[comment..].starts_with("///") {
+            open = true;
+        } else if !code.is_empty() {
+            open = ends_open(code);
+        }
+        offset += line.len();
+    }
+    if start < input.len() {
+        spans.push((start, input.len()));
     }
+    spans
+}
 
-    let mut prog = Program::new();
-    let pairs = match LambdaCalcParser::parse(Rule::program, input) {
-        Ok(pairs) => pairs,
-        Err(e) => {
-            eprintln!("{}", e);
-            return prog;
+/// Transform a Pest pair into our own AST Expr node format
+fn parse_term(pair: Pair<Rule>) -> Term {
+    parse_term_with(pair, associativity())
+}
+
+/// Transform a Pest pair into a term, nesting applications with the given associativity
+fn parse_term_with(pair: Pair<Rule>, assoc: Associativity) -> Term {
+    match pair.as_rule() {
+        Rule::abstraction => {
+            let span = pair.as_span();
+            let mut inner: Vec<Pair<Rule>> = pair.into_inner().collect();
+            let body = parse_term_with(inner.pop().unwrap(), assoc);
+            // `λx: A, y: B. e` is `λx: A. λy: B. e`, each following abstraction starts at its parameter
+            inner
+                .into_iter()
+                .enumerate()
+                .rev()
+                .fold(body, |body, (i, pair)| {
+                    let info = if i == 0 {
+                        span.into()
+                    } else {
+                        pair.as_span().into()
+                    };
+                    let (param, expected) = match pair {
+                        // Parse variable or `_` with optional type annotation
+                        pair if matches!(pair.as_rule(), Rule::variable | Rule::wildcard) => {
+                            let mut inner_var = pair.into_inner();
+                            let var_name = inner_var.next().unwrap().as_str().to_string();
+                            let type_annotation = inner_var.next().map(parse_type);
+                            (var_name, type_annotation)
+                        }
+                        // Parse untyped variable
+                        pair if pair.as_rule() == Rule::untyped_variable => {
+                            let var_name = pair.as_str().to_string();
+                            (var_name, None)
+                        }
+                        _ => unreachable!("Expected variable or untyped variable"),
+                    };
+                    Term::Abstraction(param, expected, Box::new(body), info)
+                })
         }
-    };
-    for pair in pairs {
-        match pair.as_rule() {
-            Rule::EOI => break,
-            Rule::assignment => {
-                let mut inner = pair.into_inner();
-                let name = parse_term(inner.next().unwrap());
-                let (name, expected) = match name {
-                    Term::Variable(name, expected, _) => (name, expected),
-                    _ => unreachable!("Assignment target must be a variable with type annotation"),
+        // Rule::application => {
+        //     let mut inner = pair.into_inner();
+        //     let lhs = parse_term(inner.next().unwrap());
+        //     let rhs = parse_term(inner.next().unwrap());
+        //     Term::Application(Box::new(lhs), Box::new(rhs))
+        // }
+        // Operands separated by infix operators, folded to the left
+        Rule::application | Rule::sum | Rule::product => {
+            let span = pair.as_span();
+            let mut inner = pair.into_inner();
+            let mut lhs = parse_term_with(inner.next().unwrap(), assoc);
+            while let (Some(op), Some(rhs)) = (inner.next(), inner.next()) {
+                let name = match op.as_rule() {
+                    Rule::eq_op => "Eq",
+                    Rule::add_op => "Add",
+                    Rule::mul_op => "Mul",
+                    r => unreachable!("Operator {:?} not expected", r),
                 };
-                let term = parse_term(inner.next().unwrap());
-                prog.push(Expr::Assignment(name, expected, term));
+                let f = Term::Variable(name.to_string(), None, op.as_span().into());
+                let f = Term::Application(Box::new(f), Box::new(lhs), span.into());
+                lhs = Term::Application(
+                    Box::new(f),
+                    Box::new(parse_term_with(rhs, assoc)),
+                    span.into(),
+                );
+            }
+            lhs
+        }
+        // rhs is one or more terms
+        Rule::spine => {
+            // Syntax sugar: (e1 e2 e3 ...) -> ((e1 e2) e3 ...), or (e1 (e2 (e3 ...))) if right associative
+            let span = pair.as_span();
+            let terms = pair.into_inner().map(|term| parse_term_with(term, assoc));
+            let app = |f, x| Term::Application(Box::new(f), Box::new(x), span.into());
+            match assoc {
+                Associativity::Left => terms.reduce(app).unwrap(),
+                Associativity::Right => terms.rev().reduce(|x, f| app(f, x)).unwrap(),
             }
-            Rule::type_def => {
-                let mut inner = pair.into_inner();
-                let name = inner.next().unwrap().as_str().to_string();
-                let type_annotation = parse_type(inner.next().unwrap());
-                prog.push(Expr::TypeDef(name, type_annotation));
+        }
+        Rule::variable => {
+            let span = pair.as_span();
+            let mut inner = pair.into_inner();
+            let var_name = inner.next().unwrap().as_str().to_string();
+            let type_annotation = inner.next().map(parse_type);
+            Term::Variable(var_name, type_annotation, span.into())
+        }
+        Rule::untyped_variable => {
+            // Variable without type annotation
+            let var_name = pair.as_str().to_string();
+            Term::Variable(var_name, None, pair.as_span().into())
+        }
+        Rule::number => {
+            // Literals are normalized so `0x10`, `1_6` and `16` are the same numeral
+            let value = literal_value(pair.as_str()).expect("Literal checked when parsing");
+            Term::Variable(value.to_string(), None, pair.as_span().into())
+        }
+        // letrec f = M in N   is   (λf. N) (fix λf. M), with the annotation of `f` on both binders and the fixpoint
+        Rule::letrec => {
+            let info: LineInfo = pair.as_span().into();
+            let mut inner = pair.into_inner().skip(1);
+            let mut var = inner.next().unwrap().into_inner();
+            let name = var.next().unwrap().as_str().to_string();
+            let ty = var.next().map(parse_type);
+            let def = parse_term_with(inner.next().unwrap(), assoc);
+            // Skip the `in` keyword
+            let body = parse_term_with(inner.nth(1).unwrap(), assoc);
+            let abs =
+                |term| Term::Abstraction(name.clone(), ty.clone(), Box::new(term), info.clone());
+            let fix = Term::Fix(Box::new(abs(def)), info.clone());
+            let fix = match &ty {
+                Some(ty) => Term::Ascription(Box::new(fix), ty.clone(), info.clone()),
+                None => fix,
+            };
+            Term::Application(Box::new(abs(body)), Box::new(fix), info)
+        }
+        Rule::fix => {
+            let span = pair.as_span();
+            // Skip the keyword
+            let term = parse_term_with(pair.into_inner().nth(1).unwrap(), assoc);
+            Term::Fix(Box::new(term), span.into())
+        }
+        Rule::unit => Term::Unit(pair.as_span().into()),
+        Rule::parens => {
+            let span = pair.as_span();
+            let mut inner = pair.into_inner();
+            let term = parse_term_with(inner.next().unwrap(), assoc);
+            // Parentheses only group unless they contain a type ascription
+            match inner.next() {
+                Some(ty) => Term::Ascription(Box::new(term), parse_type(ty), span.into()),
+                None => term,
+            }
+        }
+        r => unreachable!("Rule {:?} not expected", r),
+    }
+}
+
+fn parse_type(pair: Pair<Rule>) -> Type {
+    match pair.as_rule() {
+        Rule::base_type => match pair.as_str() {
+            "*" => Type::Any, // Represents any type
+            "!" => Type::Never,
+            // Parenthesized type expression
+            s if s.starts_with('(') => match pair.into_inner().next() {
+                Some(inner) => parse_type(inner),
+                None => Type::Unit,
+            },
+            name => Type::Variable(name.to_string()),
+        },
+        // A base type, optionally followed by `-> type`
+        Rule::app_type => {
+            let mut inner = pair.into_inner();
+            let base = parse_type(inner.next().unwrap());
+            match inner.next() {
+                Some(next) => Type::Abstraction(Rc::new(base), Rc::new(parse_type(next))),
+                None => base,
+            }
+        }
+        Rule::forall_type => {
+            // Skip the keyword
+            let mut inner = pair.into_inner().skip(1);
+            let var = inner.next().unwrap().as_str().to_string();
+            Type::Forall(var, Rc::new(parse_type(inner.next().unwrap())))
+        }
+        r => unreachable!("Rule {:?} not expected", r),
+    }
+}
+
+/// Parse a single type, like `* -> *` or `forall a. a -> a`.
+/// Fails if there is any input after the type.
+#[allow(clippy::result_large_err)]
+pub fn parse_type_str(input: &str) -> Result<Type, ParseError> {
+    Ok(parse_type(
+        parse_rule(Rule::type_input, input)?.next().unwrap(),
+    ))
+}
+
+/// Parse a single term, like `λx. x` or `f x`, without a trailing `;`.
+/// Fails if there is any input after the term.
+#[allow(clippy::result_large_err)]
+pub fn parse_term_str(input: &str) -> Result<Term, ParseError> {
+    parse_term_str_with(input, associativity())
+}
+
+/// Parse a single term like `parse_term_str`, nesting applications with the given associativity
+#[allow(clippy::result_large_err)]
+pub fn parse_term_str_with(input: &str, assoc: Associativity) -> Result<Term, ParseError> {
+    let pair = parse_rule(Rule::term_input, input)?.next().unwrap();
+    let span = pair.as_span();
+    check_depth(parse_term_with(pair, assoc), span)
+}
+
+/// Parse a top-level program, recovering from errors by skipping to the next `;`.
+/// Returns the successfully parsed expressions along with all parse errors.
+pub fn parse_prog_recover(input: &str) -> (Program, Vec<ParseError>) {
+    fn parse_exprs(pairs: Pairs<Rule>, prog: &mut Program, errors: &mut Vec<ParseError>) {
+        for pair in pairs {
+            let span = pair.as_span();
+            match pair.as_rule() {
+                // The only pair of an empty program
+                Rule::EOI => break,
+                Rule::assignment => {
+                    let mut inner = pair.into_inner().peekable();
+                    let mut doc = Vec::new();
+                    while let Some(line) = inner.next_if(|pair| pair.as_rule() == Rule::doc_line) {
+                        let line = line.as_str().trim_start_matches("///");
+                        doc.push(line.strip_prefix(' ').unwrap_or(line).trim_end());
+                    }
+                    let doc = (!doc.is_empty()).then(|| doc.join("\n"));
+                    let name = parse_term(inner.next().unwrap());
+                    let (name, expected) = match name {
+                        Term::Variable(name, expected, _) => (name, expected),
+                        _ => unreachable!(
+                            "Assignment target must be a variable with type annotation"
+                        ),
+                    };
+                    match check_depth(parse_term(inner.next().unwrap()), span) {
+                        Ok(term) => prog.push(Expr::Assignment(name, expected, term, doc)),
+                        Err(e) => errors.push(e),
+                    }
+                }
+                Rule::import => {
+                    let mut names: Vec<String> =
+                        pair.into_inner().map(|p| p.as_str().to_string()).collect();
+                    let module = names.pop().unwrap();
+                    prog.push(Expr::Import(names, module));
+                }
+                Rule::type_def => {
+                    let mut inner = pair.into_inner();
+                    let name = inner.next().unwrap().as_str().to_string();
+                    let type_annotation = parse_type(inner.next().unwrap());
+                    prog.push(Expr::TypeDef(name, type_annotation));
+                }
+                Rule::pragma => {
+                    let mut inner = pair.into_inner();
+                    let key = inner.next().unwrap().as_str().to_string();
+                    let value = inner.next().map_or("", |value| value.as_str().trim_end());
+                    prog.push(Expr::Pragma(key, value.to_string()));
+                }
+                // Parse a lambda calculus term
+                _ => match check_depth(parse_term(pair), span) {
+                    Ok(term) => prog.push(Expr::Term(term)),
+                    Err(e) => errors.push(e),
+                },
+            }
+        }
+    }
+
+    let mut prog = Program::new();
+    let mut errors = Vec::new();
+    match parse_rule(Rule::program, input) {
+        Ok(pairs) => parse_exprs(pairs, &mut prog, &mut errors),
+        Err(_) => {
+            // Parse each statement on its own, blanking out everything before it
+            // so that line and column numbers in the errors stay the same
+            for (start, end) in statements(input) {
+                let mut masked: String = input[..start]
+                    .chars()
+                    .map(|c| if c == '\n' { '\n' } else { ' ' })
+                    .collect();
+                masked.push_str(&input[start..end]);
+                match parse_rule(Rule::program, &masked) {
+                    Ok(pairs) => parse_exprs(pairs, &mut prog, &mut errors),
+                    Err(e) => errors.push(e),
+                }
             }
-            // Parse a lambda calculus term
-            _ => prog.push(Expr::Term(parse_term(pair))),
         }
     }
-    prog
+    (prog, errors)
 }
@@ -1,117 +1,764 @@
-use std::io::Write;
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
 
-use crate::{parser::Type, types::TypeError, Term};
+use crate::{
+    eval::{canonicalize_names, Env, Timing},
+    parser::{associativity, Associativity, Expr, LineInfo, Program, Term, Type},
+    types::{self, Ctx, TypeError},
+};
 
-const RED: &str = "\x1b[31m";
-const DARK_GRAY: &str = "\x1b[90m";
-const YELLOW: &str = "\x1b[33m";
-const CYAN: &str = "\x1b[36m";
-const GREEN: &str = "\x1b[32m";
-const PINK: &str = "\x1b[35m";
-const PURPLE: &str = "\x1b[95m";
-const ITALIC: &str = "\x1b[3m";
-const RESET: &str = "\x1b[0m";
+/// ANSI escape codes used by the pretty printers
+pub struct Palette {
+    pub red: &'static str,
+    pub dark_gray: &'static str,
+    pub yellow: &'static str,
+    pub cyan: &'static str,
+    pub green: &'static str,
+    pub pink: &'static str,
+    pub purple: &'static str,
+    pub blue: &'static str,
+    pub italic: &'static str,
+    pub reset: &'static str,
+}
+
+pub const COLORED: Palette = Palette {
+    red: "\x1b[31m",
+    dark_gray: "\x1b[90m",
+    yellow: "\x1b[33m",
+    cyan: "\x1b[36m",
+    green: "\x1b[32m",
+    pink: "\x1b[35m",
+    purple: "\x1b[95m",
+    blue: "\x1b[34m",
+    italic: "\x1b[3m",
+    reset: "\x1b[0m",
+};
+
+pub const PLAIN: Palette = Palette {
+    red: "",
+    dark_gray: "",
+    yellow: "",
+    cyan: "",
+    green: "",
+    pink: "",
+    purple: "",
+    blue: "",
+    italic: "",
+    reset: "",
+};
+
+impl Palette {
+    /// The palette to print with, all codes are empty when colors are disabled
+    pub fn new(enabled: bool) -> &'static Palette {
+        if enabled {
+            &COLORED
+        } else {
+            &PLAIN
+        }
+    }
+
+    /// Colors are enabled unless the `NO_COLOR` environment variable is set (see https://no-color.org)
+    pub fn from_env() -> &'static Palette {
+        Palette::new(std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()))
+    }
+}
+
+/// Maximum line width when printing terms, 0 means unlimited
+static WIDTH: AtomicUsize = AtomicUsize::new(0);
+
+/// Wrap printed terms that are wider than the given number of columns
+pub fn set_width(width: usize) {
+    WIDTH.store(width, Ordering::Relaxed);
+}
+
+/// How deeply nested and how many subterms of a printed term are shown, 0 means unlimited
+static DEPTH: AtomicUsize = AtomicUsize::new(0);
+static MAX_NODES: AtomicUsize = AtomicUsize::new(0);
+
+/// Print subterms nested deeper than `depth` as `…`, the root of a term is at depth 1
+pub fn set_print_depth(depth: usize) {
+    DEPTH.store(depth, Ordering::Relaxed);
+}
 
-pub fn line(len: usize) {
-    println!("{}{}{}", DARK_GRAY, "-".repeat(len), RESET);
+/// Print at most `nodes` subterms of a term, counted from the left, the rest as `…`
+pub fn set_print_max_nodes(nodes: usize) {
+    MAX_NODES.store(nodes, Ordering::Relaxed);
 }
 
-pub fn pause(s: &str) {
-    print!("{YELLOW}<{}>{RESET}", s);
-    std::io::stdout().flush().unwrap();
-    let _ = std::io::stdin().read_line(&mut String::new()).unwrap();
+/// The name of the variable standing in for the subterms left out by `truncate`
+pub const ELLIPSIS: &str = "…";
+
+/// Replace the subterms nested deeper than `depth`, and those after the first `max_nodes`, with `…`.
+/// A limit of 0 means unlimited, only what is shown changes and the term itself is still evaluated in full.
+pub fn truncate(t: &Term, depth: usize, max_nodes: usize) -> Term {
+    fn go(t: &Term, depth: usize, nodes: &mut usize) -> Term {
+        if depth == 0 || *nodes == 0 {
+            return Term::Variable(ELLIPSIS.to_string(), None, t.info().clone());
+        }
+        *nodes -= 1;
+        let go = |t: &Term, nodes: &mut usize| Box::new(go(t, depth - 1, nodes));
+        match t {
+            Term::Abstraction(param, ty, body, info) => {
+                Term::Abstraction(param.clone(), ty.clone(), go(body, nodes), info.clone())
+            }
+            Term::Application(f, x, info) => {
+                let f = go(f, nodes);
+                Term::Application(f, go(x, nodes), info.clone())
+            }
+            Term::Fix(f, info) => Term::Fix(go(f, nodes), info.clone()),
+            Term::Ascription(term, ty, info) => {
+                Term::Ascription(go(term, nodes), ty.clone(), info.clone())
+            }
+            Term::Variable(_, _, _) | Term::Unit(_) => t.clone(),
+        }
+    }
+    let unlimited = |limit| if limit == 0 { usize::MAX } else { limit };
+    go(t, unlimited(depth), &mut unlimited(max_nodes))
+}
+
+/// Whether type annotations are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TypeMode {
+    /// Print parameters, variables and ascriptions with their annotations, like `λx : A. (x : A)`
+    #[default]
+    ShowTypes,
+    /// Leave out all annotations for an untyped view, like `λx. x`
+    HideTypes,
+}
+
+static HIDE_TYPES: AtomicBool = AtomicBool::new(false);
+
+/// Set whether printed terms show their type annotations
+pub fn set_type_mode(mode: TypeMode) {
+    HIDE_TYPES.store(mode == TypeMode::HideTypes, Ordering::Relaxed);
+}
+
+pub fn type_mode() -> TypeMode {
+    if HIDE_TYPES.load(Ordering::Relaxed) {
+        TypeMode::HideTypes
+    } else {
+        TypeMode::ShowTypes
+    }
+}
+
+/// How nested abstractions are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BinderMode {
+    /// Print each abstraction with its own `λ`, like `λx. λy. x`
+    #[default]
+    Nested,
+    /// Print directly nested abstractions with one `λ` and their parameters in order, like `λx y. x`
+    Flat,
+}
+
+static FLAT_BINDERS: AtomicBool = AtomicBool::new(false);
+
+/// Set whether directly nested abstractions are printed with a single `λ`
+pub fn set_binder_mode(mode: BinderMode) {
+    FLAT_BINDERS.store(mode == BinderMode::Flat, Ordering::Relaxed);
+}
+
+pub fn binder_mode() -> BinderMode {
+    if FLAT_BINDERS.load(Ordering::Relaxed) {
+        BinderMode::Flat
+    } else {
+        BinderMode::Nested
+    }
+}
+
+/// The parameters printed after one `λ` and the body after them, only the first parameter unless binders are flat
+fn binders<'a>(
+    t: &'a Term,
+    types: TypeMode,
+    mode: BinderMode,
+    p: &Palette,
+) -> (Vec<String>, &'a Term) {
+    let mut params = Vec::new();
+    let mut t = t;
+    while let Term::Abstraction(param, expected, body, _) = t {
+        params.push(typed_var_with(param, expected, types, p));
+        t = shown(body, types);
+        if mode == BinderMode::Nested || !matches!(t, Term::Abstraction(_, _, _, _)) {
+            break;
+        }
+    }
+    (params, t)
+}
+
+/// The term as it is printed, ascriptions are left out when types are hidden
+fn shown(t: &Term, types: TypeMode) -> &Term {
+    match t {
+        Term::Ascription(term, _, _) if types == TypeMode::HideTypes => shown(term, types),
+        _ => t,
+    }
+}
+
+pub fn line(len: usize, p: &Palette) {
+    let Palette {
+        dark_gray, reset, ..
+    } = p;
+    println!("{}{}{}", dark_gray, "-".repeat(len), reset);
+}
+
+/// Wait for Enter on stdin, which browsers don't have
+#[cfg(not(target_arch = "wasm32"))]
+pub fn pause(s: &str, p: &Palette) {
+    use std::io::Write;
+    let Palette { yellow, reset, .. } = p;
+    print!("{yellow}<{}>{reset}", s);
+    // Stepping continues without waiting if stdout or stdin are closed
+    let _ = std::io::stdout().flush();
+    let _ = std::io::stdin().read_line(&mut String::new());
     print!("\x1b[1A"); // Move up one line
     print!("\x1b[2K"); // Clear the line
 }
 
-pub fn var(v: &str) -> String {
+pub fn var(v: &str, p: &Palette) -> String {
+    let Palette {
+        cyan,
+        dark_gray,
+        green,
+        pink,
+        italic,
+        reset,
+        ..
+    } = p;
     match v {
+        // Terms built outside the parser may have an empty name, there is nothing to color
+        "" => String::new(),
+        // subterms left out by `truncate`
+        ELLIPSIS => format!("{dark_gray}{ELLIPSIS}{reset}"),
         // booleans
-        "true" => format!("{CYAN}{ITALIC}true{RESET}"),
-        "false" => format!("{CYAN}{ITALIC}false{RESET}"),
+        "true" => format!("{cyan}{italic}true{reset}"),
+        "false" => format!("{cyan}{italic}false{reset}"),
         // function names
-        _ if char::is_uppercase(v.chars().next().unwrap()) => {
-            format!("{PINK}{}{RESET}", v)
+        _ if v.chars().next().is_some_and(char::is_uppercase) => {
+            format!("{pink}{}{reset}", v)
         }
         // digits
         _ if v.chars().all(char::is_numeric) => {
-            format!("{GREEN}{}{RESET}", v)
+            format!("{green}{}{reset}", v)
         }
         // variable names
-        _ => format!("{ITALIC}{}{RESET}", v),
+        _ => format!("{italic}{}{reset}", v),
+    }
+}
+
+/// Print a set of variables in sorted order, like `{x, y}`
+pub fn var_set(vars: &HashSet<String>, p: &Palette) -> String {
+    let Palette {
+        dark_gray, reset, ..
+    } = p;
+    let mut vars: Vec<&String> = vars.iter().collect();
+    vars.sort();
+    let vars: Vec<String> = vars.into_iter().map(|v| var(v, p)).collect();
+    format!(
+        "{dark_gray}{{{reset}{}{dark_gray}}}{reset}",
+        vars.join(&format!("{dark_gray},{reset} "))
+    )
+}
+
+static CANONICAL: AtomicBool = AtomicBool::new(false);
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Suppress the REPL prompt and what the type checker is doing, leaving results, warnings and errors
+pub fn set_quiet(enabled: bool) {
+    QUIET.store(enabled, Ordering::Relaxed);
+}
+
+/// Check if output is quiet, see `set_quiet`
+pub fn quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Rename the bound variables of printed terms with `eval::canonicalize_names`, so output is stable
+pub fn set_canonical(enabled: bool) {
+    CANONICAL.store(enabled, Ordering::Relaxed);
+}
+
+/// Pretty print a term using as few parentheses as possible.
+/// Application is left-associative and abstraction bodies extend as far right as possible,
+/// so `((f x) y)` is printed as `f x y` and `λx. (λy. y)` as `λx. λy. y`.
+/// Subterms beyond the print depth or node limit are left out, see `truncate`.
+pub fn term(t: &Term, p: &Palette) -> String {
+    let canonical;
+    let t = if CANONICAL.load(Ordering::Relaxed) {
+        canonical = canonicalize_names(t);
+        &canonical
+    } else {
+        t
+    };
+    let truncated;
+    let t = match (
+        DEPTH.load(Ordering::Relaxed),
+        MAX_NODES.load(Ordering::Relaxed),
+    ) {
+        (0, 0) => t,
+        (depth, nodes) => {
+            truncated = truncate(t, depth, nodes);
+            &truncated
+        }
+    };
+    match WIDTH.load(Ordering::Relaxed) {
+        0 => term_with(t, p),
+        width => term_width(t, width, p),
     }
 }
 
-/// Pretty print a term
-pub fn term(t: &Term) -> String {
+/// Pretty print a term, breaking it across lines where it is wider than `width` columns.
+/// Applications put each argument on its own line and abstractions put their body on the next,
+/// indented below them.
+pub fn term_width(t: &Term, width: usize, p: &Palette) -> String {
+    wrap_with(t, p, 0, width)
+}
+
+fn wrap_with(t: &Term, p: &Palette, indent: usize, width: usize) -> String {
+    let Palette {
+        dark_gray,
+        yellow,
+        reset,
+        ..
+    } = p;
+    let t = shown(t, type_mode());
+    // Measure without colors, escape codes take no space
+    if indent + term_with(t, &PLAIN).chars().count() <= width {
+        return term_with(t, p);
+    }
+    let pad = " ".repeat(indent + 2);
+    // Wrap in parentheses unless it is a plain variable, like an argument in `term_with`
+    let arg = |x: &Term| match shown(x, type_mode()) {
+        Term::Variable(_, None, _) | Term::Unit(_) | Term::Ascription(_, _, _) => {
+            wrap_with(x, p, indent + 2, width)
+        }
+        // Leave room for the closing parenthesis
+        _ => parens(wrap_with(x, p, indent + 3, width.saturating_sub(1)), p),
+    };
     match t {
-        Term::Abstraction(param, expected, body, _) => {
-            let body = term(body);
+        Term::Abstraction(_, _, _, _) => {
+            let (params, body) = binders(t, type_mode(), binder_mode(), p);
             format!(
-                "{YELLOW}λ{RESET}{}{DARK_GRAY}.{RESET}{}",
-                typed_var(param, expected),
-                body
+                "{yellow}λ{reset}{}{dark_gray}.{reset}\n{pad}{}",
+                params.join(" "),
+                wrap_with(body, p, indent + 2, width)
             )
         }
-        Term::Application(f, x, _) => format!(
-            "{DARK_GRAY}({RESET}{} {}{DARK_GRAY}){RESET}",
-            term(f),
-            term(x)
-        ),
-        Term::Variable(v, t, _) => {
-            if let Some(t) = t {
-                format!("{} {DARK_GRAY}:{RESET} {}", var(v), r#type(t))
-            } else {
-                var(v)
+        // A right associative application `f (x y)` is `f x y`, its function is wrapped instead
+        Term::Application(f, x, _) if associativity() == Associativity::Right => {
+            let f = match shown(f, type_mode()) {
+                Term::Abstraction(_, _, _, _) | Term::Application(_, _, _) => {
+                    parens(wrap_with(f, p, indent + 1, width.saturating_sub(1)), p)
+                }
+                _ => wrap_with(f, p, indent, width),
+            };
+            let x = match shown(x, type_mode()) {
+                Term::Application(_, _, _) => wrap_with(x, p, indent + 2, width),
+                _ => arg(x),
+            };
+            format!("{}\n{pad}{}", f, x)
+        }
+        Term::Application(_, _, _) => {
+            // Collect the arguments of the whole spine `f x y ...`
+            let mut head = t;
+            let mut args = Vec::new();
+            while let Term::Application(f, x, _) = head {
+                args.push(arg(x));
+                head = f;
             }
+            args.reverse();
+            let head = match shown(head, type_mode()) {
+                Term::Abstraction(_, _, _, _) => {
+                    parens(wrap_with(head, p, indent + 1, width.saturating_sub(1)), p)
+                }
+                _ => wrap_with(head, p, indent, width),
+            };
+            format!("{}\n{pad}{}", head, args.join(&format!("\n{pad}")))
         }
+        Term::Fix(f, _) => format!("{yellow}fix{reset}\n{pad}{}", arg(f)),
+        Term::Ascription(term, ty, _) => format!(
+            "{dark_gray}({reset}{}\n{pad}{dark_gray}:{reset} {}{dark_gray}){reset}",
+            wrap_with(term, p, indent + 1, width),
+            r#type(ty, p)
+        ),
+        Term::Variable(_, _, _) | Term::Unit(_) => term_with(t, p),
     }
 }
 
-pub fn typed_var(v: &str, ty: &Option<Type>) -> String {
-    if let Some(t) = ty {
-        format!("{} {DARK_GRAY}:{RESET} {}", var(v), r#type(t))
+/// Print a term without colors, so it can be parsed again
+pub fn plain_term(t: &Term) -> String {
+    term_with(t, &PLAIN)
+}
+
+/// Print a term on one line like `term`, for applications nested with the given associativity
+pub fn term_assoc(t: &Term, assoc: Associativity, p: &Palette) -> String {
+    compact(t, p, assoc, type_mode(), binder_mode())
+}
+
+/// Print a term on one line like `term`, with or without its type annotations
+pub fn term_types(t: &Term, types: TypeMode, p: &Palette) -> String {
+    compact(t, p, associativity(), types, binder_mode())
+}
+
+/// Print a term on one line like `term`, with nested abstractions printed as given
+pub fn term_binders(t: &Term, mode: BinderMode, p: &Palette) -> String {
+    compact(t, p, associativity(), type_mode(), mode)
+}
+
+fn term_with(t: &Term, p: &Palette) -> String {
+    compact(t, p, associativity(), type_mode(), binder_mode())
+}
+
+/// Print a term on one line, leaving out the parentheses implied by the associativity of application
+fn compact(
+    t: &Term,
+    p: &Palette,
+    assoc: Associativity,
+    types: TypeMode,
+    mode: BinderMode,
+) -> String {
+    let Palette {
+        dark_gray,
+        yellow,
+        cyan,
+        reset,
+        ..
+    } = p;
+    match shown(t, types) {
+        Term::Abstraction(_, _, _, _) => {
+            let (params, body) = binders(shown(t, types), types, mode, p);
+            format!(
+                "{yellow}λ{reset}{}{dark_gray}.{reset} {}",
+                params.join(" "),
+                compact(body, p, assoc, types, mode)
+            )
+        }
+        Term::Application(f, x, _) => {
+            // Abstractions must be wrapped on the left, or they would swallow the argument,
+            // and applications unless they associate to the left
+            let f = match (shown(f, types), assoc) {
+                (Term::Abstraction(_, _, _, _), _)
+                | (Term::Application(_, _, _), Associativity::Right) => {
+                    parens(compact(f, p, assoc, types, mode), p)
+                }
+                _ => compact(f, p, assoc, types, mode),
+            };
+            // Only plain variables, units and ascriptions can be applied without parentheses,
+            // and applications if they associate to the right
+            let x = match (shown(x, types), assoc) {
+                (Term::Variable(_, None, _) | Term::Unit(_) | Term::Ascription(_, _, _), _)
+                | (Term::Application(_, _, _), Associativity::Right) => {
+                    compact(x, p, assoc, types, mode)
+                }
+                _ => parens(compact(x, p, assoc, types, mode), p),
+            };
+            format!("{} {}", f, x)
+        }
+        Term::Variable(v, t, _) => typed_var_with(v, t, types, p),
+        Term::Fix(f, _) => {
+            let f = match shown(f, types) {
+                Term::Variable(_, None, _) | Term::Unit(_) | Term::Ascription(_, _, _) => {
+                    compact(f, p, assoc, types, mode)
+                }
+                _ => parens(compact(f, p, assoc, types, mode), p),
+            };
+            format!("{yellow}fix{reset} {}", f)
+        }
+        Term::Unit(_) => format!("{cyan}(){reset}"),
+        Term::Ascription(term, ty, _) => {
+            let ascription = format!(
+                "{} {dark_gray}:{reset} {}",
+                compact(term, p, assoc, types, mode),
+                r#type(ty, p)
+            );
+            parens(ascription, p)
+        }
+    }
+}
+
+fn parens(s: String, p: &Palette) -> String {
+    let Palette {
+        dark_gray, reset, ..
+    } = p;
+    format!("{dark_gray}({reset}{}{dark_gray}){reset}", s)
+}
+
+pub fn typed_var(v: &str, ty: &Option<Type>, p: &Palette) -> String {
+    typed_var_with(v, ty, type_mode(), p)
+}
+
+fn typed_var_with(v: &str, ty: &Option<Type>, types: TypeMode, p: &Palette) -> String {
+    let Palette {
+        dark_gray, reset, ..
+    } = p;
+    if let (Some(t), TypeMode::ShowTypes) = (ty, types) {
+        format!("{} {dark_gray}:{reset} {}", var(v, p), r#type(t, p))
     } else {
-        var(v)
+        var(v, p)
     }
 }
 
-pub fn assign(target: &str, ty: &Option<Type>, body: &Term) -> String {
+pub fn assign(target: &str, ty: &Option<Type>, body: &Term, p: &Palette) -> String {
+    let Palette {
+        dark_gray, reset, ..
+    } = p;
     format!(
-        "{} {DARK_GRAY}={RESET} {}",
-        typed_var(target, ty),
-        term(body)
+        "{} {dark_gray}={reset} {}",
+        typed_var(target, ty, p),
+        term(body, p)
     )
 }
 
-pub fn r#type(t: &Type) -> String {
+/// Print an expression of a program, without the trailing `;`
+pub fn expr(e: &Expr, p: &Palette) -> String {
+    let Palette {
+        dark_gray,
+        yellow,
+        reset,
+        ..
+    } = p;
+    match e {
+        Expr::Assignment(target, ty, body, doc) => {
+            // Doc comments are kept above the definition, so printed programs can be loaded again
+            let doc: String = doc
+                .iter()
+                .flat_map(|doc| doc.lines())
+                .map(|line| match line {
+                    "" => format!("{dark_gray}///{reset}\n"),
+                    _ => format!("{dark_gray}/// {}{reset}\n", line),
+                })
+                .collect();
+            format!(
+                "{}{} {dark_gray}={reset} {}",
+                doc,
+                typed_var_with(target, ty, type_mode(), p),
+                term_with(body, p)
+            )
+        }
+        Expr::TypeDef(name, ty) => format!(
+            "{yellow}type{reset} {} {dark_gray}={reset} {}",
+            r#type(&Type::Variable(name.clone()), p),
+            r#type(ty, p)
+        ),
+        Expr::Import(names, module) => format!(
+            "{yellow}import{reset} {} {yellow}from{reset} {}",
+            names
+                .iter()
+                .map(|name| var(name, p))
+                .collect::<Vec<_>>()
+                .join(", "),
+            module
+        ),
+        Expr::Pragma(key, value) if value.is_empty() => format!("{yellow}#{}{reset}", key),
+        Expr::Pragma(key, value) => format!("{yellow}#{}{reset} {}", key, value),
+        Expr::Term(t) => term_with(t, p),
+    }
+}
+
+/// Print a program with one expression per line
+pub fn program(prog: &Program, p: &Palette) -> String {
+    prog.iter().map(|e| format!("{};\n", expr(e, p))).collect()
+}
+
+/// Print a program without colors, so it can be parsed again
+pub fn plain_program(prog: &Program) -> String {
+    program(prog, &PLAIN)
+}
+
+/// Print a term as an indented tree of its nodes, with their names, types and positions
+pub fn ast_tree(t: &Term, p: &Palette) -> String {
+    let mut lines = Vec::new();
+    ast_node(t, 0, p, &mut lines);
+    lines.join("\n")
+}
+
+fn ast_node(t: &Term, depth: usize, p: &Palette, lines: &mut Vec<String>) {
+    let Palette {
+        dark_gray,
+        purple,
+        reset,
+        ..
+    } = p;
+    let typed = |ty: &Type| format!("{dark_gray}:{reset} {} ", r#type(ty, p));
+    let named = |x: &str, ty: &Option<Type>| {
+        format!("{} {}", var(x, p), ty.as_ref().map_or(String::new(), typed))
+    };
+    let (kind, details, children): (&str, String, Vec<&Term>) = match t {
+        Term::Abstraction(x, ty, body, _) => ("Abstraction", named(x, ty), vec![body]),
+        Term::Application(f, x, _) => ("Application", String::new(), vec![f, x]),
+        Term::Variable(x, ty, _) => ("Variable", named(x, ty), vec![]),
+        Term::Fix(f, _) => ("Fix", String::new(), vec![f]),
+        Term::Unit(_) => ("Unit", String::new(), vec![]),
+        Term::Ascription(e, ty, _) => ("Ascription", typed(ty), vec![e]),
+    };
+    let LineInfo(line, col) = t.info();
+    lines.push(format!(
+        "{}{purple}{}{reset} {}{dark_gray}at {}:{}{reset}",
+        "  ".repeat(depth),
+        kind,
+        details,
+        line,
+        col
+    ));
+    for child in children {
+        ast_node(child, depth + 1, p, lines);
+    }
+}
+
+/// Print a type without colors
+pub fn plain_type(t: &Type) -> String {
+    r#type(t, &PLAIN)
+}
+
+pub fn r#type(t: &Type, p: &Palette) -> String {
+    let Palette {
+        dark_gray,
+        cyan,
+        purple,
+        reset,
+        ..
+    } = p;
     match t {
-        Type::Any => format!("{CYAN}*{RESET}"),
-        Type::Variable(name) => format!("{PURPLE}{}{RESET}", name),
-        Type::Abstraction(t1, t2) => format!("{} {DARK_GRAY}->{RESET} {}", r#type(t1), r#type(t2)),
+        Type::Any => format!("{cyan}*{reset}"),
+        Type::Unit => format!("{cyan}(){reset}"),
+        Type::Never => format!("{cyan}!{reset}"),
+        Type::Variable(name) => format!("{purple}{}{reset}", name),
+        Type::Forall(var, body) => format!(
+            "{cyan}forall{reset} {purple}{}{reset}{dark_gray}.{reset} {}",
+            var,
+            r#type(body, p)
+        ),
+        // Arrows are right-associative and quantifiers extend right, so only a function parameter needs parentheses
+        Type::Abstraction(t1, t2) => {
+            let t1 = match t1.as_ref() {
+                Type::Abstraction(_, _) | Type::Forall(_, _) => parens(r#type(t1, p), p),
+                _ => r#type(t1, p),
+            };
+            format!("{} {dark_gray}->{reset} {}", t1, r#type(t2, p))
+        }
+    }
+}
+
+/// Print a step of a reduction trace, with the location of the redex it reduced
+pub fn trace_step(step: usize, t: &Term, location: &LineInfo, p: &Palette) -> String {
+    let Palette {
+        dark_gray, reset, ..
+    } = p;
+    format!(
+        "{dark_gray}{}.{reset} {} {dark_gray}(redex at line {} col {}){reset}",
+        step,
+        term(t, p),
+        location.0,
+        location.1
+    )
+}
+
+/// Print how long a reduction took and how many steps it needed
+pub fn timing(t: &Timing, p: &Palette) -> String {
+    let Palette { blue, reset, .. } = p;
+    let steps = if t.steps == 1 { "step" } else { "steps" };
+    format!("{blue}Took {:?} in {} {}{reset}", t.elapsed, t.steps, steps)
+}
+
+/// Print each binding of the environment as `name = term` on its own line, sorted by name
+pub fn env(env: &Env, p: &Palette) -> String {
+    let mut names: Vec<&String> = env.keys().collect();
+    names.sort();
+    names
+        .into_iter()
+        .map(|name| format!("{} = {}", name, term(&env[name], p)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Print the bindings of an environment as a program that can be loaded again
+pub fn env_program(env: &Env) -> String {
+    let mut names: Vec<&String> = env.keys().collect();
+    names.sort();
+    let prog: Program = names
+        .into_iter()
+        .map(|name| Expr::Assignment(name.clone(), None, env[name].clone(), None))
+        .collect();
+    plain_program(&prog)
+}
+
+/// Print two types with the parts where they differ highlighted in red, and the parts they share dimmed
+pub fn type_diff(expected: &Type, found: &Type, p: &Palette) -> (String, String) {
+    let Palette {
+        red,
+        dark_gray,
+        reset,
+        ..
+    } = p;
+    match (expected, found) {
+        (Type::Abstraction(e1, e2), Type::Abstraction(f1, f2)) => {
+            let (e1_diff, f1_diff) = type_diff(e1, f1, p);
+            let (e2_diff, f2_diff) = type_diff(e2, f2, p);
+            // Same parentheses as `r#type`, only a function parameter needs them
+            let param = |t: &Type, diff: String| match t {
+                Type::Abstraction(_, _) | Type::Forall(_, _) => parens(diff, p),
+                _ => diff,
+            };
+            let arrow = format!(" {dark_gray}->{reset} ");
+            (
+                format!("{}{arrow}{}", param(e1, e1_diff), e2_diff),
+                format!("{}{arrow}{}", param(f1, f1_diff), f2_diff),
+            )
+        }
+        _ => {
+            let color = if expected == found { dark_gray } else { red };
+            (
+                format!("{color}{}{reset}", r#type(expected, &PLAIN)),
+                format!("{color}{}{reset}", r#type(found, &PLAIN)),
+            )
+        }
     }
 }
 
-pub fn ty_err(err: TypeError) -> String {
-    let type_error = format!("{RED}Type error{RESET}");
+/// Print a type, followed by its definition if it is an alias in the context
+fn alias(t: &Type, ctx: Option<&Ctx>, p: &Palette) -> String {
+    let Palette {
+        dark_gray, reset, ..
+    } = p;
+    if let (Type::Variable(_), Some(ctx)) = (t, ctx) {
+        let resolved = types::resolve_type(ctx, t);
+        if resolved != *t {
+            return format!(
+                "{} {dark_gray}(={reset} {}{dark_gray}){reset}",
+                r#type(t, p),
+                r#type(&resolved, p)
+            );
+        }
+    }
+    r#type(t, p)
+}
+
+/// Print a type error, expanding type aliases using the context if given
+pub fn ty_err(err: TypeError, ctx: Option<&Ctx>, p: &Palette) -> String {
+    let Palette { red, reset, .. } = p;
+    let type_error = format!("{red}Type error{reset}");
     match err {
         TypeError::Mismatch {
             expected,
             found,
             info,
         } => {
+            // Function types are compared part by part to show where they differ
+            let (expected, found) = match (&expected, &found) {
+                (Type::Abstraction(_, _), Type::Abstraction(_, _)) => {
+                    type_diff(&expected, &found, p)
+                }
+                _ => (alias(&expected, ctx, p), alias(&found, ctx, p)),
+            };
             format!(
                 "{type_error}: expected {} but found {} at line {} col {}",
-                r#type(&expected),
-                r#type(&found),
-                info.0,
-                info.1
+                expected, found, info.0, info.1
             )
         }
         TypeError::NotAFunction(t, info) => {
             format!(
                 "{type_error}: {} is not a function type at line {} col {}",
-                r#type(&t),
+                alias(&t, ctx, p),
                 info.0,
                 info.1
             )
@@ -119,7 +766,22 @@ pub fn ty_err(err: TypeError) -> String {
         TypeError::Unbound(name, info) => {
             format!(
                 "{type_error}: unbound variable `{}` at line {} col {}",
-                var(&name),
+                var(&name, p),
+                info.0,
+                info.1
+            )
+        }
+        TypeError::AnnotationConflict {
+            name,
+            first,
+            second,
+            info,
+        } => {
+            format!(
+                "{type_error}: `{}` is annotated {} where it is bound but {} at line {} col {}",
+                var(&name, p),
+                alias(&first, ctx, p),
+                alias(&second, ctx, p),
                 info.0,
                 info.1
             )
@@ -127,13 +789,74 @@ pub fn ty_err(err: TypeError) -> String {
     }
 }
 
-pub fn ctx(ctx: &crate::types::Ctx) -> String {
+/// Print an error for a variable that is not defined in the environment
+pub fn unbound_err(name: &str, info: &LineInfo, p: &Palette) -> String {
+    let Palette { red, reset, .. } = p;
+    format!(
+        "{red}Evaluation error{reset}: unbound variable `{}` at line {} col {}",
+        var(name, p),
+        info.0,
+        info.1
+    )
+}
+
+/// Print a warning for a pragma that is unknown or has an invalid value
+pub fn pragma_warning(key: &str, value: &str, p: &Palette) -> String {
+    let Palette { yellow, reset, .. } = p;
+    format!(
+        "{yellow}Warning{reset}: ignoring pragma `#{} {}`",
+        key, value
+    )
+}
+
+/// Print a warning for a binder shadowing an enclosing binder of the same name
+pub fn shadow_warning(name: &str, info: &LineInfo, p: &Palette) -> String {
+    let Palette { yellow, reset, .. } = p;
+    format!(
+        "{yellow}Warning{reset}: `{}` shadows an enclosing binder at line {} col {}",
+        var(name, p),
+        info.0,
+        info.1
+    )
+}
+
+/// Print a warning for a bound variable that is not used exactly once
+pub fn linear_warning(name: &str, uses: usize, info: &LineInfo, p: &Palette) -> String {
+    let Palette { yellow, reset, .. } = p;
+    let uses = match uses {
+        0 => "never used".to_string(),
+        n => format!("used {} times", n),
+    };
+    format!(
+        "{yellow}Warning{reset}: `{}` is {} instead of once at line {} col {}",
+        var(name, p),
+        uses,
+        info.0,
+        info.1
+    )
+}
+
+/// Print a warning for an application that is stuck on a free variable
+pub fn stuck_warning(t: &Term, p: &Palette) -> String {
+    let Palette { yellow, reset, .. } = p;
+    format!(
+        "{yellow}Warning{reset}: `{}` is stuck applying a free variable at line {} col {}",
+        term(t, p),
+        t.info().0,
+        t.info().1
+    )
+}
+
+pub fn ctx(ctx: &Ctx, p: &Palette) -> String {
+    let Palette {
+        dark_gray, reset, ..
+    } = p;
     let mut ctx_str = "Γ = {\n".to_string();
     for (name, t) in ctx.iter() {
         ctx_str.push_str(&format!(
-            "  {} {DARK_GRAY}:{RESET} {}{DARK_GRAY},{RESET}\n",
-            var(name),
-            r#type(t)
+            "  {} {dark_gray}:{reset} {}{dark_gray},{reset}\n",
+            var(name, p),
+            r#type(t, p)
         ));
     }
     ctx_str.push('}');
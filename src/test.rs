@@ -1,18 +1,53 @@
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
+    use std::{
+        collections::{HashMap, HashSet},
+        rc::Rc,
+        sync::Mutex,
+    };
 
     use crate::{
-        eval::{eval_expr, inline_vars},
-        parser::{parse_prog, Expr, Term},
+        debruijn::{self, Nameless},
+        emit,
+        eval::{
+            alpha_eq, beta_eta_equal, beta_step, bound_vars, canonicalize_names, check_bound,
+            decode_numeral, depth, encode_bool, eval_expr, eval_expr_in, eval_expr_timed,
+            eval_prog, eval_prog_results, free_vars, import, inline_vars, is_normal_form,
+            load_files, load_std, load_std_from, native_step, only_term, redex, redex_path,
+            reduce_observed, reduce_to_normal_form, reduce_to_normal_form_observed,
+            reduce_to_normal_form_timed, reduce_to_whnf, reduce_with_limit, reduce_with_trace,
+            search, std_source, step, substitute, substitute_fresh, subterm, term_hash,
+            to_eta_long, type_of, unbound_vars, EvalError, FreshNames, Options, Printer,
+            ReductionIter, ReductionObserver, Strategy, DEFAULT_MAX_DEPTH,
+        },
+        lint, nbe,
+        parser::{
+            parse_prog_recover, parse_status, parse_term_str, parse_term_str_with, parse_type_str,
+            try_parse_prog, Associativity, Expr, LineInfo, ParseStatus, Program, Term, Type,
+            MAX_LITERAL, MAX_NESTING,
+        },
+        print::{self, PLAIN},
+        types::{self, Ctx, TypeError},
         PRINT_NONE,
     };
 
-    impl Expr {
+    /// Parse a program, empty if it has parse errors
+    fn parse_prog(input: &str) -> Program {
+        try_parse_prog(input).unwrap_or_default()
+    }
+
+    /// The term of an expression, panicking if it has none
+    trait ExprTerm {
+        fn term(&self) -> &Term;
+    }
+
+    impl ExprTerm for Expr {
         fn term(&self) -> &Term {
             match self {
-                Expr::Assignment(_, _, term) => term,
+                Expr::Assignment(_, _, term, _) => term,
                 Expr::TypeDef(_, _) => panic!("Type definitions should not be used as terms"),
+                Expr::Pragma(_, _) => panic!("Pragmas should not be used as terms"),
+                Expr::Import(_, _) => panic!("Imports should not be used as terms"),
                 Expr::Term(term) => term,
             }
         }
@@ -23,7 +58,7 @@ mod tests {
         let input = "x = y; λx. (x y); x y;";
         let terms = parse_prog(input);
 
-        if let Expr::Assignment(target, _, body) = &terms[0] {
+        if let Expr::Assignment(target, _, body, _) = &terms[0] {
             assert_eq!(target, "x");
             if let Term::Variable(var_name, _, _) = body {
                 assert_eq!(var_name, "y");
@@ -116,8 +151,8 @@ mod tests {
         let input = "x = λx. (x y); x y;";
         let prog = parse_prog(input);
         assert_eq!(prog.len(), 2);
-        eval_expr(&prog[0], &mut env, false, PRINT_NONE);
-        let result = eval_expr(&prog[1], &mut env, false, PRINT_NONE);
+        eval_expr(&prog[0], &mut env, false, PRINT_NONE).unwrap();
+        let result = eval_expr(&prog[1], &mut env, false, PRINT_NONE).unwrap();
 
         if let Term::Application(f, x, _) = result {
             if let Term::Variable(var_name, _, _) = &*f {
@@ -146,8 +181,2841 @@ mod tests {
         let binding = parse_prog(expected).pop().unwrap();
         let prog_expected = binding.term();
         assert_eq!(prog.len(), 2);
-        eval_expr(&prog[0], &mut env, false, PRINT_NONE);
+        eval_expr(&prog[0], &mut env, false, PRINT_NONE).unwrap();
         let inlined = inline_vars(prog[1].term(), &env);
         assert_eq!(&inlined, prog_expected);
     }
+    #[test]
+    fn test_no_color() {
+        let prog = parse_prog("λx: Bool. (x True);");
+        let printed = print::term(prog[0].term(), &PLAIN);
+        assert!(!printed.contains('\x1b'));
+        assert_eq!(printed, "λx : Bool. x True");
+        // Each call prints with the palette it is given
+        let colored = print::term(prog[0].term(), print::Palette::new(true));
+        assert!(colored.contains('\x1b'));
+        assert_eq!(
+            print::term(prog[0].term(), print::Palette::new(false)),
+            printed
+        );
+    }
+    #[test]
+    fn test_print_minimal_parens() {
+        let cases = [
+            ("((f x) y);", "f x y"),
+            ("λx. (λy. y);", "λx. λy. y"),
+            ("f (g x);", "f (g x)"),
+            ("(λx. x) y;", "(λx. x) y"),
+            ("f (λx. x);", "f (λx. x)"),
+            ("λf. λx. (f (f x));", "λf. λx. f (f x)"),
+            ("(((f x) (g y)) z);", "f x (g y) z"),
+        ];
+        for (input, expected) in cases {
+            let prog = parse_prog(input);
+            assert_eq!(
+                print::term(prog[0].term(), &PLAIN),
+                expected,
+                "printing {}",
+                input
+            );
+        }
+    }
+    #[test]
+    fn test_abstraction_body_extends() {
+        // The body takes the whole application, instead of the abstraction being applied
+        let prog = parse_prog("λx. x y;");
+        let Term::Abstraction(_, _, body, _) = prog[0].term() else {
+            panic!("Expected an abstraction");
+        };
+        assert!(matches!(body.as_ref(), Term::Application(_, _, _)));
+        let prog = parse_prog("(λx. x) y;");
+        assert!(matches!(prog[0].term(), Term::Application(_, _, _)));
+        // Printed abstractions parse back to the same term
+        let printed = print::term(parse_prog("λf. λx. (f (f x));")[0].term(), &PLAIN);
+        assert_eq!(print::term(parse_prog(&printed)[0].term(), &PLAIN), printed);
+    }
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_emit_json() {
+        let prog = parse_prog("λx. x;");
+        let json = emit::to_json(prog[0].term()).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "Abstraction": ["x", null, { "Variable": ["x", null, [1, 5]] }, [1, 1]]
+            })
+        );
+        // The emitted JSON should round-trip back to the same term
+        let term: Term = emit::from_json(&json).unwrap();
+        assert_eq!(&term, prog[0].term());
+    }
+    #[test]
+    fn test_emit_dot() {
+        let prog = parse_prog("(λx. x) y;");
+        let dot = emit::to_dot(prog[0].term());
+        assert!(dot.starts_with("digraph term {"));
+        // @, λx, x and y
+        assert_eq!(dot.matches("[label=").count(), 4);
+        assert_eq!(dot.matches(" -> ").count(), 3);
+        for id in 0..4 {
+            assert_eq!(dot.matches(&format!("n{} [label=", id)).count(), 1);
+        }
+    }
+    #[test]
+    fn test_beta_step_normal_order() {
+        let env = HashMap::new();
+        let prog = parse_prog("(λx. x) ((λy. y) z);");
+        let term = prog[0].term();
+        // The outer redex is reduced before the argument
+        let first = beta_step(term, &env, HashSet::new()).unwrap();
+        assert_eq!(print::term(&first, &PLAIN), "(λy. y) z");
+        let second = beta_step(&first, &env, HashSet::new()).unwrap();
+        assert_eq!(print::term(&second, &PLAIN), "z");
+        assert_eq!(beta_step(&second, &env, HashSet::new()), None);
+        assert_eq!(
+            reduce_to_normal_form(term, &env, false, PRINT_NONE).unwrap(),
+            second
+        );
+    }
+
+    #[test]
+    fn test_beta_step_single_redex() {
+        let env = HashMap::new();
+        // Both sides of the application are redexes, only the left one may be reduced
+        let prog = parse_prog("((λx. x) a) ((λy. y) b);");
+        let first = beta_step(prog[0].term(), &env, HashSet::new()).unwrap();
+        assert_eq!(print::term(&first, &PLAIN), "a ((λy. y) b)");
+    }
+    #[test]
+    fn test_import() {
+        let mut env = HashMap::new();
+        eval_prog(
+            "import True, Not from std;".into(),
+            &mut env,
+            &mut Ctx::new(),
+            false,
+            PRINT_NONE,
+            Strategy::Normal,
+            false,
+        )
+        .unwrap();
+        assert!(env.contains_key("True"));
+        assert!(env.contains_key("Not"));
+        // Dependencies of imported definitions are not brought into scope
+        assert!(!env.contains_key("False"));
+        assert!(!env.contains_key("Succ"));
+        // `Not` still refers to the module's `False`
+        let prog = parse_prog("Not True;");
+        let result = eval_expr(&prog[0], &mut env, false, PRINT_NONE).unwrap();
+        let expected = parse_prog("λtrue. λfalse. false;");
+        assert_eq!(
+            print::term(&result, &PLAIN),
+            print::term(expected[0].term(), &PLAIN)
+        );
+    }
+
+    #[test]
+    fn test_import_missing() {
+        let mut env = HashMap::new();
+        let result = eval_prog(
+            "import True, Nope from std;".into(),
+            &mut env,
+            &mut Ctx::new(),
+            false,
+            PRINT_NONE,
+            Strategy::Normal,
+            false,
+        );
+        assert_eq!(result.unwrap_err(), "Module `std` has no definition `Nope`");
+        assert!(env.is_empty());
+    }
+    #[test]
+    fn test_import_type_error() {
+        // Imports of a program that does not type check are not kept
+        let mut env = HashMap::new();
+        let mut ctx = Ctx::new();
+        let result = eval_prog(
+            "import True from std; ((λx. λy. x) : A -> A);".into(),
+            &mut env,
+            &mut ctx,
+            false,
+            PRINT_NONE,
+            Strategy::Normal,
+            false,
+        );
+        assert!(result.is_err());
+        assert!(env.is_empty());
+        assert!(ctx.is_empty());
+    }
+    #[test]
+    fn test_substitute_avoids_capture() {
+        // (λx. x' y)[y := x] must not rename x to x', which is free in the body
+        let term = parse_prog("λx. (x' y);");
+        let value = parse_prog("x;");
+        let result = substitute(term[0].term(), "y", value[0].term());
+        assert_eq!(print::term(&result, &PLAIN), "λx''. x' x");
+        assert_eq!(
+            free_vars(&result),
+            HashSet::from(["x'".to_string(), "x".to_string()])
+        );
+
+        // (λx. λx'. y)[y := x']
+        let term = parse_prog("λx. λx'. y;");
+        let value = parse_prog("x';");
+        let result = substitute(term[0].term(), "y", value[0].term());
+        assert_eq!(print::term(&result, &PLAIN), "λx. λx''. x'");
+        assert_eq!(free_vars(&result), HashSet::from(["x'".to_string()]));
+    }
+    #[test]
+    fn test_parse_status() {
+        assert_eq!(parse_status("λx. x"), ParseStatus::Complete);
+        assert_eq!(parse_status("f x; g y;"), ParseStatus::Complete);
+        assert_eq!(parse_status(""), ParseStatus::Complete);
+        // Missing abstraction body
+        assert_eq!(parse_status("λx."), ParseStatus::Incomplete);
+        assert_eq!(parse_status("λx. "), ParseStatus::Incomplete);
+        // Unbalanced parentheses
+        assert_eq!(parse_status("(f (g x)"), ParseStatus::Incomplete);
+        assert_eq!(parse_status("x ="), ParseStatus::Incomplete);
+        assert_eq!(parse_status("f )"), ParseStatus::Invalid);
+        assert_eq!(parse_status("λ. x"), ParseStatus::Invalid);
+    }
+    #[test]
+    fn test_parse_prog_recover() {
+        let (prog, errors) =
+            parse_prog_recover("x = y;\nf );\nλx. x;\n-- a comment; with a semicolon\n. g;");
+        assert_eq!(prog.len(), 2);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors[0].line_col,
+            pest::error::LineColLocation::Pos((2, 3))
+        );
+        assert_eq!(
+            errors[1].line_col,
+            pest::error::LineColLocation::Pos((5, 1))
+        );
+        // Nothing is evaluated if any statement fails to parse
+        assert!(parse_prog("x = y;\nf );").is_empty());
+        let errors = try_parse_prog("x = y;\nf );\n. g;").unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(try_parse_prog("x = y;").unwrap().len(), 1);
+    }
+    #[test]
+    fn test_parse_prog_recover_lines() {
+        // Line breaks separate statements, so each broken statement is reported
+        let input = "A = x\nB = ) x\nC =\n  λy.\ny\nD = x )\n/// Doc\nE = A\n+ C";
+        let (prog, errors) = parse_prog_recover(input);
+        let positions: Vec<_> = errors.iter().map(|err| err.line_col.clone()).collect();
+        assert_eq!(
+            positions,
+            [
+                pest::error::LineColLocation::Pos((2, 5)),
+                pest::error::LineColLocation::Pos((6, 7))
+            ]
+        );
+        assert_eq!(prog.len(), 3);
+        // The same as with `;`
+        let (_, errors) = parse_prog_recover(&input.replace("\nB", ";\nB").replace("\nD", ";\nD"));
+        assert_eq!(errors.len(), 2);
+    }
+    #[test]
+    fn test_save_env_round_trip() {
+        let mut env = HashMap::new();
+        let input =
+            "I = λx. x; K = λx. λy. x; S = λx. λy. λz. x z (y z); T = λf: (A -> B) -> C. f;";
+        for expr in parse_prog(input) {
+            eval_expr(&expr, &mut env, false, PRINT_NONE).unwrap();
+        }
+        let path = std::env::temp_dir().join("tlc_test_save_env.lc");
+        std::fs::write(&path, print::env_program(&env)).unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!saved.contains('\x1b'));
+
+        let mut loaded = HashMap::new();
+        for expr in parse_prog(&saved) {
+            eval_expr(&expr, &mut loaded, false, PRINT_NONE).unwrap();
+        }
+        assert_eq!(loaded.len(), env.len());
+        for (name, term) in env.iter() {
+            assert_eq!(print::plain_term(&loaded[name]), print::plain_term(term));
+        }
+        // Parenthesized parameter types are kept intact
+        let Term::Abstraction(_, Some(Type::Abstraction(param, _)), _, _) = &loaded["T"] else {
+            panic!("Expected an annotated abstraction");
+        };
+        assert!(matches!(param.as_ref(), Type::Abstraction(_, _)));
+    }
+
+    #[test]
+    fn test_type_names_without_whitespace() {
+        let var = |name: &str| Rc::new(Type::Variable(name.to_string()));
+        let prog = parse_prog("λx : A  ->\tB. x;");
+        let Term::Abstraction(_, Some(ty), _, _) = prog[0].term() else {
+            panic!("Expected an annotated abstraction");
+        };
+        assert_eq!(ty, &Type::Abstraction(var("A"), var("B")));
+    }
+    #[test]
+    fn test_ty_err_expands_aliases() {
+        let mut ctx = Ctx::new();
+        for expr in
+            parse_prog("type Name = A -> B; type Both = Name -> Name; type Loop = Loop -> A;")
+        {
+            types::check_expr(&mut ctx, &expr).unwrap();
+        }
+        let err = TypeError::Mismatch {
+            expected: Type::Variable("Name".to_string()),
+            found: Type::Variable("C".to_string()),
+            info: LineInfo(1, 1),
+        };
+        assert_eq!(
+            print::ty_err(err, Some(&ctx), &PLAIN),
+            "Type error: expected Name (= A -> B) but found C at line 1 col 1"
+        );
+        // Nested aliases are expanded fully
+        let both = types::resolve_type(&ctx, &Type::Variable("Both".to_string()));
+        assert_eq!(print::r#type(&both, &PLAIN), "(A -> B) -> A -> B");
+        // Recursive aliases are only expanded once
+        let looped = types::resolve_type(&ctx, &Type::Variable("Loop".to_string()));
+        assert_eq!(print::r#type(&looped, &PLAIN), "Loop -> A");
+    }
+    #[test]
+    fn test_reduce_to_whnf() {
+        let mut env = HashMap::new();
+        // Redexes under a binder are left alone
+        let prog = parse_prog("λx. ((λy. y) z);");
+        let whnf = reduce_to_whnf(prog[0].term(), &env).unwrap();
+        assert_eq!(print::term(&whnf, &PLAIN), "λx. (λy. y) z");
+
+        let prog = parse_prog("(λx. x) a;");
+        assert_eq!(
+            print::term(&reduce_to_whnf(prog[0].term(), &env).unwrap(), &PLAIN),
+            "a"
+        );
+
+        // Arguments of a free variable are not reduced
+        let prog = parse_prog("f ((λx. x) a);");
+        assert_eq!(
+            print::term(&reduce_to_whnf(prog[0].term(), &env).unwrap(), &PLAIN),
+            "f ((λx. x) a)"
+        );
+
+        // Environment definitions in head position are inlined
+        for expr in parse_prog("K = λx. λy. x;") {
+            eval_expr(&expr, &mut env, false, PRINT_NONE).unwrap();
+        }
+        let prog = parse_prog("K a ((λz. z) b);");
+        assert_eq!(
+            print::term(&reduce_to_whnf(prog[0].term(), &env).unwrap(), &PLAIN),
+            "a"
+        );
+    }
+    #[test]
+    fn test_depth_exceeded() {
+        // f x x ... x, nested far deeper than the limit
+        let mut term = Term::var("f");
+        for _ in 0..DEFAULT_MAX_DEPTH * 2 {
+            term = Term::app(term, Term::var("x"));
+        }
+        assert_eq!(depth(&term), DEFAULT_MAX_DEPTH * 2 + 1);
+        let env = HashMap::new();
+        assert_eq!(
+            reduce_to_normal_form(&term, &env, false, PRINT_NONE),
+            Err(EvalError::DepthExceeded(DEFAULT_MAX_DEPTH))
+        );
+        assert_eq!(
+            reduce_to_whnf(&term, &env),
+            Err(EvalError::DepthExceeded(DEFAULT_MAX_DEPTH))
+        );
+        assert_eq!(
+            eval_expr(&Expr::Term(term), &mut HashMap::new(), false, PRINT_NONE),
+            Err(EvalError::DepthExceeded(DEFAULT_MAX_DEPTH))
+        );
+    }
+    #[test]
+    fn test_substitute_large_value() {
+        // A value with many free variables, one of which clashes with every binder
+        let value = (0..200).fold("x".to_string(), |acc, i| format!("{} v{}", acc, i));
+        let value = parse_prog(&format!("{};", value));
+        let term = parse_prog("λx. λy. λx. z x y;");
+        let result = substitute(term[0].term(), "z", value[0].term());
+        let expected = parse_prog(&format!(
+            "λx'. λy. λx'. ({}) x' y;",
+            print::term(value[0].term(), &PLAIN)
+        ));
+        assert_eq!(
+            print::term(&result, &PLAIN),
+            print::term(expected[0].term(), &PLAIN)
+        );
+        assert_eq!(free_vars(&result), free_vars(value[0].term()));
+    }
+    #[test]
+    fn test_is_normal_form() {
+        let normal = |s: &str, eta: bool| is_normal_form(parse_prog(s)[0].term(), eta);
+        assert!(normal("λx. x;", false));
+        assert!(!normal("(λx. x) y;", false));
+        assert!(normal("f (λx. x);", false));
+        // Redexes under binders and in arguments count too
+        assert!(!normal("λy. f ((λx. x) y);", false));
+        // η-redexes are only rejected when asked for
+        assert!(normal("λx. f x;", false));
+        assert!(!normal("λx. f x;", true));
+        assert!(normal("λx. x x;", true));
+    }
+    #[test]
+    fn test_expr_keeps_assignments() {
+        static OUTPUT: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        let args = |expr: &str| {
+            ["lambda", "--expr", expr]
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+        let mut env = HashMap::new();
+        crate::expr(
+            &args("A = λx. x; B = A; B"),
+            &mut env,
+            &mut Ctx::new(),
+            false,
+            Printer {
+                out: |t, _| OUTPUT.lock().unwrap().push(t),
+                palette: &PLAIN,
+            },
+            Strategy::Normal,
+            false,
+        );
+        assert!(env.contains_key("A") && env.contains_key("B"));
+        // Standard library definitions are available once loaded
+        let mut env = HashMap::new();
+        load_std(&mut env).unwrap();
+        crate::expr(
+            &args("A = λx. x; Not (A True)"),
+            &mut env,
+            &mut Ctx::new(),
+            false,
+            Printer {
+                out: |t, _| OUTPUT.lock().unwrap().push(t),
+                palette: &PLAIN,
+            },
+            Strategy::Normal,
+            false,
+        );
+        assert_eq!(*OUTPUT.lock().unwrap(), ["λx. x", "λtrue. λfalse. false"]);
+    }
+    #[test]
+    fn test_fix_unfolds() {
+        let env = HashMap::new();
+        let prog = parse_prog("fix f; g (fix λx. x); fixed; fix (λf. λn. n) y;");
+        assert!(matches!(prog[0].term(), Term::Fix(_, _)));
+        // Unfolding is a single step: fix f → f (fix f)
+        let unfolded = beta_step(prog[0].term(), &env, HashSet::new()).unwrap();
+        assert_eq!(print::term(&unfolded, &PLAIN), "f (fix f)");
+        assert_eq!(print::term(prog[1].term(), &PLAIN), "g (fix (λx. x))");
+        // The keyword is not a prefix of identifiers
+        assert!(matches!(prog[2].term(), Term::Variable(name, _, _) if name == "fixed"));
+        let result = reduce_to_normal_form(prog[3].term(), &env, false, PRINT_NONE).unwrap();
+        assert_eq!(print::term(&result, &PLAIN), "y");
+    }
+    #[test]
+    fn test_fix_typing() {
+        let mut ctx = Ctx::new();
+        // Synthesis: fix : (A -> A) -> A
+        let prog = parse_prog("fix λx. x; fix λx. λy. x;");
+        let ty = types::check_expr(&mut ctx, &prog[0]).unwrap();
+        assert_eq!(*ty, Type::Variable("x".to_string()));
+        assert!(matches!(
+            types::check_expr(&mut ctx, &prog[1]),
+            Err(TypeError::Mismatch { .. })
+        ));
+        // Checking: a recursive definition against its annotation
+        let prog = parse_prog("type N = * -> *; Loop : N = fix λf. λn. f n;");
+        for expr in &prog {
+            types::check_expr(&mut ctx, expr).unwrap();
+        }
+        let prog = parse_prog("Bad : B = fix λf. λx. x;");
+        assert!(types::check_expr(&mut ctx, &prog[0]).is_err());
+        // Binders annotated with named types, in both directions
+        let prog = parse_prog(
+            "Id : A -> A = fix λf : A -> A. λx : A. x; fix λf : A -> A. λx : A. f x; fix λf : A -> B. λx : A. x;",
+        );
+        types::check_expr(&mut ctx, &prog[0]).unwrap();
+        let ty = types::check_expr(&mut ctx, &prog[1]).unwrap();
+        assert_eq!(print::r#type(&ty, &PLAIN), "A -> A");
+        assert!(matches!(
+            types::check_expr(&mut ctx, &prog[2]),
+            Err(TypeError::Mismatch { .. })
+        ));
+    }
+    #[test]
+    fn test_unit() {
+        let prog = parse_prog("(); f ( ) x; U : () -> () = λu. u;");
+        assert!(matches!(prog[0].term(), Term::Unit(_)));
+        assert_eq!(print::term(prog[1].term(), &PLAIN), "f () x");
+        let Expr::Assignment(_, Some(ty), _, _) = &prog[2] else {
+            panic!("Expected an annotated assignment");
+        };
+        assert_eq!(print::r#type(ty, &PLAIN), "() -> ()");
+        // The unit value synthesizes the unit type
+        let mut ctx = Ctx::new();
+        let ty = types::check_expr(&mut ctx, &prog[0]).unwrap();
+        assert_eq!(*ty, Type::Unit);
+        types::check_expr(&mut ctx, &prog[2]).unwrap();
+        let prog = parse_prog("U (); U (λx. x);");
+        assert_eq!(*types::check_expr(&mut ctx, &prog[0]).unwrap(), Type::Unit);
+        assert!(types::check_expr(&mut ctx, &prog[1]).is_err());
+        // It is already a normal form
+        let unit = Term::Unit(LineInfo(1, 1));
+        assert!(is_normal_form(&unit, true));
+        assert_eq!(step(&unit, &HashMap::new()), None);
+    }
+    #[test]
+    fn test_beta_eta_equal() {
+        let terms = |s: &str| {
+            parse_prog(s)
+                .into_iter()
+                .map(|e| e.term().clone())
+                .collect::<Vec<_>>()
+        };
+        let mut env = HashMap::new();
+        let t = terms("λx. f x; f; λy. f y; λx. x f;");
+        assert!(beta_eta_equal(&t[0], &t[1], &env).unwrap());
+        assert!(alpha_eq(&t[0], &t[2]));
+        assert!(!beta_eta_equal(&t[3], &t[1], &env).unwrap());
+        load_std(&mut env).unwrap();
+        let t = terms("Succ 0; 1; 2;");
+        assert!(beta_eta_equal(&t[0], &t[1], &env).unwrap());
+        assert!(!beta_eta_equal(&t[0], &t[2], &env).unwrap());
+        // A term without normal form makes the comparison inconclusive
+        let t = terms("(λx. x x) (λx. x x); y;");
+        assert!(matches!(
+            beta_eta_equal(&t[0], &t[1], &env),
+            Err(EvalError::StepLimit(_))
+        ));
+    }
+    #[test]
+    fn test_timing() {
+        let mut env = HashMap::new();
+        let prog = parse_prog("I = λx. x; I (I y);");
+        let (_, timing) = eval_expr_timed(&prog[0], &mut env, false, PRINT_NONE).unwrap();
+        assert_eq!(timing.steps, 0);
+        let (term, timing) = eval_expr_timed(&prog[1], &mut env, false, PRINT_NONE).unwrap();
+        assert_eq!(print::term(&term, &PLAIN), "y");
+        // Variables are inlined before reducing, leaving two β-reductions
+        assert_eq!(timing.steps, 2);
+        assert!(print::timing(&timing, &PLAIN).ends_with("in 2 steps"));
+    }
+    #[test]
+    fn test_native_numerals() {
+        let mut env = HashMap::new();
+        load_std(&mut env).unwrap();
+        let native = |term: &Term| {
+            let mut term = inline_vars(term, &env);
+            while let Some(next) = native_step(&term, &env) {
+                term = next;
+            }
+            term
+        };
+        for m in 0..4 {
+            for n in 0..4 {
+                for op in ["Add", "Mul"] {
+                    let prog = parse_prog(&format!("{} {} {};", op, m, n));
+                    let pure = reduce_to_normal_form(prog[0].term(), &env, false, PRINT_NONE);
+                    let fast = native(prog[0].term());
+                    assert!(alpha_eq(&pure.unwrap(), &fast), "{} {} {}", op, m, n);
+                }
+            }
+        }
+        let prog = parse_prog("Succ (Mul (Add 1 2) 3); Add 100 200; Add x 1;");
+        assert_eq!(decode_numeral(&native(prog[0].term()), &env), Some(10));
+        assert_eq!(decode_numeral(&native(prog[1].term()), &env), Some(300));
+        // Anything that is not a numeral is left to β-reduction
+        assert_eq!(native_step(prog[2].term(), &env), None);
+    }
+    #[test]
+    fn test_shadow_warnings() {
+        let prog = parse_prog("λx. λx. x;\nF = λy. λz. (λy. y) z;");
+        let shadowed = lint::shadowed(&prog);
+        assert_eq!(
+            shadowed,
+            [
+                ("x".to_string(), LineInfo(1, 5)),
+                ("y".to_string(), LineInfo(2, 14))
+            ]
+        );
+        assert_eq!(
+            print::shadow_warning(&shadowed[0].0, &shadowed[0].1, &PLAIN),
+            "Warning: `x` shadows an enclosing binder at line 1 col 5"
+        );
+        // Distinct binders and binders in separate scopes are fine
+        let prog = parse_prog("λx. λy. x y; (λx. x) (λx. x);");
+        assert!(lint::shadowed(&prog).is_empty());
+    }
+    #[test]
+    fn test_print_width() {
+        let prog = parse_prog("f x; Fold (λacc. λx. Add acc (Mul x x)) initial (Range 1 10);");
+        // Terms that fit stay on one line
+        assert_eq!(print::term_width(prog[0].term(), 20, &PLAIN), "f x");
+        assert_eq!(
+            print::term_width(prog[1].term(), 31, &PLAIN),
+            "Fold\n  (λacc. λx. Add acc (Mul x x))\n  initial\n  (Range 1 10)"
+        );
+        let wrapped = print::term_width(prog[1].term(), 30, &PLAIN);
+        assert!(wrapped.lines().all(|line| line.chars().count() <= 30));
+        // Wrapped output still parses to the same term
+        let reparsed = parse_prog(&format!("{};", wrapped));
+        assert_eq!(
+            print::term(reparsed[0].term(), &PLAIN),
+            print::term(prog[1].term(), &PLAIN)
+        );
+        let narrow = print::term_width(prog[1].term(), 20, &PLAIN);
+        assert!(narrow.contains("(λacc.\n"));
+    }
+    #[test]
+    fn test_persistent_ctx() {
+        let mut env = HashMap::new();
+        let mut ctx = Ctx::new();
+        let run = |input: &str, env: &mut HashMap<String, Term>, ctx: &mut Ctx| {
+            eval_prog(
+                input.into(),
+                env,
+                ctx,
+                false,
+                PRINT_NONE,
+                Strategy::Normal,
+                false,
+            )
+        };
+        run(
+            "type B = * -> * -> *; T : B = λa. λb. a;",
+            &mut env,
+            &mut ctx,
+        )
+        .unwrap();
+        // Types of earlier inputs are kept for later ones
+        run("F : B = T;", &mut env, &mut ctx).unwrap();
+        let ty = type_of(&parse_prog("F;")[0], &env, &ctx).unwrap();
+        assert_eq!(print::r#type(&ty, &PLAIN), "* -> * -> *");
+        assert!(print::ctx(&ctx, &PLAIN).contains("  T : * -> * -> *,"));
+        // A program that does not type check leaves the context alone
+        assert!(run("type N = A -> A; G : N = λx. λy. x;", &mut env, &mut ctx).is_err());
+        assert!(!ctx.contains_key("N") && !ctx.contains_key("G"));
+        // Definitions without a known type are still in scope
+        env.insert("H".to_string(), parse_prog("λx. x;")[0].term().clone());
+        let ty = type_of(&parse_prog("H;")[0], &env, &ctx).unwrap();
+        assert_eq!(*ty, Type::Any);
+    }
+    #[test]
+    fn test_ascription() {
+        let prog = parse_prog("((λx. x) : A -> A); ((λx. λy. x) : A -> A); f ((g x) : B);");
+        assert!(matches!(prog[0].term(), Term::Ascription(_, _, _)));
+        assert_eq!(print::term(prog[0].term(), &PLAIN), "(λx. x : A -> A)");
+        assert_eq!(print::term(prog[2].term(), &PLAIN), "f (g x : B)");
+        // The annotation switches inference into checking mode
+        let mut ctx = Ctx::new();
+        let ty = types::check_expr(&mut ctx, &prog[0]).unwrap();
+        assert_eq!(print::r#type(&ty, &PLAIN), "A -> A");
+        assert!(matches!(
+            types::check_expr(&mut ctx, &prog[1]),
+            Err(TypeError::Mismatch { .. })
+        ));
+        // Annotations don't change evaluation
+        let prog = parse_prog("(λx. x : A -> A) y;");
+        let result = reduce_to_normal_form(prog[0].term(), &HashMap::new(), false, PRINT_NONE);
+        assert_eq!(print::term(&result.unwrap(), &PLAIN), "y");
+    }
+    /// Steps after which a reduction is considered divergent in tests
+    const TEST_MAX_STEPS: usize = 200;
+
+    /// Assert that `input` reduces to `expected` under `strategy`, up to α-equivalence.
+    /// An `expected` of `None` means the reduction must not terminate within `TEST_MAX_STEPS`.
+    fn assert_reduces(input: &str, strategy: Strategy, expected: Option<&str>) {
+        let env = HashMap::new();
+        let prog = parse_prog(input);
+        let result = reduce_with_limit(prog[0].term(), &env, strategy, TEST_MAX_STEPS);
+        match (result, expected) {
+            (Ok(result), Some(expected)) => {
+                let expected = parse_prog(expected);
+                assert!(
+                    alpha_eq(&result, expected[0].term()),
+                    "{} reduced to {} under {:?}, expected {}",
+                    input,
+                    print::term(&result, &PLAIN),
+                    strategy,
+                    print::term(expected[0].term(), &PLAIN)
+                );
+            }
+            (Err(EvalError::StepLimit(_)), None) => {}
+            (result, expected) => panic!(
+                "{} reduced to {:?} under {:?}, expected {:?}",
+                input,
+                result.map(|t| print::term(&t, &PLAIN)),
+                strategy,
+                expected
+            ),
+        }
+    }
+
+    #[test]
+    fn test_strategies() {
+        use Strategy::{Lazy, Normal, Whnf};
+        const OMEGA: &str = "((λx. x x) (λx. x x))";
+        let table: &[(String, Strategy, Option<&str>)] = &[
+            ("(λx. x) y".into(), Normal, Some("y")),
+            ("(λx. x) y".into(), Whnf, Some("y")),
+            ("(λx. x) y".into(), Lazy, Some("y")),
+            // Reductions under binders only happen in normal order
+            ("λz. (λx. x) z".into(), Normal, Some("λz. z")),
+            ("λz. (λx. x) z".into(), Whnf, Some("λz. (λx. x) z")),
+            ("λz. (λx. x) z".into(), Lazy, Some("λz. (λx. x) z")),
+            ("f ((λx. x) y)".into(), Normal, Some("f y")),
+            ("f ((λx. x) y)".into(), Whnf, Some("f ((λx. x) y)")),
+            ("f ((λx. x) y)".into(), Lazy, Some("f ((λx. x) y)")),
+            // Arguments are substituted when reading back the result, avoiding capture
+            ("(λx. λy. x y) y".into(), Lazy, Some("λy'. y y'")),
+            // A discarded divergent argument is never reduced, applicative order would diverge
+            (format!("(λx. λy. y) {} z", OMEGA), Normal, Some("z")),
+            (format!("(λx. λy. y) {} z", OMEGA), Whnf, Some("z")),
+            (format!("(λx. λy. y) {} z", OMEGA), Lazy, Some("z")),
+            (
+                format!("λz. {}", OMEGA),
+                Whnf,
+                Some(&*format!("λz. {}", OMEGA)),
+            ),
+            // Terms without a normal form diverge
+            (OMEGA.into(), Normal, None),
+            (OMEGA.into(), Whnf, None),
+            (OMEGA.into(), Lazy, None),
+            (format!("λz. {}", OMEGA), Normal, None),
+            ("fix f".into(), Normal, None),
+            // Unfolding stops once a free variable is in head position
+            ("fix f".into(), Whnf, Some("f (fix f)")),
+            ("fix f".into(), Lazy, Some("f (fix f)")),
+        ];
+        for (input, strategy, expected) in table {
+            assert_reduces(&format!("{};", input), *strategy, *expected);
+        }
+    }
+    #[test]
+    fn test_reduction_observer() {
+        /// Records every step of a reduction
+        struct Trace(Vec<(usize, Term)>);
+        impl ReductionObserver for Trace {
+            fn on_step(&mut self, step: usize, term: &Term) {
+                self.0.push((step, term.clone()));
+            }
+        }
+        let prog = parse_prog("(λx. λy. x) a b;");
+        let mut trace = Trace(Vec::new());
+        let (result, timing) =
+            reduce_to_normal_form_observed(prog[0].term(), &HashMap::new(), &mut trace).unwrap();
+        assert_eq!(print::term(&result, &PLAIN), "a");
+        assert_eq!(timing.steps, 2);
+        let trace: Vec<(usize, String)> = trace
+            .0
+            .iter()
+            .map(|(step, term)| (*step, print::term(term, &PLAIN)))
+            .collect();
+        assert_eq!(trace, [(1, "(λy. a) b".to_string()), (2, "a".to_string())]);
+    }
+    #[test]
+    fn test_term_builders() {
+        // λf. λx. f (f x)
+        let two = Term::abs(
+            "f",
+            Term::abs(
+                "x",
+                Term::app(Term::var("f"), Term::app(Term::var("f"), Term::var("x"))),
+            ),
+        );
+        let parsed = parse_prog("λf. λx. f (f x);");
+        assert!(alpha_eq(&two, parsed[0].term()));
+        assert_eq!(two.info(), &LineInfo(0, 0));
+        assert_eq!(
+            Type::arrow(Type::Variable("A".to_string()), Type::Unit).to_string(),
+            "(A -> ())"
+        );
+    }
+    #[test]
+    fn test_fresh_names() {
+        // Substituting x x0 for z under λx. λy. ... must rename x, and x0 is taken
+        let term = parse_prog("λx. λy. z x y x';");
+        let value = parse_prog("x x0;");
+        let rename = |scheme| {
+            print::term(
+                &substitute_fresh(term[0].term(), "z", value[0].term(), scheme),
+                &PLAIN,
+            )
+        };
+        assert_eq!(rename(FreshNames::Primes), "λx''. λy. x x0 x'' y x'");
+        assert_eq!(rename(FreshNames::Numbered), "λx1. λy. x x0 x1 y x'");
+        assert_eq!(
+            FreshNames::from_name("numbered"),
+            Some(FreshNames::Numbered)
+        );
+        assert_eq!(FreshNames::from_name("subscript"), None);
+    }
+    #[test]
+    fn test_strict_unbound() {
+        let mut env = HashMap::new();
+        let prog = parse_prog("I = λx. x; I (λy. Foo y a);");
+        eval_expr(&prog[0], &mut env, false, PRINT_NONE).unwrap();
+        let term = prog[1].term();
+        // Without strict mode the misspelled name is left stuck in the result
+        assert_eq!(
+            print::term(
+                &eval_expr(&prog[1], &mut env, false, PRINT_NONE).unwrap(),
+                &PLAIN
+            ),
+            "λy. Foo y a"
+        );
+        let unbound: Vec<String> = unbound_vars(term, &env)
+            .into_iter()
+            .map(|(n, _)| n)
+            .collect();
+        assert_eq!(unbound, ["Foo", "a"]);
+        assert_eq!(
+            check_bound(term, &env),
+            Err(EvalError::Unbound("Foo".to_string(), LineInfo(1, 19)))
+        );
+        assert_eq!(
+            check_bound(term, &env).unwrap_err().to_string(),
+            "Evaluation error: unbound variable `Foo` at line 1 col 19"
+        );
+    }
+    #[test]
+    fn test_pragmas() {
+        static OUTPUT: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        let prog = parse_prog("#max-steps 5000;\n#strategy  whnf -- comment\n#verbose;");
+        assert_eq!(
+            prog,
+            [
+                Expr::Pragma("max-steps".to_string(), "5000".to_string()),
+                Expr::Pragma("strategy".to_string(), "whnf".to_string()),
+                Expr::Pragma("verbose".to_string(), String::new()),
+            ]
+        );
+        let k = parse_prog("(λx. λy. x) (λa. a) (λb. b);");
+        assert_eq!(
+            reduce_observed(
+                k[0].term(),
+                &HashMap::new(),
+                Strategy::Normal,
+                Some(1),
+                &mut ()
+            ),
+            Err(EvalError::StepLimit(1))
+        );
+        // Definitions from the environment are typed as `*`, so K I I type checks
+        let mut env = HashMap::new();
+        for expr in parse_prog("K = λx. λy. x; I = λa. a;") {
+            eval_expr(&expr, &mut env, false, PRINT_NONE).unwrap();
+        }
+        let run = |input: &str| {
+            OUTPUT.lock().unwrap().clear();
+            eval_prog(
+                input.to_string(),
+                &mut env.clone(),
+                &mut Ctx::new(),
+                false,
+                Printer {
+                    out: |t, _| OUTPUT.lock().unwrap().push(t),
+                    palette: &PLAIN,
+                },
+                Strategy::Normal,
+                false,
+            )
+            .unwrap_or_else(|err| OUTPUT.lock().unwrap().push(err));
+            OUTPUT.lock().unwrap().clone()
+        };
+        // Reducing K I I takes two steps
+        assert_eq!(run("#max-steps 2; K I I;"), ["λa. a"]);
+        assert_eq!(
+            run("#max-steps 1; K I I;"),
+            ["Evaluation error: no normal form within 1 steps"]
+        );
+        assert_eq!(run("#strategy whnf; K (λy. I y);"), ["λy. λy. (λa. a) y"]);
+        assert_eq!(run("K (λy. I y);"), ["λy. λy. y"]);
+        // Unknown pragmas and invalid values are ignored with a warning
+        assert_eq!(
+            run("#colour red; #max-steps lots; λx. x;"),
+            [
+                "Warning: ignoring pragma `#colour red`",
+                "Warning: ignoring pragma `#max-steps lots`",
+                "λx. x"
+            ]
+        );
+    }
+    #[test]
+    fn test_debruijn() {
+        use Nameless::*;
+        let prog = parse_prog("λx. λy. x (y z);");
+        let nameless = debruijn::to_nameless(prog[0].term());
+        assert_eq!(
+            nameless,
+            Abs(
+                "x".to_string(),
+                Box::new(Abs(
+                    "y".to_string(),
+                    Box::new(App(
+                        Box::new(Var(1)),
+                        Box::new(App(Box::new(Var(0)), Box::new(Free("z".to_string()))))
+                    ))
+                ))
+            )
+        );
+        assert_eq!(
+            print::term(&debruijn::from_nameless(&nameless), &PLAIN),
+            "λx. λy. x (y z)"
+        );
+        // Both evaluators agree up to α-equivalence
+        let mut env = HashMap::new();
+        load_std(&mut env).unwrap();
+        let cases = [
+            "Mul 3 4;",
+            "Add 2 (Succ 3);",
+            "Not (IsZero 0);",
+            "Fst (Pair a b);",
+            "(λx. λy. x) y;",
+            "(λx. λy. λz. x y z) (λa. λb. y);",
+        ];
+        for input in cases {
+            let prog = parse_prog(input);
+            let term = inline_vars(prog[0].term(), &env);
+            let named = reduce_with_limit(&term, &env, Strategy::Normal, TEST_MAX_STEPS).unwrap();
+            let (nameless, _) = debruijn::reduce_to_normal_form_timed(
+                &term,
+                &env,
+                Some(TEST_MAX_STEPS),
+                &Options::default(),
+            )
+            .unwrap();
+            assert!(
+                alpha_eq(&named, &nameless),
+                "{}: {} is not {}",
+                input,
+                print::term(&named, &PLAIN),
+                print::term(&nameless, &PLAIN)
+            );
+        }
+        // Binders are renamed when converting back would capture a free variable
+        let prog = parse_prog("(λx. λy. x) y;");
+        let (result, _) =
+            debruijn::reduce_to_normal_form_timed(prog[0].term(), &env, None, &Options::default())
+                .unwrap();
+        assert_eq!(print::term(&result, &PLAIN), "λy'. y");
+        let omega = parse_prog("(λx. x x) (λx. x x);");
+        assert_eq!(
+            debruijn::reduce_to_normal_form_timed(
+                omega[0].term(),
+                &env,
+                Some(10),
+                &Options::default()
+            ),
+            Err(EvalError::StepLimit(10))
+        );
+    }
+    /// Compare named and nameless reduction of numeral arithmetic,
+    /// run with `cargo test --release bench_debruijn -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_debruijn() {
+        let mut env = HashMap::new();
+        load_std(&mut env).unwrap();
+        let prog = parse_prog("Mul 5 (Add 10 15);");
+        let term = inline_vars(prog[0].term(), &env);
+        let (named, named_timing) =
+            reduce_to_normal_form_timed(&term, &env, false, PRINT_NONE).unwrap();
+        let (nameless, nameless_timing) =
+            debruijn::reduce_to_normal_form_timed(&term, &env, None, &Options::default()).unwrap();
+        assert!(alpha_eq(&named, &nameless));
+        println!(
+            "named: {:?} in {} steps, de Bruijn: {:?} in {} steps",
+            named_timing.elapsed,
+            named_timing.steps,
+            nameless_timing.elapsed,
+            nameless_timing.steps
+        );
+    }
+    #[test]
+    fn test_print_program() {
+        let input = "#max-steps 100;\nimport True, False from std;\ntype Fn = (* -> *) -> *;\nI : * -> * = λx. x;\nK = λx. λy. x;\nK (I ()) (λf : Fn. f);\n";
+        let prog = parse_prog(input);
+        assert_eq!(print::program(&prog, &PLAIN), input);
+        assert_eq!(print::expr(&prog[3], &PLAIN), "I : * -> * = λx. x");
+        // Printing the parsed output again gives the same program
+        let reparsed = parse_prog(&print::plain_program(&prog));
+        assert_eq!(reparsed.len(), prog.len());
+        assert_eq!(print::program(&reparsed, &PLAIN), input);
+        assert!(alpha_eq(reparsed[5].term(), prog[5].term()));
+    }
+    #[test]
+    fn test_mutual_recursion() {
+        // Both bodies refer to the other binding, the second one before it is defined
+        let mut prog = parse_prog(
+            "IsEven : Nat -> Bool = λn. IsZero n True (IsOdd (Pred n));
+            IsOdd : Nat -> Bool = λn. IsZero n False (IsEven (Pred n));
+            type Nat = * -> *;
+            type Bool = * -> * -> *;",
+        );
+        let mut ctx = Ctx::new();
+        for name in ["IsZero", "True", "False", "Pred"] {
+            ctx.insert(name.to_string(), Rc::new(Type::Any));
+        }
+        types::check_program(&mut ctx, &mut prog).unwrap();
+        assert_eq!(ctx["IsOdd"].to_string(), "((* -> *) -> (* -> (* -> *)))");
+        // Forward references to unannotated bindings are still unbound
+        let mut prog = parse_prog("F = λn. G n; G = λn. n;");
+        assert!(matches!(
+            types::check_program(&mut Ctx::new(), &mut prog),
+            Err(TypeError::Unbound(name, _)) if name == "G"
+        ));
+    }
+    #[test]
+    fn test_type_diff() {
+        let var = |name: &str| Type::Variable(name.to_string());
+        let strip = |s: &str| {
+            let mut plain = String::new();
+            let mut chars = s.chars();
+            while let Some(c) = chars.next() {
+                if c == '\x1b' {
+                    chars.by_ref().find(|c| *c == 'm');
+                } else {
+                    plain.push(c);
+                }
+            }
+            plain
+        };
+        let (red, dim, reset) = (
+            print::COLORED.red,
+            print::COLORED.dark_gray,
+            print::COLORED.reset,
+        );
+        let expected = Type::arrow(var("A"), var("B"));
+        let found = Type::arrow(var("A"), var("C"));
+        let (e, f) = print::type_diff(&expected, &found, &print::COLORED);
+        assert_eq!(
+            (strip(&e), strip(&f)),
+            ("A -> B".to_string(), "A -> C".to_string())
+        );
+        // Only the differing result is highlighted, the shared parameter is dimmed
+        assert!(f.contains(&format!("{red}C{reset}")));
+        assert!(f.contains(&format!("{dim}A{reset}")));
+        assert!(!f.contains(&format!("{red}A")));
+        // Parameters that are functions keep their parentheses
+        let expected = Type::arrow(Type::arrow(var("A"), var("B")), var("A"));
+        let found = Type::arrow(Type::arrow(var("A"), Type::Unit), var("A"));
+        let (e, f) = print::type_diff(&expected, &found, &print::COLORED);
+        assert_eq!(strip(&e), "(A -> B) -> A");
+        assert_eq!(strip(&f), "(A -> ()) -> A");
+        assert!(f.contains(&format!("{red}(){reset}")));
+        // Without colors the diff is the plain types
+        let (e, f) = print::type_diff(&expected, &found, &print::PLAIN);
+        assert_eq!(
+            (e.as_str(), f.as_str()),
+            ("(A -> B) -> A", "(A -> ()) -> A")
+        );
+    }
+    #[test]
+    fn test_parse_term_str() {
+        let term = parse_term_str("λx. x").unwrap();
+        assert!(alpha_eq(&term, &Term::abs("x", Term::var("x"))));
+        assert_eq!(
+            print::term(&parse_term_str(" f (g x) -- apply\n").unwrap(), &PLAIN),
+            "f (g x)"
+        );
+        // Trailing input, statements and assignments are not a single term
+        assert!(parse_term_str("x y z extra; stuff").is_err());
+        assert!(parse_term_str("λx. x;").is_err());
+        assert!(parse_term_str("I = λx. x").is_err());
+        assert!(parse_term_str("").is_err());
+    }
+    #[test]
+    fn test_reduction_trace() {
+        let env = HashMap::new();
+        let prog = parse_prog("a ((λx. x) b) ((λy. y) c);");
+        let (result, trace) =
+            reduce_with_trace(prog[0].term(), &env, Strategy::Normal, None).unwrap();
+        let (_, timing) =
+            reduce_to_normal_form_timed(prog[0].term(), &env, false, PRINT_NONE).unwrap();
+        assert_eq!(trace.steps.len(), timing.steps);
+        assert_eq!(print::term(&result, &PLAIN), "a b c");
+        let steps: Vec<(usize, String, LineInfo)> = trace
+            .steps
+            .into_iter()
+            .map(|(step, term, location, _)| (step, print::term(&term, &PLAIN), location))
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                (1, "a b ((λy. y) c)".to_string(), LineInfo(1, 4)),
+                (2, "a b c".to_string(), LineInfo(1, 16)),
+            ]
+        );
+        // Head reduction stops at the abstraction, without reducing its body
+        let prog = parse_prog("(λx. λy. x y) ((λz. z) w);");
+        let (_, trace) = reduce_with_trace(prog[0].term(), &env, Strategy::Whnf, None).unwrap();
+        assert_eq!(trace.steps.len(), 1);
+        assert_eq!(trace.steps[0].2, LineInfo(1, 1));
+    }
+    #[test]
+    fn test_repl_args() {
+        assert_eq!(crate::repl_args(""), None);
+        assert_eq!(crate::repl_args("  \t "), None);
+        // Commands are found after leading spaces, and extra spaces are ignored
+        assert_eq!(
+            crate::repl_args("  :env  clear"),
+            Some(vec![":env", "clear"])
+        );
+        assert_eq!(
+            crate::repl_args(":type λx. x"),
+            Some(vec![":type", "λx.", "x"])
+        );
+        assert_eq!(
+            crate::repl_args(" I = λx. x;"),
+            Some(vec!["I", "=", "λx.", "x;"])
+        );
+    }
+    #[test]
+    fn test_stdin_source() {
+        static OUTPUT: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        let stdin = "I : * -> * = λx. x;\nI I;\n".as_bytes();
+        for path in ["-", "--stdin"] {
+            let input = crate::source(path, stdin).unwrap();
+            assert_eq!(input.as_bytes(), stdin);
+            OUTPUT.lock().unwrap().clear();
+            eval_prog(
+                input,
+                &mut HashMap::new(),
+                &mut Ctx::new(),
+                false,
+                Printer {
+                    out: |t, _| OUTPUT.lock().unwrap().push(t),
+                    palette: &PLAIN,
+                },
+                Strategy::Normal,
+                false,
+            )
+            .unwrap();
+            assert_eq!(*OUTPUT.lock().unwrap(), ["λx. x"]);
+        }
+    }
+    #[test]
+    fn test_call_by_need() {
+        /// Counts the steps contracting a given redex
+        struct Count<'a>(&'a Term, usize);
+        impl ReductionObserver for Count<'_> {
+            fn on_step(&mut self, _step: usize, term: &Term) {
+                if alpha_eq(term, self.0) {
+                    self.1 += 1;
+                }
+            }
+        }
+        let env = HashMap::new();
+        // The argument is used three times, but only reduced the first time
+        let prog = parse_prog("(λx. x x x) ((λy. y) (λz. z)); (λy. y) (λz. z);");
+        let mut shared = Count(prog[1].term(), 0);
+        let (result, lazy) =
+            reduce_observed(prog[0].term(), &env, Strategy::Lazy, None, &mut shared).unwrap();
+        assert_eq!(print::term(&result, &PLAIN), "λz. z");
+        assert_eq!(shared.1, 1);
+        // Call-by-name reduces every copy of the argument
+        let (result, whnf) =
+            reduce_observed(prog[0].term(), &env, Strategy::Whnf, None, &mut ()).unwrap();
+        assert_eq!(print::term(&result, &PLAIN), "λz. z");
+        assert!(lazy.steps < whnf.steps);
+        // Definitions from the environment are shared as well
+        let mut env = HashMap::new();
+        for expr in parse_prog("A = (λy. y) (λz. z); P = λx. x x;") {
+            eval_expr(&expr, &mut env, false, PRINT_NONE).unwrap();
+        }
+        let prog = parse_prog("P A;");
+        let mut shared = Count(&env["A"], 0);
+        reduce_observed(prog[0].term(), &env, Strategy::Lazy, None, &mut shared).unwrap();
+        assert_eq!(shared.1, 1);
+    }
+
+    #[test]
+    fn test_parse_only() {
+        let output = crate::parse("I = λx. x;\r\n#strategy lazy\nI y;", &PLAIN).unwrap();
+        assert_eq!(output, "I = λx. x;\n#strategy lazy;\nI y;");
+        // Nothing is evaluated, so no undefined variable is reported either
+        let errors = crate::parse("x = y;\nf );\nundefined;", &PLAIN).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].line_col,
+            pest::error::LineColLocation::Pos((2, 3))
+        );
+    }
+
+    #[test]
+    fn test_spine_checking() {
+        let mut ctx = Ctx::new();
+        let decls = parse_prog("type B = () -> (); a : A = a; b : B = b; f : A -> B -> A = f;");
+        types::check_program(&mut ctx, &mut decls.clone()).unwrap();
+        // The function's parameter types are only known from its arguments
+        let prog = parse_prog("((λg. λx. λy. g x y) f a b : A); ((λx. λy. y) a b : B);");
+        for expr in &prog {
+            types::check_expr(&mut ctx, expr).unwrap();
+        }
+        // Without the ascription the function can't be synthesized
+        let prog = parse_prog("(λg. λx. λy. g x y) f a b;");
+        assert!(types::check_expr(&mut ctx, &prog[0]).is_err());
+        // Arguments are checked against the parameters along the spine
+        let prog = parse_prog("(f a b : A); (f b a : A); (f a b : B); (a b : A);");
+        assert!(types::check_expr(&mut ctx, &prog[0]).is_ok());
+        assert!(matches!(
+            types::check_expr(&mut ctx, &prog[1]),
+            Err(TypeError::Mismatch {
+                found: Type::Abstraction(_, _),
+                ..
+            })
+        ));
+        assert!(matches!(
+            types::check_expr(&mut ctx, &prog[2]),
+            Err(TypeError::Mismatch { .. })
+        ));
+        assert!(matches!(
+            types::check_expr(&mut ctx, &prog[3]),
+            Err(TypeError::NotAFunction(Type::Variable(_), LineInfo(1, 41)))
+        ));
+    }
+
+    #[test]
+    fn test_never_type() {
+        let prog =
+            parse_prog("Loop : ! = fix λx. x; E : ! -> ! = λe. e; Fail : () -> ! = λu. Loop;");
+        let Expr::Assignment(_, Some(ty), _, _) = &prog[1] else {
+            panic!("Expected an annotated assignment");
+        };
+        assert_eq!(print::r#type(ty, &PLAIN), "! -> !");
+        let mut ctx = Ctx::new();
+        types::check_program(&mut ctx, &mut prog.clone()).unwrap();
+        // A diverging term is accepted wherever a value is expected
+        let prog = parse_prog("type B = () -> (); (Loop : ()); (Loop : B); (Fail () : B); ((λu. u) Loop : ()); (λx. Loop : * -> B);");
+        for expr in &prog {
+            types::check_expr(&mut ctx, expr).unwrap();
+        }
+        // but a value is not accepted where a diverging term is expected
+        let prog = parse_prog("(() : !); (λx. x : ! -> !);");
+        assert!(matches!(
+            types::check_expr(&mut ctx, &prog[0]),
+            Err(TypeError::Mismatch {
+                expected: Type::Never,
+                found: Type::Unit,
+                ..
+            })
+        ));
+        types::check_expr(&mut ctx, &prog[1]).unwrap();
+        // Normal terms never synthesize `!`
+        let prog = parse_prog("(); λx. x; fix λx. x; (λx. x : () -> ()) ();");
+        for expr in &prog {
+            assert_ne!(*types::check_expr(&mut ctx, expr).unwrap(), Type::Never);
+        }
+    }
+
+    #[test]
+    fn test_run() {
+        use lamda_calc::{run, Config, Error};
+        let terms = run(
+            "I : () -> () = λx. x; import Add, 1, 2 from std; #strategy normal\nAdd 1 2; I ();",
+            &Config::default(),
+        )
+        .into_result()
+        .unwrap();
+        let printed: Vec<String> = terms.iter().map(print::plain_term).collect();
+        assert_eq!(printed, ["λf. λx. f (f (f x))", "()"]);
+        // The configuration is used unless a pragma overrides it
+        let config = Config {
+            strategy: Strategy::Whnf,
+            ..Config::default()
+        };
+        let terms = run("λz. (λx. x : * -> *) z;", &config)
+            .into_result()
+            .unwrap();
+        assert_eq!(print::plain_term(&terms[0]), "λz. (λx. x) z");
+        let terms = run("#strategy normal\nλz. (λx. x : * -> *) z;", &config)
+            .into_result()
+            .unwrap();
+        assert_eq!(print::plain_term(&terms[0]), "λz. z");
+        // Each stage reports its own errors
+        assert!(
+            matches!(run("f );", &Config::default()).error, Some(Error::Parse(errors)) if errors.len() == 1)
+        );
+        assert!(matches!(
+            run("import Nope from std;", &Config::default()).error,
+            Some(Error::Import(_))
+        ));
+        assert!(matches!(
+            run("U : () = λx. x;", &Config::default()).error,
+            Some(Error::Type(_))
+        ));
+        let config = Config {
+            max_steps: Some(3),
+            use_std: true,
+            ..Config::default()
+        };
+        assert!(matches!(
+            run("Mul 3 4;", &config).error,
+            Some(Error::Eval(EvalError::StepLimit(3)))
+        ));
+    }
+
+    #[test]
+    fn test_run_options() {
+        use lamda_calc::{run, Config, Error, FreshNames};
+        // Each run uses the options of its own configuration, not the interpreter's globals
+        let numerals = Config {
+            use_std: true,
+            native_numerals: true,
+            ..Config::default()
+        };
+        let std = Config {
+            use_std: true,
+            ..Config::default()
+        };
+        assert!(run("Add 40 1;", &numerals).error.is_none());
+        assert!(matches!(
+            run("Add 40 1;", &std).error,
+            Some(Error::Type(TypeError::Unbound(name, _))) if name == "40"
+        ));
+        let renamed = |fresh_names| {
+            let config = Config {
+                use_std: true,
+                fresh_names,
+                ..Config::default()
+            };
+            print::plain_term(&run("λfalse. True false;", &config).terms[0])
+        };
+        assert_eq!(renamed(FreshNames::Numbered), "λfalse. λfalse0. false");
+        assert_eq!(renamed(FreshNames::Primes), "λfalse. λfalse'. false");
+        let shallow = Config {
+            max_depth: 3,
+            ..Config::default()
+        };
+        assert!(matches!(
+            run("λa. λb. λc. λd. d;", &shallow).error,
+            Some(Error::Eval(EvalError::DepthExceeded(3)))
+        ));
+        let nameless = Config {
+            debruijn: true,
+            use_std: true,
+            ..Config::default()
+        };
+        let church = |config| print::plain_term(&run("Add 1 2;", config).terms[0]);
+        assert_eq!(church(&nameless), church(&std));
+        let by_evaluation = Config {
+            nbe: true,
+            use_std: true,
+            ..Config::default()
+        };
+        assert_eq!(church(&by_evaluation), church(&std));
+    }
+
+    #[test]
+    fn test_fuzz_regressions() {
+        // Printing a variable without a name, which the parser never produces
+        assert_eq!(print::plain_term(&Term::var("")), "");
+        // Nested terms and types used to be parsed again at every level, taking exponential time
+        for input in [
+            format!("{}x;", "λx. ".repeat(50)),
+            format!("{}x{};", "(".repeat(50), ")".repeat(50)),
+            format!("type A = {}*{};", "(".repeat(50), ")".repeat(50)),
+        ] {
+            let (prog, errors) = parse_prog_recover(&input);
+            assert!(errors.is_empty() && prog.len() == 1, "parsing {}", input);
+        }
+        // Input nested too deep to parse without overflowing the stack is an error
+        let (prog, errors) = parse_prog_recover(&format!("{}x", "λx. ".repeat(MAX_NESTING + 1)));
+        assert!(prog.is_empty());
+        assert!(errors[0].to_string().contains("nested deeper than"));
+        assert_eq!(
+            parse_status(&"(".repeat(MAX_NESTING + 1)),
+            ParseStatus::Invalid
+        );
+        assert!(parse_term_str(&"fix ".repeat(MAX_NESTING + 1)).is_err());
+        // so are terms the type checker and evaluator could not recurse into, even if they parse flat
+        let deep = vec!["x"; DEFAULT_MAX_DEPTH + 1].join(" ");
+        let (prog, errors) = parse_prog_recover(&format!("I = λx. x; {}; I;", deep));
+        assert_eq!(prog.len(), 2);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].line_col,
+            pest::error::LineColLocation::Span((1, 12), (1, 10013))
+        );
+        // Nesting closes with its parentheses and statements
+        let shallow = "(λx. λy. x) ;".repeat(MAX_NESTING);
+        assert_eq!(parse_prog_recover(&shallow).0.len(), MAX_NESTING);
+    }
+
+    #[test]
+    fn test_print_var_names() {
+        for name in ["", "1x", "2", "x'", "_"] {
+            assert_eq!(print::var(name, &PLAIN), name);
+        }
+        assert_eq!(print::term(&Term::abs("", Term::var("")), &PLAIN), "λ. ");
+        assert_eq!(
+            print::term(&Term::app(Term::var("1x"), Term::var("")), &PLAIN),
+            "1x "
+        );
+    }
+
+    #[test]
+    fn test_eta_long() {
+        let a = || Type::Variable("A".to_string());
+        let b = || Type::Variable("B".to_string());
+        let f = Term::var("f");
+        let long = to_eta_long(&f, &Type::arrow(a(), b()));
+        assert_eq!(print::plain_term(&long), "λx. f x");
+
+        // Arguments of function type are expanded as well
+        let ty = Type::arrow(Type::arrow(a(), b()), b());
+        assert_eq!(
+            print::plain_term(&to_eta_long(&f, &ty)),
+            "λx. f (λx'. x x')"
+        );
+
+        // Variables bound by abstractions take their type from the binder
+        let g = Term::abs("g", Term::var("g"));
+        let ty = Type::arrow(Type::arrow(a(), b()), Type::arrow(a(), b()));
+        assert_eq!(print::plain_term(&to_eta_long(&g, &ty)), "λg. λx. g x");
+
+        // The fresh variable does not capture free variables of the term
+        let fx = Term::app(Term::var("f"), Term::var("x"));
+        let long = to_eta_long(&fx, &Type::arrow(a(), b()));
+        assert_eq!(print::plain_term(&long), "λx'. f x x'");
+
+        // Base types leave the term unchanged
+        assert!(alpha_eq(&to_eta_long(&f, &a()), &f));
+    }
+
+    #[test]
+    fn test_numeric_literals() {
+        use lamda_calc::{run, Config};
+        let env = HashMap::new();
+        let size = |input: &str| decode_numeral(&parse_term_str(input).unwrap(), &env);
+        assert_eq!(size("0x10"), Some(16));
+        assert_eq!(size("1_024"), Some(1024));
+        assert_eq!(size("0xF_F"), Some(255));
+        assert_eq!(size("007"), Some(7));
+        // Literals are normalized, so they refer to the same numerals as plain ones
+        let terms = run("import 16 from std; 0x10;", &Config::default())
+            .into_result()
+            .unwrap();
+        assert_eq!(decode_numeral(&terms[0], &env), Some(16));
+        // Names starting with digits are still variables
+        assert_eq!(
+            print::plain_term(&parse_term_str("1x 2'").unwrap()),
+            "1x 2'"
+        );
+        assert!(parse_term_str("1__0").is_err());
+
+        let max = MAX_LITERAL.to_string();
+        assert_eq!(size(&max), Some(MAX_LITERAL));
+        for input in [
+            format!("{}0", max),
+            "0xFFFF_FFFF_FFFF_FFFF_FFFF".to_string(),
+        ] {
+            let message = parse_term_str(&input).unwrap_err().to_string();
+            assert!(
+                message.contains("Numeric literal is larger than"),
+                "{}",
+                message
+            );
+        }
+        let (prog, errors) = parse_prog_recover("9_999_999_999; 0x2A;");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(decode_numeral(prog[0].term(), &env), Some(42));
+    }
+
+    #[test]
+    fn test_no_inline() {
+        use lamda_calc::{run, Config};
+        let source = "I : * -> * = λx. x; K : * -> * -> * = λx. λy. x; K I (); (λy. y : * -> *) K;";
+        let printed = |config: &Config| -> Vec<String> {
+            let terms = run(source, config).into_result().unwrap();
+            terms.iter().map(print::plain_term).collect()
+        };
+        assert_eq!(printed(&Config::default()), ["λx. x", "λx. λy. x"]);
+        // Redexes are still reduced, but the definitions are left as free variables
+        let config = Config {
+            inline: false,
+            ..Config::default()
+        };
+        assert_eq!(printed(&config), ["K I ()", "K"]);
+    }
+
+    #[test]
+    fn test_infix_operators() {
+        use lamda_calc::{run, Config};
+        let cases = [
+            ("1 + 2 * 3", "Add 1 (Mul 2 3)"),
+            ("1 * 2 + 3", "Add (Mul 1 2) 3"),
+            ("1 + 2 + 3", "Add (Add 1 2) 3"),
+            ("(1 + 2) * 3", "Mul (Add 1 2) 3"),
+            ("Succ 1 * f x y", "Mul (Succ 1) (f x y)"),
+            ("a == b + c", "Eq a (Add b c)"),
+            ("λx. x + 1", "λx. Add x 1"),
+            ("(λx. x : * -> *) 2 * 2", "Mul ((λx. x : * -> *) 2) 2"),
+        ];
+        for (sugar, plain) in cases {
+            let sugar_term = parse_term_str(sugar).unwrap();
+            assert!(
+                alpha_eq(&sugar_term, &parse_term_str(plain).unwrap()),
+                "{} parsed as {}",
+                sugar,
+                print::plain_term(&sugar_term)
+            );
+        }
+        let config = Config {
+            use_std: true,
+            ..Config::default()
+        };
+        let terms = run("1 + 2 * 3;", &config).into_result().unwrap();
+        assert_eq!(decode_numeral(&terms[0], &HashMap::new()), Some(7));
+        assert!(parse_term_str("1 +").is_err());
+        assert!(parse_term_str("+ 1").is_err());
+    }
+
+    #[test]
+    fn test_check_bind() {
+        let check = |ctx: &mut Ctx, input: &str| types::check_expr(ctx, &parse_prog(input)[0]);
+        let unit = Rc::new(Type::Unit);
+        let unit_fn = Type::arrow(Type::Unit, Type::Unit);
+        let mut ctx = Ctx::new();
+        // Unbound and unannotated: bound to the inferred type of the body
+        assert_eq!(*check(&mut ctx, "U = ();").unwrap(), Type::Unit);
+        assert_eq!(ctx["U"], unit);
+        assert!(check(&mut ctx, "V = W ();").is_err());
+        assert!(!ctx.contains_key("V"));
+        // Unbound and annotated: bound to the annotation, which the body may refer to
+        assert_eq!(
+            *check(&mut ctx, "F : () -> () = λx. F x;").unwrap(),
+            unit_fn
+        );
+        assert_eq!(*ctx["F"], unit_fn);
+        assert!(check(&mut ctx, "G : () -> () = λx. G;").is_err());
+        assert!(!ctx.contains_key("G"));
+        // Annotated: the body is checked against the annotation, which is expected in errors
+        assert!(matches!(
+            check(&mut ctx, "E : () -> () = ();"),
+            Err(TypeError::Mismatch { expected, found: Type::Unit, .. }) if expected == unit_fn
+        ));
+        assert!(!ctx.contains_key("E"));
+        // Bound and annotated: rebound to the annotation, whatever type it had before
+        assert_eq!(*check(&mut ctx, "U : () = ();").unwrap(), Type::Unit);
+        assert_eq!(*check(&mut ctx, "U : () -> () = λx. x;").unwrap(), unit_fn);
+        assert_eq!(*ctx["U"], unit_fn);
+        assert!(check(&mut ctx, "U : () = λx. x;").is_err());
+        assert_eq!(*ctx["U"], unit_fn);
+        check(&mut ctx, "U : () = ();").unwrap();
+        // Bound and unannotated: the body is checked against the bound type
+        assert_eq!(*check(&mut ctx, "U = ();").unwrap(), Type::Unit);
+        assert!(check(&mut ctx, "U = λx. x;").is_err());
+        assert_eq!(ctx["U"], unit);
+        // Binders shadow a binding only in their body, even if checking it fails
+        ctx.insert("x".to_string(), unit.clone());
+        check(&mut ctx, "H = λx. x;").unwrap();
+        assert!(check(&mut ctx, "J = λx. y;").is_err());
+        assert!(check(&mut ctx, "K : () -> () = λx. y;").is_err());
+        assert_eq!(ctx["x"], unit);
+        assert!(!ctx.contains_key("J") && !ctx.contains_key("K"));
+    }
+
+    #[test]
+    fn test_type_error_rollback() {
+        let mut ctx = Ctx::new();
+        // The parameter is unbound again after its body fails to check or infer
+        let term = parse_term_str("λx. x ()").unwrap();
+        let expected = Rc::new(Type::arrow(Type::Unit, Type::Unit));
+        assert!(matches!(
+            types::check_term(&mut ctx, &term, &expected),
+            Err(TypeError::NotAFunction(_, _))
+        ));
+        assert!(!ctx.contains_key("x"));
+        let prog = parse_prog("λx. λy. z;");
+        assert!(types::check_expr(&mut ctx, &prog[0]).is_err());
+        assert!(ctx.is_empty());
+        // A failed program declares none of its bindings
+        let mut prog = parse_prog("type T = (); A : T = (); B : () = λx. x;");
+        assert!(types::check_program(&mut ctx, &mut prog).is_err());
+        assert!(ctx.is_empty());
+    }
+
+    #[test]
+    fn test_load_files() {
+        let dir = std::env::temp_dir();
+        let first = dir.join("tlc_test_load_first.lc");
+        let second = dir.join("tlc_test_load_second.lc");
+        let broken = dir.join("tlc_test_load_broken.lc");
+        std::fs::write(&first, "I : () -> () = λx. x;").unwrap();
+        std::fs::write(&second, "U = I ();").unwrap();
+        std::fs::write(&broken, "V = ();\nf );").unwrap();
+        let paths = [&first, &second, &broken].map(|path| path.to_str().unwrap());
+        let mut env = HashMap::new();
+        let mut ctx = Ctx::new();
+        let mut load = |paths: &[&str]| {
+            load_files(
+                paths,
+                &mut env,
+                &mut ctx,
+                false,
+                PRINT_NONE,
+                Strategy::Normal,
+                false,
+            )
+        };
+        // Later files see the definitions of earlier ones
+        load(&paths[..2]).unwrap();
+        // Loading stops at the first file that can't be read or parsed
+        let missing = "tlc_test_load_missing.lc";
+        let err = load(&[paths[2], missing]).unwrap_err();
+        assert!(err.contains(paths[2]), "{}", err);
+        let err = load(&[missing, paths[2]]).unwrap_err();
+        assert!(err.contains(missing), "{}", err);
+        for path in [&first, &second, &broken] {
+            std::fs::remove_file(path).unwrap();
+        }
+        assert!(env.contains_key("I") && env.contains_key("U") && !env.contains_key("V"));
+        assert_eq!(*ctx["U"], Type::Unit);
+    }
+
+    #[test]
+    fn test_undo() {
+        let mut env = HashMap::new();
+        let mut ctx = Ctx::new();
+        let mut undo = crate::UndoHistory::default();
+        let mut input = |input: &str, env: &mut HashMap<String, Term>, ctx: &mut Ctx| {
+            let (env_before, ctx_before) = (env.clone(), ctx.clone());
+            eval_prog(
+                input.into(),
+                env,
+                ctx,
+                false,
+                PRINT_NONE,
+                Strategy::Normal,
+                false,
+            )
+            .unwrap();
+            undo.record(&env_before, env, &ctx_before, ctx);
+        };
+        input("I : * = λx. x;", &mut env, &mut ctx);
+        input("I = λy. λz. y; K = ();", &mut env, &mut ctx);
+        // Inputs that change nothing are not recorded
+        input("I;", &mut env, &mut ctx);
+        assert_eq!(print::term(&env["I"], &PLAIN), "λy. λz. y");
+        assert!(undo.undo(&mut env, &mut ctx));
+        assert_eq!(print::term(&env["I"], &PLAIN), "λx. x");
+        assert!(!env.contains_key("K") && !ctx.contains_key("K"));
+        assert!(undo.redo(&mut env, &mut ctx));
+        assert_eq!(print::term(&env["I"], &PLAIN), "λy. λz. y");
+        assert_eq!(*ctx["K"], Type::Unit);
+        assert!(!undo.redo(&mut env, &mut ctx));
+        assert!(undo.undo(&mut env, &mut ctx) && undo.undo(&mut env, &mut ctx));
+        assert!(env.is_empty() && ctx.is_empty());
+        assert!(!undo.undo(&mut env, &mut ctx));
+    }
+
+    #[test]
+    fn test_nbe() {
+        let mut env = HashMap::new();
+        load_std(&mut env).unwrap();
+        let terms = [
+            "Add 2 3",
+            "Mul (Mul 2 3) 4",
+            "Succ (Succ 0)",
+            "If (IsZero 0) True False",
+            "Not (And True False)",
+            "Eq 2 (Succ 1)",
+            "λx. (λy. y) x",
+            "(λx. λy. x) y",
+            "λy. (λx. λy. x y) y",
+            "λx. λx. x",
+            "(λx. λy. y) ((λx. x x) (λx. x x))",
+            "fix (λf. λx. x) z",
+            "x ((λy. y) z) (λw. (λv. v) w)",
+            "(λx. x : * -> *) ((λx. x) ())",
+            "() ((λx. x) y)",
+        ];
+        for input in terms {
+            let term = parse_term_str(input).unwrap();
+            let expected = reduce_to_normal_form(&term, &env, false, PRINT_NONE).unwrap();
+            let normal = nbe::normalize_nbe(&term, &env).unwrap();
+            assert!(
+                alpha_eq(&normal, &expected),
+                "{}: {} is not {}",
+                input,
+                print::term(&normal, &PLAIN),
+                print::term(&expected, &PLAIN)
+            );
+        }
+        // Binders keep their names when nothing is captured
+        let term = parse_term_str("λf. λx. (λy. f y) x").unwrap();
+        assert_eq!(
+            print::term(&nbe::normalize_nbe(&term, &env).unwrap(), &PLAIN),
+            "λf. λx. f x"
+        );
+        // Terms without a normal form run out of steps
+        for input in ["(λx. x x) (λx. x x)", "fix (λf. λx. f x)"] {
+            let term = parse_term_str(input).unwrap();
+            assert_eq!(
+                nbe::normalize_timed(&term, &env, Some(100), &Options::default()).unwrap_err(),
+                EvalError::StepLimit(100)
+            );
+        }
+    }
+
+    #[test]
+    fn test_free_and_bound_vars() {
+        let term = parse_term_str("λx. (x y)").unwrap();
+        assert_eq!(print::var_set(&free_vars(&term), &PLAIN), "{y}");
+        assert_eq!(print::var_set(&bound_vars(&term), &PLAIN), "{x}");
+        // A variable can occur both free and bound, and unused binders still bind
+        let term = parse_term_str("x (λx. λz. x) (fix λf. b)").unwrap();
+        assert_eq!(print::var_set(&free_vars(&term), &PLAIN), "{b, x}");
+        assert_eq!(print::var_set(&bound_vars(&term), &PLAIN), "{f, x, z}");
+        assert_eq!(
+            print::var_set(&bound_vars(&Term::Unit(LineInfo(0, 0))), &PLAIN),
+            "{}"
+        );
+    }
+
+    #[test]
+    fn test_associativity() {
+        let var = |name: &str| Box::new(Term::Variable(name.to_string(), None, LineInfo(0, 0)));
+        let app = |f, x| Box::new(Term::Application(f, x, LineInfo(0, 0)));
+        let left = app(app(var("f"), var("x")), var("y"));
+        let right = app(var("f"), app(var("x"), var("y")));
+        assert!(alpha_eq(
+            &parse_term_str_with("f x y", Associativity::Left).unwrap(),
+            &left
+        ));
+        assert!(alpha_eq(
+            &parse_term_str_with("f x y", Associativity::Right).unwrap(),
+            &right
+        ));
+        // Parentheses are only needed against the direction of association
+        assert_eq!(
+            print::term_assoc(&left, Associativity::Left, &PLAIN),
+            "f x y"
+        );
+        assert_eq!(
+            print::term_assoc(&right, Associativity::Left, &PLAIN),
+            "f (x y)"
+        );
+        assert_eq!(
+            print::term_assoc(&right, Associativity::Right, &PLAIN),
+            "f x y"
+        );
+        assert_eq!(
+            print::term_assoc(&left, Associativity::Right, &PLAIN),
+            "(f x) y"
+        );
+    }
+
+    #[test]
+    fn test_annotation_conflict() {
+        let mut prog = parse_prog("λx: A. (x : B);");
+        let err = types::check_program(&mut Ctx::new(), &mut prog).unwrap_err();
+        assert!(matches!(
+            &err,
+            TypeError::AnnotationConflict { name, first: Type::Variable(a), second: Type::Variable(b), .. }
+                if name == "x" && a == "A" && b == "B"
+        ));
+        assert_eq!(
+            print::ty_err(err, None, &PLAIN),
+            "Type error: `x` is annotated A where it is bound but B at line 1 col 8"
+        );
+        // Checking against a function type reports the conflict too, agreeing annotations and shadowing binders do not
+        let id = Rc::new(Type::Abstraction(
+            Rc::new(Type::Variable("A".to_string())),
+            Rc::new(Type::Variable("A".to_string())),
+        ));
+        let term = parse_term_str("λx: A. (x : B)").unwrap();
+        assert!(matches!(
+            types::check_term(&mut Ctx::new(), &term, &id),
+            Err(TypeError::AnnotationConflict { .. })
+        ));
+        let term = parse_term_str("λx: A. (x : A)").unwrap();
+        assert!(types::check_term(&mut Ctx::new(), &term, &id).is_ok());
+        let term = parse_term_str("λx: A. λx: B. (x : B)").unwrap();
+        assert!(!matches!(
+            types::check_term(&mut Ctx::new(), &term, &id),
+            Err(TypeError::AnnotationConflict { .. })
+        ));
+    }
+
+    #[test]
+    fn test_type_mode() {
+        let term = parse_term_str("λx: A -> B. λy: A. (x y : B)").unwrap();
+        assert_eq!(
+            print::term_types(&term, print::TypeMode::ShowTypes, &PLAIN),
+            "λx : A -> B. λy : A. (x y : B)"
+        );
+        assert_eq!(
+            print::term_types(&term, print::TypeMode::HideTypes, &PLAIN),
+            "λx. λy. x y"
+        );
+        // Hidden ascriptions no longer group their term, so it is wrapped where needed
+        let term = parse_term_str("f (g x : A) (λz: A. z : A -> A)").unwrap();
+        assert_eq!(
+            print::term_types(&term, print::TypeMode::HideTypes, &PLAIN),
+            "f (g x) (λz. z)"
+        );
+    }
+
+    #[test]
+    fn test_letrec() {
+        // The definition refers to itself, its annotation is in the context while checking it
+        let mut ctx = Ctx::new();
+        let expr = &parse_prog("letrec f : A -> A = λn. (f n) in f;")[0];
+        assert_eq!(
+            types::check_expr(&mut ctx, expr).unwrap().to_string(),
+            "(A -> A)"
+        );
+        assert!(!ctx.contains_key("f"));
+        let expr = &parse_prog("letrec f : A -> A = λn. n n in f;")[0];
+        assert!(types::check_expr(&mut ctx, expr).is_err());
+        // An annotated binder applied directly has its annotation, the argument must match it
+        let expr = &parse_prog("(λx: A. x) ();")[0];
+        assert!(types::check_expr(&mut ctx, expr).is_err());
+        let expr = &parse_prog("(λx: (). x) ();")[0];
+        assert_eq!(types::check_expr(&mut ctx, expr).unwrap().to_string(), "()");
+        // Unfolding stops at the base case, or at the step limit if there is none
+        let mut env = HashMap::new();
+        load_std(&mut env).unwrap();
+        let term = parse_term_str("letrec f = λn. IsZero n () (f 0) in f 3").unwrap();
+        let result = reduce_with_limit(&term, &env, Strategy::Normal, 1_000).unwrap();
+        assert!(matches!(result, Term::Unit(_)));
+        let term = parse_term_str("letrec f = λn. f n in f ()").unwrap();
+        assert!(matches!(
+            reduce_with_limit(&term, &env, Strategy::Normal, 100),
+            Err(EvalError::StepLimit(100))
+        ));
+        // `in` ends the definition, but names only starting with a keyword are still variables
+        let term = parse_term_str("letrec g = inner letrecs in g").unwrap();
+        assert_eq!(free_vars(&term).len(), 2);
+    }
+
+    #[test]
+    fn test_error_report() {
+        let mismatch = TypeError::Mismatch {
+            expected: Type::arrow(Type::Unit, Type::Unit),
+            found: Type::Unit,
+            info: LineInfo(2, 7),
+        };
+        assert_eq!(
+            emit::ErrorReport::from(&mismatch),
+            emit::ErrorReport {
+                code: "mismatch",
+                message: "expected () -> () but found ()".to_string(),
+                line: 2,
+                col: 7,
+                expected: Some("() -> ()".to_string()),
+                found: Some("()".to_string()),
+            }
+        );
+        let report = emit::ErrorReport::from(&TypeError::Unbound("x".to_string(), LineInfo(1, 3)));
+        assert_eq!((report.code, report.line, report.col), ("unbound", 1, 3));
+        assert_eq!(report.expected, None);
+        let err = parse_term_str("λx. (x").unwrap_err();
+        let report = emit::ErrorReport::from(&err);
+        assert_eq!((report.code, report.line), ("parse", 1));
+    }
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_error_report_json() {
+        let mismatch = TypeError::Mismatch {
+            expected: Type::arrow(Type::Unit, Type::Unit),
+            found: Type::Unit,
+            info: LineInfo(2, 7),
+        };
+        let json = emit::report_json(&emit::ErrorReport::from(&mismatch));
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "code": "mismatch",
+                "message": "expected () -> () but found ()",
+                "line": 2,
+                "col": 7,
+                "expected": "() -> ()",
+                "found": "()"
+            })
+        );
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let path = std::env::temp_dir().join("tlc_test_doc_comments.lc");
+        let source = "/// The identity function\n///\n/// Returns its argument\nI : () -> () = λx. x;\n-- Not a doc comment\nU = I ();";
+        std::fs::write(&path, source).unwrap();
+        let docs = load_files(
+            &[path.to_str().unwrap()],
+            &mut HashMap::new(),
+            &mut Ctx::new(),
+            false,
+            PRINT_NONE,
+            Strategy::Normal,
+            false,
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(docs["I"], "The identity function\n\nReturns its argument");
+        assert!(!docs.contains_key("U"));
+        // Printed programs keep their doc comments
+        let prog = parse_prog(source);
+        assert_eq!(
+            print::program(&prog[..1].to_vec(), &PLAIN),
+            "/// The identity function\n///\n/// Returns its argument\nI : () -> () = λx. x;\n"
+        );
+        let printed = print::program(&prog, &PLAIN);
+        assert_eq!(print::program(&parse_prog(&printed), &PLAIN), printed);
+    }
+
+    #[test]
+    fn test_canonicalize_names() {
+        let canonical =
+            |input: &str| print::plain_term(&canonicalize_names(&parse_term_str(input).unwrap()));
+        // α-equivalent terms are printed the same, even with shadowed binders
+        assert_eq!(canonical("λx. λy. x (λz. z y)"), "λa. λb. a (λc. c b)");
+        assert_eq!(canonical("λp. λq. p (λp. p q)"), "λa. λb. a (λc. c b)");
+        assert_eq!(canonical("λx'. λx''. x'' x'"), canonical("λy. λz. z y"));
+        // Binders are numbered from left to right and free variables keep their names
+        assert_eq!(canonical("(λx. a x) (λy. y) b"), "(λc. a c) (λd. d) b");
+        let many = "λv. ".repeat(27) + "v";
+        assert!(canonical(&many).ends_with("λz. λa1. a1"));
+    }
+
+    #[test]
+    fn test_only() {
+        use lamda_calc::{run, Config, Error};
+        let source = "I : () -> () = λx. x;\nI;\nU = I ();\nI U;\nV = U;\nλz. z;";
+        let config = |only| Config {
+            only,
+            ..Config::default()
+        };
+        let printed = |terms: Vec<Term>| terms.iter().map(print::plain_term).collect::<Vec<_>>();
+        assert_eq!(
+            printed(run(source, &config(None)).into_result().unwrap()),
+            ["λx. x", "()", "λz. z"]
+        );
+        // Definitions before the term are still evaluated
+        assert_eq!(
+            printed(run(source, &config(Some(2))).into_result().unwrap()),
+            ["()"]
+        );
+        assert_eq!(
+            printed(run(source, &config(Some(1))).into_result().unwrap()),
+            ["λx. x"]
+        );
+        assert!(matches!(
+            run(source, &config(Some(4))).error,
+            Some(Error::Only(_))
+        ));
+        // Later expressions are left out
+        let prog = only_term(parse_prog(source), 2).unwrap();
+        assert_eq!(prog.len(), 3);
+        assert!(matches!(&prog[2], Expr::Term(_)));
+    }
+
+    #[test]
+    fn test_stuck_terms() {
+        let env = HashMap::new();
+        let normal = |input: &str| {
+            reduce_to_normal_form(&parse_term_str(input).unwrap(), &env, false, PRINT_NONE).unwrap()
+        };
+        let term = normal("(undefined y)");
+        let stuck = lint::stuck(&term, &env);
+        assert_eq!(stuck.len(), 1);
+        assert_eq!(
+            print::stuck_warning(stuck[0], &PLAIN),
+            "Warning: `undefined y` is stuck applying a free variable at line 1 col 2"
+        );
+        assert!(lint::stuck(&normal("(λx.x) y"), &env).is_empty());
+        // Bound and defined heads are not stuck, each stuck application is reported once with its arguments
+        let env = HashMap::from([("f".to_string(), parse_term_str("λx. x").unwrap())]);
+        let term = parse_term_str("λg. g (f x) (h x y (k z))").unwrap();
+        let stuck: Vec<String> = lint::stuck(&term, &env)
+            .into_iter()
+            .map(print::plain_term)
+            .collect();
+        assert_eq!(stuck, ["k z", "h x y (k z)"]);
+    }
+
+    #[test]
+    fn test_reduction_iter() {
+        let env = HashMap::new();
+        let term = parse_term_str("(λf. λx. f (f x)) (λy. y) z").unwrap();
+        let steps = |strategy, max_steps| {
+            ReductionIter::new(&term, &env, strategy, max_steps)
+                .map(|term| print::plain_term(&term))
+                .collect::<Vec<_>>()
+        };
+        let first: Vec<String> = ReductionIter::new(&term, &env, Strategy::Normal, None)
+            .take(3)
+            .map(|term| print::plain_term(&term))
+            .collect();
+        assert_eq!(
+            first,
+            [
+                "(λx. (λy. y) ((λy. y) x)) z",
+                "(λy. y) ((λy. y) z)",
+                "(λy. y) z"
+            ]
+        );
+        // The iterator ends at the normal form or the step limit
+        assert_eq!(steps(Strategy::Normal, None).last().unwrap(), "z");
+        assert_eq!(steps(Strategy::Normal, None).len(), 4);
+        assert_eq!(steps(Strategy::Normal, Some(2)).len(), 2);
+        let term = parse_term_str("λx. (λy. y) x").unwrap();
+        assert!(ReductionIter::new(&term, &env, Strategy::Whnf, None)
+            .next()
+            .is_none());
+    }
+
+    #[test]
+    fn test_any_in_type_alias() {
+        let check = |input: &str| types::check_program(&mut Ctx::new(), &mut parse_prog(input));
+        // `*` in an alias matches any type in its place, the rest of the alias must still match
+        let aliases = "type F = * -> (); type G = (* -> *) -> ();";
+        assert!(check(&format!("{} f : F = λx: A. ();", aliases)).is_ok());
+        assert!(check(&format!("{} f : F = λx: A -> A. ();", aliases)).is_ok());
+        assert!(check(&format!("{} g : G = λh: () -> (). ();", aliases)).is_ok());
+        assert!(check(&format!("{} f : F = λx: A. x;", aliases)).is_err());
+        assert!(check(&format!("{} g : G = λh: (). ();", aliases)).is_err());
+        // A wildcard function type still needs a function
+        assert!(matches!(
+            check(&format!("{} f : F = ();", aliases)),
+            Err(TypeError::Mismatch { .. })
+        ));
+        assert!(check("type H = * -> *; h : H = ();").is_err());
+        assert!(check("type H = * -> *; h : H = λx: (). x;").is_ok());
+    }
+
+    #[test]
+    fn test_dump_env() {
+        let path = std::env::temp_dir().join("tlc_test_dump_env.lc");
+        std::fs::write(&path, "K = λx. λy. x;\nI = λx. x;\nU = ();\nK;\n").unwrap();
+        let input = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut env = HashMap::new();
+        eval_prog(
+            input,
+            &mut env,
+            &mut Ctx::new(),
+            false,
+            PRINT_NONE,
+            Strategy::Normal,
+            false,
+        )
+        .unwrap();
+        // Sorted by name, with the terms as they were bound
+        assert_eq!(print::env(&env, &PLAIN), "I = λx. x\nK = λx. λy. x\nU = ()");
+        assert_eq!(print::env(&HashMap::new(), &PLAIN), "");
+    }
+
+    #[test]
+    fn test_newline_separators() {
+        // Line breaks separate statements like `;` does, the positions are the same in both
+        let with_semicolons =
+            parse_prog("I = λx. x;\nK = λx. λy. x;\n\n-- apply\nK I;\n#strategy whnf;\nI");
+        let with_newlines =
+            parse_prog("I = λx. x\nK = λx. λy. x\n\n-- apply\nK I\n#strategy whnf\nI");
+        assert_eq!(with_semicolons.len(), 5);
+        assert_eq!(with_newlines, with_semicolons);
+        // The last statement doesn't need a `;`
+        assert_eq!(parse_prog("f x"), parse_prog("f x;"));
+        let Expr::Term(term) = &with_newlines[2] else {
+            panic!("expected a term, got {:?}", with_newlines[2]);
+        };
+        assert_eq!(term.info(), &LineInfo(5, 1));
+        // Indented lines, a closing parenthesis and tokens that can't end a statement continue it
+        let continued = parse_prog(
+            "F = λf.\nλx. f\n  (f x) -- twice\nG = (F\n)\nT : A ->\nA = F (x\n  : A)\n1\n+ 2",
+        );
+        assert_eq!(continued.len(), 4);
+        assert!(
+            matches!(&continued[0], Expr::Assignment(name, _, Term::Abstraction(..), _) if name == "F")
+        );
+        assert!(matches!(
+            &continued[3],
+            Expr::Term(Term::Application(_, _, LineInfo(9, 1)))
+        ));
+        // A line starting at the first column is a new statement
+        assert_eq!(parse_prog("f\nx").len(), 2);
+        assert_eq!(parse_prog("f\n x").len(), 1);
+        assert!(parse_prog_recover("λx.\nx y\nz )").1.len() == 1);
+    }
+
+    #[test]
+    fn test_redex_path() {
+        let env = HashMap::new();
+        let prog = parse_prog("(λx. x) ((λy. y) z);");
+        let (_, trace) = reduce_with_trace(prog[0].term(), &env, Strategy::Normal, None).unwrap();
+        // Normal order reduces the outer application first
+        assert_eq!(trace.steps[0].3, Vec::<usize>::new());
+        assert_eq!(trace.steps[0].2, LineInfo(1, 1));
+        // The argument of the application is child 1, the body of an abstraction child 0
+        let prog = parse_prog("λf. f ((λx. x) f);");
+        let term = prog[0].term();
+        let path = redex_path(term, &env, Strategy::Normal, &Options::current()).unwrap();
+        assert_eq!(path, [0, 1]);
+        assert_eq!(
+            subterm(term, &path),
+            redex(term, &env, Strategy::Normal, &Options::current())
+        );
+        let (_, trace) = reduce_with_trace(term, &env, Strategy::Normal, None).unwrap();
+        assert_eq!(trace.steps[0].3, [0, 1]);
+        assert_eq!(subterm(term, &[1]), None);
+        // Head reduction only follows functions, an environment variable is unfolded in place
+        let env = HashMap::from([("I".to_string(), Term::abs("x", Term::var("x")))]);
+        let prog = parse_prog("I a b;");
+        assert_eq!(
+            redex_path(prog[0].term(), &env, Strategy::Whnf, &Options::current()).unwrap(),
+            [0, 0]
+        );
+        assert_eq!(
+            redex_path(
+                prog[0].term(),
+                &HashMap::new(),
+                Strategy::Whnf,
+                &Options::current()
+            ),
+            None
+        );
+        // Arithmetic on numerals is computed at once with native numerals in the options
+        let mut env = HashMap::new();
+        load_std(&mut env).unwrap();
+        let prog = parse_prog("Add (λf. λx. f x) (λf. λx. f x);");
+        let native = Options {
+            native_numerals: true,
+            ..Options::current()
+        };
+        let plain = Options {
+            native_numerals: false,
+            ..Options::current()
+        };
+        let path = |options| redex_path(prog[0].term(), &env, Strategy::Normal, options);
+        assert_eq!(path(&native), Some(vec![]));
+        assert_eq!(path(&plain), Some(vec![0]));
+        // Unless the result is too deep to compute
+        let shallow = Options {
+            max_depth: 2,
+            ..native
+        };
+        assert_eq!(path(&shallow), Some(vec![0]));
+    }
+
+    #[test]
+    fn test_multi_param_abstraction() {
+        let sugar = parse_term_str("λx: A, y: B. b").unwrap();
+        let nested = parse_term_str("λx: A. λy: B. b").unwrap();
+        let Term::Abstraction(x, Some(Type::Variable(a)), body, _) = &sugar else {
+            panic!("expected an abstraction, got {:?}", sugar);
+        };
+        let Term::Abstraction(y, Some(Type::Variable(b)), _, _) = body.as_ref() else {
+            panic!("expected a nested abstraction, got {:?}", body);
+        };
+        assert_eq!(
+            (x.as_str(), a.as_str(), y.as_str(), b.as_str()),
+            ("x", "A", "y", "B")
+        );
+        assert!(alpha_eq(&sugar, &nested));
+        assert_eq!(print::term(&sugar, &PLAIN), print::term(&nested, &PLAIN));
+        // Parameters may be unannotated, and function types end at the comma
+        assert!(alpha_eq(
+            &parse_term_str("λf: A -> B, x, y: (). f x").unwrap(),
+            &parse_term_str("λf: A -> B. λx. λy: (). f x").unwrap()
+        ));
+        assert!(parse_term_str("λx, . x").is_err());
+        // Spaces separate parameters like commas, as flat binders are printed
+        assert!(alpha_eq(
+            &parse_term_str("λx : A y, z. x").unwrap(),
+            &parse_term_str("λx: A. λy. λz. x").unwrap()
+        ));
+        // The checker sees the nested abstractions
+        let mut ctx = Ctx::new();
+        let mut prog = parse_prog("K : A -> B -> A = λx: A, y: B. x;");
+        assert!(types::check_program(&mut ctx, &mut prog).is_ok());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_session_round_trip() {
+        let mut env = HashMap::new();
+        let mut ctx = Ctx::new();
+        eval_prog(
+            "type T = * -> (); I : T = λx: A. (); K = λx. λy. x;".to_string(),
+            &mut env,
+            &mut ctx,
+            false,
+            PRINT_NONE,
+            Strategy::Normal,
+            false,
+        )
+        .unwrap();
+        assert!(env.contains_key("K"));
+        let path = std::env::temp_dir().join("tlc_test_session.json");
+        let path = path.to_str().unwrap();
+        emit::save_session(path, &env, &ctx).unwrap();
+        let session = emit::load_session(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        // The terms come back with their annotations and line information
+        assert_eq!(session.env, env);
+        assert_eq!(session.ctx, ctx);
+        assert!(emit::load_session(path).is_err());
+    }
+    #[cfg(not(feature = "json"))]
+    #[test]
+    fn test_session_requires_json() {
+        let path = std::env::temp_dir().join("tlc_test_session_disabled.json");
+        let path = path.to_str().unwrap();
+        assert!(emit::save_session(path, &HashMap::new(), &Ctx::new()).is_err());
+        assert!(!std::path::Path::new(path).exists());
+        assert!(emit::load_session(path).is_err());
+    }
+
+    #[test]
+    fn test_lint_linear() {
+        let term = parse_term_str("λx. x (λx. x x) x").unwrap();
+        let Term::Abstraction(_, _, body, _) = &term else {
+            panic!("expected an abstraction, got {:?}", term);
+        };
+        // The `x` bound by the inner abstraction are not counted
+        assert_eq!(lint::usage_count(body, "x"), 2);
+        assert_eq!(lint::usage_count(&term, "x"), 0);
+        assert!(lint::nonlinear(&parse_prog("λx. x;")).is_empty());
+        assert!(lint::nonlinear(&parse_prog("λf. λx. f x;")).is_empty());
+        assert_eq!(
+            lint::nonlinear(&parse_prog("λx. (x x);")),
+            [("x".to_string(), 2, LineInfo(1, 1))]
+        );
+        assert_eq!(
+            lint::nonlinear(&parse_prog("K = λx. y;")),
+            [("x".to_string(), 0, LineInfo(1, 5))]
+        );
+        assert_eq!(
+            print::linear_warning("x", 0, &LineInfo(1, 5), &PLAIN),
+            "Warning: `x` is never used instead of once at line 1 col 5"
+        );
+        assert_eq!(
+            print::linear_warning("x", 2, &LineInfo(1, 1), &PLAIN),
+            "Warning: `x` is used 2 times instead of once at line 1 col 1"
+        );
+    }
+
+    #[test]
+    fn test_compare_quantified_types() {
+        let ty = |input: &str| match &parse_prog(&format!("type T = {};", input))[..] {
+            [Expr::TypeDef(_, ty)] => ty.clone(),
+            prog => panic!("expected a type definition, got {:?}", prog),
+        };
+        let same = |a: &str, b: &str| types::compare_types(&ty(a), &ty(b));
+        // Bound variables are compared up to renaming
+        assert!(same("forall a. a -> a", "forall b. b -> b"));
+        assert!(same(
+            "forall a. forall b. a -> b",
+            "forall x. forall y. x -> y"
+        ));
+        assert!(same("forall a. forall a. a", "forall b. forall c. c"));
+        assert!(same("(forall a. a) -> ()", "(forall b. b) -> ()"));
+        // Whitespace around the names is not part of them
+        assert!(same("forall a.a->a", "forall  b .  b  ->\tb"));
+        assert!(same("forall a. (a ) -> a", "forall b. b -> ( b)"));
+        assert!(!same(
+            "forall a. forall b. a -> b",
+            "forall b. forall a. a -> b"
+        ));
+        assert!(!same("forall a. a -> a", "forall b. b -> a"));
+        assert!(!same("forall a. a -> A", "forall b. b -> B"));
+        assert!(!same("forall a. a", "a"));
+        // Free variables still match by name
+        assert!(same("forall a. a -> B", "forall b. b -> B"));
+        assert_eq!(
+            print::plain_type(&ty("(forall a. a -> a) -> forall b. b")),
+            "(forall a. a -> a) -> forall b. b"
+        );
+        // An alias with the name of a bound variable isn't expanded under the quantifier
+        let mut ctx = Ctx::new();
+        ctx.insert("a".to_string(), Rc::new(Type::Unit));
+        assert_eq!(
+            types::resolve_type(&ctx, &ty("forall a. a -> a")),
+            ty("forall a. a -> a")
+        );
+        assert_eq!(
+            types::resolve_type(&ctx, &ty("forall b. a -> b")),
+            ty("forall b. () -> b")
+        );
+    }
+
+    #[test]
+    fn test_prompts() {
+        assert_eq!(
+            crate::prompts(None, false),
+            ("> ".to_string(), "... ".to_string())
+        );
+        assert_eq!(
+            crate::prompts(Some("λ> "), false),
+            ("λ> ".to_string(), "... ".to_string())
+        );
+        // Piped input gets no prompts in front of the results
+        assert_eq!(
+            crate::prompts(Some("λ> "), true),
+            (String::new(), String::new())
+        );
+    }
+
+    #[test]
+    fn test_infer_unused_param() {
+        let mut ctx = Ctx::new();
+        ctx.insert("y".to_string(), Rc::new(Type::Variable("C".to_string())));
+        let infer = |ctx: &mut Ctx, input: &str| types::check_expr(ctx, &parse_prog(input)[0]);
+        // An unused parameter is not named after itself
+        assert_eq!(
+            *infer(&mut ctx, "λx. y;").unwrap(),
+            Type::arrow(Type::Any, Type::Variable("C".to_string()))
+        );
+        assert_eq!(
+            *infer(&mut ctx, "λx. λz. z;").unwrap(),
+            Type::arrow(
+                Type::Any,
+                Type::arrow(
+                    Type::Variable("z".to_string()),
+                    Type::Variable("z".to_string())
+                )
+            )
+        );
+        // A parameter the body uses still gets a variable
+        assert_eq!(
+            *infer(&mut ctx, "λx. x;").unwrap(),
+            Type::arrow(
+                Type::Variable("x".to_string()),
+                Type::Variable("x".to_string())
+            )
+        );
+    }
+
+    #[test]
+    fn test_load_std_from() {
+        let path = std::env::temp_dir().join("tlc_test_prelude.lc");
+        std::fs::write(
+            &path,
+            "-- A smaller prelude\nId = λx. x;\nConst = λx. λy. x;\n",
+        )
+        .unwrap();
+        let mut env = HashMap::new();
+        let loaded = load_std_from(Some(path.to_str().unwrap()), &mut env);
+        // A run loads and imports the standard library of its configuration
+        let config = lamda_calc::Config {
+            use_std: true,
+            std_path: Some(path.to_str().unwrap().to_string()),
+            ..lamda_calc::Config::default()
+        };
+        let ran = lamda_calc::run("Const Id;", &config);
+        let imported = lamda_calc::run("import Id from std; Id;", &config);
+        std::fs::remove_file(&path).unwrap();
+        loaded.unwrap();
+        assert_eq!(
+            print::plain_term(&ran.into_result().unwrap()[0]),
+            "λy. λx. x"
+        );
+        assert_eq!(
+            print::plain_term(&imported.into_result().unwrap()[0]),
+            "λx. x"
+        );
+        assert!(env.contains_key("Id") && env.contains_key("Const"));
+        assert!(!env.contains_key("True") && !env.contains_key("Succ"));
+        // Without a path the bundled one is loaded
+        let mut env = HashMap::new();
+        load_std_from(None, &mut env).unwrap();
+        assert!(env.contains_key("True") && !env.contains_key("Id"));
+        assert!(std_source(None).unwrap().contains("Succ"));
+        assert!(load_std_from(Some(path.to_str().unwrap()), &mut env).is_err());
+    }
+
+    #[test]
+    fn test_read_source_error() {
+        let path = std::env::temp_dir()
+            .join("tlc_test_missing")
+            .join("program.lc");
+        let path = path.to_str().unwrap();
+        let err = crate::read_source(path).unwrap_err();
+        assert!(
+            err.starts_with(&format!("Error reading file {}: ", path)),
+            "unexpected error: {}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_flat_binders() {
+        let term = parse_term_str("λx. λy. λz. x z (y z)").unwrap();
+        assert_eq!(
+            print::term_binders(&term, print::BinderMode::Nested, &PLAIN),
+            "λx. λy. λz. x z (y z)"
+        );
+        assert_eq!(
+            print::term_binders(&term, print::BinderMode::Flat, &PLAIN),
+            "λx y z. x z (y z)"
+        );
+        // Annotations stay with their parameter
+        let term = parse_term_str("λx: A. λy: B -> C. λz. y x").unwrap();
+        assert_eq!(
+            print::term_binders(&term, print::BinderMode::Flat, &PLAIN),
+            "λx : A y : B -> C z. y x"
+        );
+        // Only directly nested abstractions are flattened
+        let term = parse_term_str("λf. f (λx. λy. x) λz. z").unwrap();
+        assert_eq!(
+            print::term_binders(&term, print::BinderMode::Flat, &PLAIN),
+            "λf. f (λx y. x) (λz. z)"
+        );
+    }
+    #[test]
+    fn test_flat_binders_round_trip() {
+        let mut env = HashMap::new();
+        let input = "K = λx. λy. x; S = λx. λy. λz. x z (y z); A = λx: A. λy: B -> C. λ_. λz. y x;";
+        for expr in parse_prog(input) {
+            eval_expr(&expr, &mut env, false, PRINT_NONE).unwrap();
+        }
+        // Saved like `:save` with `--flat-binders` on
+        let mut names: Vec<&String> = env.keys().collect();
+        names.sort();
+        let saved: String = names
+            .into_iter()
+            .map(|name| {
+                let term = print::term_binders(&env[name], print::BinderMode::Flat, &PLAIN);
+                format!("{} = {};\n", name, term)
+            })
+            .collect();
+        assert!(saved.contains("K = λx y. x;"), "{}", saved);
+        let (prog, errors) = parse_prog_recover(&saved);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let mut loaded = HashMap::new();
+        for expr in prog {
+            eval_expr(&expr, &mut loaded, false, PRINT_NONE).unwrap();
+        }
+        assert_eq!(loaded.len(), env.len());
+        for (name, term) in env.iter() {
+            assert_eq!(print::plain_term(&loaded[name]), print::plain_term(term));
+        }
+    }
+
+    #[test]
+    fn test_std_pred_eq() {
+        let mut env = HashMap::new();
+        load_std(&mut env).unwrap();
+        let normal = |source: &str| {
+            let term = parse_term_str(source).unwrap();
+            reduce_to_normal_form(&term, &env, false, PRINT_NONE).unwrap()
+        };
+        // `Fst` takes the pair built from `Pair 0 0`, not the iterated step function
+        for (n, pred) in [(0, 0), (1, 0), (3, 2)] {
+            let result = normal(&format!("Pred {}", n));
+            assert_eq!(decode_numeral(&result, &env), Some(pred), "Pred {}", n);
+        }
+        // Numerals are equal if each is at most the other, not only if both sums are zero
+        for (source, expected) in [
+            ("Eq 0 0", "True"),
+            ("Eq 2 2", "True"),
+            ("Eq 1 2", "False"),
+            ("Eq 2 1", "False"),
+            ("Leq 1 2", "True"),
+            ("Leq 2 1", "False"),
+            ("Sub 3 1", "2"),
+        ] {
+            assert!(alpha_eq(&normal(source), &normal(expected)), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_native_comparisons() {
+        let mut env = HashMap::new();
+        load_std(&mut env).unwrap();
+        for (source, expected) in [
+            ("IsZero 0", true),
+            ("IsZero 3", false),
+            ("Leq 2 3", true),
+            ("Leq 3 2", false),
+            ("Eq 2 2", true),
+            ("Eq 2 3", false),
+        ] {
+            let prog = parse_prog(&format!("{};", source));
+            let pure = reduce_to_normal_form(prog[0].term(), &env, false, PRINT_NONE).unwrap();
+            // A single native step computes the Church boolean
+            let fast = native_step(&inline_vars(prog[0].term(), &env), &env).unwrap();
+            assert!(alpha_eq(&pure, &fast), "{}", source);
+            assert!(
+                alpha_eq(&fast, &encode_bool(expected, fast.info())),
+                "{}",
+                source
+            );
+        }
+    }
+    #[test]
+    fn test_empty_program() {
+        for input in [
+            "",
+            "   ",
+            "\n\t \n",
+            ";;",
+            "-- comment",
+            "-- one\n-- two\n",
+            "\n-- x; y\n  ",
+        ] {
+            let (prog, errors) = parse_prog_recover(input);
+            assert!(prog.is_empty(), "{:?}", input);
+            assert!(errors.is_empty(), "{:?}", input);
+            assert!(parse_prog(input).is_empty(), "{:?}", input);
+        }
+        assert!(crate::parse("-- nothing to see here\n", &PLAIN)
+            .unwrap()
+            .is_empty());
+    }
+    #[test]
+    fn test_search_by_type() {
+        let mut env = HashMap::new();
+        for (name, term) in [("I", "λx. x"), ("Id", "λy. y"), ("U", "()")] {
+            env.insert(name.to_string(), parse_term_str(term).unwrap());
+        }
+        let ctx = Ctx::new();
+        let query = parse_type_str("* -> *").unwrap();
+        let names: Vec<String> = search(&query, &env, &ctx)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, ["I", "Id"]);
+        let query = parse_type_str("()").unwrap();
+        assert_eq!(search(&query, &env, &ctx)[0].0, "U");
+        assert!(parse_type_str("* ->").is_err());
+    }
+    #[test]
+    fn test_substitute_renames_only_capturing_binders() {
+        let subst = |term: &str, var: &str, value: &str| {
+            let term = parse_term_str(term).unwrap();
+            let value = parse_term_str(value).unwrap();
+            print::term(
+                &substitute_fresh(&term, var, &value, FreshNames::Primes),
+                &PLAIN,
+            )
+        };
+        // `x` doesn't occur under the binder, so `y` can't be captured
+        assert_eq!(subst("λy. z y", "x", "y"), "λy. z y");
+        // Only the binder `x` is substituted under is renamed
+        assert_eq!(subst("(λy. x y) (λy. y)", "x", "y"), "(λy'. y y') (λy. y)");
+        assert_eq!(subst("λy. λy'. x y y'", "x", "y"), "λy''. λy'. y y'' y'");
+    }
+    #[test]
+    fn test_fork_environment() {
+        let config = lamda_calc::Config::default();
+        let (_, base) = lamda_calc::run_in("I = λx. x;", &config, &HashMap::new()).unwrap();
+        let (results, fork) = lamda_calc::run_in("K = λx. λy. x; K I;", &config, &base).unwrap();
+        assert_eq!(print::plain_term(&results[0]), "λy. λx. x");
+        assert!(fork.contains_key("I") && fork.contains_key("K"));
+        assert!(!base.contains_key("K"));
+        let prog = parse_prog("U = ();");
+        let (_, fork) = eval_expr_in(&prog[0], &base, false, PRINT_NONE).unwrap();
+        assert!(fork.contains_key("U"));
+        assert_eq!(base.len(), 1);
+    }
+    #[test]
+    fn test_run_result() {
+        use lamda_calc::{run, Config, Error};
+        let config = Config::default();
+        let result = run("#frobnicate on\nI : () -> () = λx. x; I (); I;", &config);
+        assert!(result.error.is_none(), "{:?}", result.error);
+        let printed: Vec<String> = result.terms.iter().map(print::plain_term).collect();
+        assert_eq!(printed, ["()", "λx. x"]);
+        assert_eq!(result.steps, [1, 0]);
+        assert_eq!(
+            result.warnings,
+            ["Warning: ignoring pragma `#frobnicate on`"]
+        );
+        let result = run("I = λx. x;\nf );", &config);
+        assert!(matches!(result.error, Some(Error::Parse(errors)) if errors.len() == 1));
+        assert!(result.terms.is_empty());
+        let result = run("U : () = λx. x;", &config);
+        assert!(matches!(result.error, Some(Error::Type(_))));
+        assert!(result.terms.is_empty() && result.steps.is_empty());
+        // Lints are enabled by the configuration, not by the interpreter's flags
+        let source = "K = λx. λx. x; λy. 5 y;";
+        let config = Config {
+            native_numerals: true,
+            ..Config::default()
+        };
+        assert!(run(source, &config).warnings.is_empty());
+        let config = Config {
+            warn_shadow: true,
+            lint_linear: true,
+            warn_stuck: true,
+            ..config
+        };
+        assert_eq!(
+            run(source, &config).warnings,
+            [
+                "Warning: `x` shadows an enclosing binder at line 1 col 9",
+                "Warning: `x` is never used instead of once at line 1 col 5",
+                "Warning: `5 y` is stuck applying a free variable at line 1 col 20"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_reports_errors() {
+        use lamda_calc::{run, Config, Error};
+        // Modules are looked up relative to the working directory
+        std::fs::write("tlcbadmoduletest.lc", "B = λx. x;\nf );").unwrap();
+        let result = run("import B from tlcbadmoduletest; B;", &Config::default());
+        std::fs::remove_file("tlcbadmoduletest.lc").unwrap();
+        assert!(
+            matches!(&result.error, Some(Error::Import(err)) if err.starts_with("Error parsing module `tlcbadmoduletest`")),
+            "{:?}",
+            result.error
+        );
+        // Expressions that are not evaluated are errors instead of panics
+        let prog = parse_prog("type T = (); #strategy lazy;");
+        for expr in &prog {
+            assert!(matches!(
+                eval_expr(expr, &mut HashMap::new(), false, PRINT_NONE),
+                Err(EvalError::NotEvaluated(_))
+            ));
+        }
+        // Parse and type errors are returned instead of printed
+        let mut env = HashMap::new();
+        let mut ctx = Ctx::new();
+        let mut run_prog = |input: &str| {
+            eval_prog(
+                input.into(),
+                &mut env,
+                &mut ctx,
+                false,
+                PRINT_NONE,
+                Strategy::Normal,
+                false,
+            )
+        };
+        assert!(run_prog("f );").unwrap_err().contains("f );"));
+        assert!(run_prog("U : () = λx. x;")
+            .unwrap_err()
+            .starts_with("Type error"));
+    }
+    #[test]
+    #[cfg(feature = "wasm")]
+    fn test_wasm_outputs() {
+        use lamda_calc::{wasm, Config};
+        let output = wasm::run_output("I : () -> () = λx. x; I ();", &Config::default());
+        assert_eq!(output.printed, ["()"]);
+        assert!(output.errors.is_empty());
+        let output = wasm::run_output("f );", &Config::default());
+        assert_eq!(output.errors[0].code, "parse");
+        let output = wasm::step_output("(λx. x) y", false);
+        assert_eq!(output.printed.as_deref(), Some("y"));
+        assert_eq!(wasm::step_output("y", false).term, None);
+    }
+    #[test]
+    fn test_check_against_any() {
+        let any = Rc::new(Type::Any);
+        let mut ctx = Ctx::new();
+        // Unannotated parameters are wildcards, so these check without synthesizing a type
+        for source in ["λx. x x", "λf. λx. f x x", "λx : * -> *. x ()"] {
+            let term = parse_term_str(source).unwrap();
+            assert!(
+                types::check_term(&mut ctx, &term, &any).is_ok(),
+                "{}",
+                source
+            );
+        }
+        // The term is still checked, `y` is unbound and `()` is not a function
+        for source in ["λx. y", "λx. λy. () x", "() ()", "(λx. x) y", "fix y"] {
+            let term = parse_term_str(source).unwrap();
+            assert!(
+                types::check_term(&mut ctx, &term, &any).is_err(),
+                "{}",
+                source
+            );
+        }
+        // Checking against a type that isn't the wildcard still synthesizes
+        let term = parse_term_str("() ()").unwrap();
+        assert!(types::check_term(&mut ctx, &term, &Rc::new(Type::Unit)).is_err());
+    }
+    #[test]
+    fn test_cyclic_imports() {
+        // Modules are looked up relative to the working directory
+        let files = [
+            ("tlccycletesta.lc", "import B from tlccycletestb; A = ();"),
+            ("tlccycletestb.lc", "import A from tlccycletesta; B = ();"),
+            (
+                "tlccycletestself.lc",
+                "import S from tlccycletestself; S = ();",
+            ),
+        ];
+        for (path, source) in files {
+            std::fs::write(path, source).unwrap();
+        }
+        let import_from = |name: &str, module: &str| {
+            import(
+                &[name.to_string()],
+                module,
+                &mut HashMap::new(),
+                &mut Ctx::new(),
+            )
+        };
+        let a = import_from("A", "tlccycletesta");
+        let own = import_from("S", "tlccycletestself");
+        let load = load_files(
+            &["tlccycletestb.lc"],
+            &mut HashMap::new(),
+            &mut Ctx::new(),
+            false,
+            PRINT_NONE,
+            Strategy::Normal,
+            false,
+        );
+        for (path, _) in files {
+            std::fs::remove_file(path).unwrap();
+        }
+        assert_eq!(
+            a.unwrap_err(),
+            "Cyclic import: tlccycletesta -> tlccycletestb -> tlccycletesta"
+        );
+        assert_eq!(
+            own.unwrap_err(),
+            "Cyclic import: tlccycletestself -> tlccycletestself"
+        );
+        // Files loaded from the command line are part of the cycle under their path
+        assert_eq!(
+            load.unwrap_err(),
+            "Cyclic import: tlccycletestb.lc -> tlccycletesta -> tlccycletestb"
+        );
+        // Nothing is left marked as loading after an error
+        assert!(import_from("Id", "std").is_err());
+        assert!(import_from("Succ", "std").is_ok());
+    }
+    #[test]
+    fn test_print_each_result() {
+        static OUTPUT: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        eval_prog_results(
+            "I : () -> () = λx. x;\nI ();\nU = ();\n(λy. y) I;\nλz. z;".into(),
+            &mut HashMap::new(),
+            &mut Ctx::new(),
+            false,
+            Printer {
+                out: |t, _| OUTPUT.lock().unwrap().push(t),
+                palette: &PLAIN,
+            },
+            Strategy::Normal,
+            false,
+            true,
+        )
+        .unwrap();
+        // Assignments are not printed, each term is printed once
+        assert_eq!(*OUTPUT.lock().unwrap(), ["()", "λx. x", "λz. z"]);
+    }
+    #[test]
+    fn test_arrow_abstraction() {
+        // The bodies start in the same column, so the positions are the same as well
+        for (arrow, dot) in [
+            ("λx -> x", "λx.   x"),
+            ("\\f, x -> f x", "\\f, x.   f x"),
+            ("λx ->\n  λy -> y x", "λx.\n  λy.   y x"),
+        ] {
+            assert_eq!(parse_term_str(arrow).unwrap(), parse_term_str(dot).unwrap());
+        }
+        assert_eq!(
+            print::term(&parse_term_str("λx -> x").unwrap(), &PLAIN),
+            "λx. x"
+        );
+        // The arrow after an annotation belongs to its type
+        let typed = parse_term_str("λx : A -> B. x").unwrap();
+        assert_eq!(print::term(&typed, &PLAIN), "λx : A -> B. x");
+    }
+    #[test]
+    fn test_term_hash() {
+        let hash = |source: &str| term_hash(&parse_term_str(source).unwrap());
+        assert_eq!(hash("λx. λy. x y"), hash("λa. λb. a b"));
+        assert_eq!(hash("λx : A. x"), hash("λx. x"));
+        assert_ne!(hash("λx. λy. x"), hash("λx. λy. y"));
+        assert_ne!(hash("λx. y"), hash("λx. z"));
+        assert_ne!(hash("(λx. x : A -> A)"), hash("λx. x"));
+    }
+    #[test]
+    fn test_cycle_detection() {
+        let mut env = HashMap::new();
+        // Terms that reduce back to themselves have no normal form
+        for source in [
+            "(λx. x x) (λx. x x)",
+            "fix (λf. f)",
+            "(λx. λy. y) z ((λx. x x) (λx. x x))",
+        ] {
+            let term = parse_term_str(source).unwrap();
+            assert!(
+                matches!(
+                    reduce_to_normal_form(&term, &env, false, PRINT_NONE),
+                    Err(EvalError::Cycle(_))
+                ),
+                "{}",
+                source
+            );
+        }
+        // Terminating reductions still reach their normal form
+        load_std(&mut env).unwrap();
+        let term = parse_term_str("Add 2 3").unwrap();
+        let result = reduce_to_normal_form(&term, &env, false, PRINT_NONE).unwrap();
+        assert_eq!(decode_numeral(&result, &env), Some(5));
+        let term = parse_term_str("letrec f = λn. IsZero n () (f 0) in f 3").unwrap();
+        let result = reduce_to_normal_form(&term, &env, false, PRINT_NONE).unwrap();
+        assert!(matches!(result, Term::Unit(_)));
+    }
+    #[test]
+    fn test_ast_tree() {
+        let term = parse_term_str("(λx. x) y").unwrap();
+        assert_eq!(
+            print::ast_tree(&term, &PLAIN),
+            "Application at 1:1\n  Abstraction x at 1:2\n    Variable x at 1:6\n  Variable y at 1:9"
+        );
+        let term = parse_term_str("(λx : A. x : A -> A)").unwrap();
+        assert_eq!(
+            print::ast_tree(&term, &PLAIN),
+            "Ascription : A -> A at 1:1\n  Abstraction x : A at 1:2\n    Variable x at 1:10"
+        );
+    }
+    #[test]
+    fn test_underscore_binder() {
+        let env = HashMap::new();
+        let ignore = parse_term_str("λ_. y").unwrap();
+        assert_eq!(print::term(&ignore, &PLAIN), "λ_. y");
+        assert_eq!(free_vars(&ignore), HashSet::from(["y".to_string()]));
+        assert!(parse_term_str("λ_ : A, x. x").is_ok());
+        // The body can't refer to the binder, and `_` is no prefix of a name
+        assert!(parse_term_str("λ_. _").is_err());
+        assert!(parse_term_str("λ_x. y").is_err());
+        let term = parse_term_str("(λ_. y) z").unwrap();
+        let result = reduce_to_normal_form(&term, &env, false, PRINT_NONE).unwrap();
+        assert_eq!(print::term(&result, &PLAIN), "y");
+        // Nested binders ignore their own arguments
+        let term = parse_term_str("(λ_. λ_. λx. x) a b c").unwrap();
+        let result = reduce_to_normal_form(&term, &env, false, PRINT_NONE).unwrap();
+        assert_eq!(print::term(&result, &PLAIN), "c");
+    }
+    #[test]
+    fn test_print_truncated() {
+        let shown = |source: &str, depth, nodes| {
+            print::term(
+                &print::truncate(&parse_term_str(source).unwrap(), depth, nodes),
+                &PLAIN,
+            )
+        };
+        // Church numeral 3 is nested six deep, counting the variable `x`
+        let three = "λf. λx. f (f (f x))";
+        assert_eq!(shown(three, 3, 0), "λf. λx. … …");
+        assert_eq!(shown(three, 4, 0), "λf. λx. f (… …)");
+        assert_eq!(shown(three, 0, 5), "λf. λx. f (… …)");
+        // Terms within the limits are printed in full
+        assert_eq!(shown(three, 6, 0), three);
+        assert_eq!(shown(three, 0, 0), three);
+        assert_eq!(shown("f (x : A)", 3, 10), "f (x : A)");
+        assert_eq!(shown("f (x : A)", 2, 0), "f (… : A)");
+    }
 }
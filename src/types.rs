@@ -1,10 +1,28 @@
-use std::{collections::HashMap, rc::Rc};
+use std::{
+    collections::HashMap,
+    rc::Rc,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
-use crate::parser::{Expr, LineInfo, Program, Term, Type};
+use crate::{
+    eval::free_vars,
+    parser::{Expr, LineInfo, Program, Term, Type},
+};
 
 pub type Ctx = HashMap<String, Rc<Type>>;
 
-#[derive(Debug)]
+static TRACE: AtomicBool = AtomicBool::new(false);
+
+/// Print what the type checker is doing while it checks and infers terms
+pub fn set_trace(enabled: bool) {
+    TRACE.store(enabled, Ordering::Relaxed);
+}
+
+fn trace() -> bool {
+    TRACE.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone)]
 pub enum TypeError {
     Mismatch {
         expected: Type,
@@ -13,121 +31,205 @@ pub enum TypeError {
     },
     NotAFunction(Type, LineInfo),
     Unbound(String, LineInfo),
+    /// A use of a variable is annotated with a type contradicting the annotation where it is bound
+    AnnotationConflict {
+        name: String,
+        first: Type,
+        second: Type,
+        info: LineInfo,
+    },
 }
 
+/// Check every expression of a program, the context is only updated if all of them type check
 pub fn check_program(ctx: &mut Ctx, prog: &mut Program) -> Result<(), TypeError> {
+    let mut checked = ctx.clone();
+    declare_bindings(&mut checked, prog);
     for expr in prog.iter() {
-        check_expr(ctx, expr)?;
+        check_expr(&mut checked, expr)?;
     }
+    *ctx = checked;
     // Remove all type definitions from the context after checking
     prog.retain(|expr| !matches!(expr, Expr::TypeDef(_, _)));
     Ok(())
 }
 
+/// Bring the annotated top-level bindings of a program into the context before checking any body,
+/// so definitions can refer to later ones, like mutually recursive functions.
+/// Names already in the context keep their type until their own definition is checked.
+fn declare_bindings(ctx: &mut Ctx, prog: &Program) {
+    // Type definitions first, so annotations can use aliases defined later in the program
+    for expr in prog.iter() {
+        if let Expr::TypeDef(name, ty) = expr {
+            ctx.entry(name.clone())
+                .or_insert_with(|| Rc::new(ty.clone()));
+        }
+    }
+    for expr in prog.iter() {
+        if let Expr::Assignment(name, Some(ty), _, _) = expr {
+            if !ctx.contains_key(name) {
+                let ty = Rc::new(resolve_type(ctx, ty));
+                ctx.insert(name.clone(), ty);
+            }
+        }
+    }
+}
+
 pub fn check_expr(ctx: &mut Ctx, expr: &Expr) -> Result<Rc<Type>, TypeError> {
     match expr {
-        Expr::Assignment(target, expected, body) => {
+        Expr::Assignment(target, expected, body, _) => {
             // Infer the body and bind it to the target
             check_bind(ctx, target, expected, body)
         }
         Expr::TypeDef(target, ty) => {
             // Insert the type definition into the context
-            println!("Inserting type definition: {} = {}", target, ty);
+            if trace() {
+                println!("Inserting type definition: {} = {}", target, ty);
+            }
             ctx.insert(target.clone(), Rc::new(ty.clone()));
             Ok(Rc::new(ty.clone()))
         }
+        // Imports are resolved and pragmas applied before type checking
+        Expr::Import(_, _) | Expr::Pragma(_, _) => Ok(Rc::new(Type::Any)),
         Expr::Term(term) => infer_term(ctx, term),
     }
 }
 
-/// Checking: Γ ⊢ x = body ⇒ T or Γ ⊢ x: T = body ⇒ T
+/// Checking: Γ ⊢ x = body ⇒ T or Γ ⊢ x: T = body ⇒ T.
+/// An annotated target is checked against its annotation and bound to it, even if it was bound before.
+/// An unannotated target keeps the type it is bound to and the body is checked against it, an unbound one is
+/// bound to the inferred type of the body. The context is unchanged if checking fails.
 fn check_bind(
     ctx: &mut Ctx,
     target: &str,
     expected: &Option<Type>,
     body: &Term,
 ) -> Result<Rc<Type>, TypeError> {
-    // let ty_def = infer(ctx, def)?;
-    //         ctx.insert(x.clone(), ty_def);
-    //         let result = infer(ctx, body);
-    //         ctx.remove(x);
-    //         result
-
-    // Check if the target is already bound
-
-    // if let Some(expected_ty) = expected {
-    //     if let Some(existing_ty) = ctx.get(target) {
-    //         if *expected_ty != **existing_ty {
-    //             Err(TypeError::Mismatch {
-    //                 expected: (*expected_ty).clone(),
-    //                 found: (**existing_ty).clone(),
-    //                 info: body.info().clone(),
-    //             })
-    //         } else {
-    //             Ok(Rc::new(expected_ty.clone()))
-    //         }
-    //     } else {
-    //         // If not bound, insert the expected type
-    //         ctx.insert(target.to_string(), Rc::new(expected_ty.clone()));
-    //         // Now check the body against the expected type
-    //         let inferred = infer_term(ctx, body)?;
-    //         if *expected_ty != *inferred {
-    //             return Err(TypeError::Mismatch {
-    //                 expected: (*expected_ty).clone(),
-    //                 found: (*inferred).clone(),
-    //                 info: body.info().clone(),
-    //             });
-    //         }
-    //         Ok(Rc::new(expected_ty.clone()))
-    //     }
-    // } else {
-    //     let inferred = infer_term(ctx, body)?;
-    //     ctx.insert(target.to_string(), inferred.clone());
-    //     // If no expected type, just return the inferred type
-    //     Ok(inferred)
-    // }
-    match infer_var(ctx, target, expected, body.info()) {
-        Ok(ty) => {
-            // Now check the body against the inferred type
+    if let Some(expected) = expected {
+        let expected_ty = Rc::new(resolve_type(ctx, expected));
+        // The body may refer to the target, like a recursive function
+        with_binding(ctx, target, expected_ty.clone(), |ctx| {
+            check_term(ctx, body, &expected_ty)
+        })?;
+        ctx.insert(target.to_string(), expected_ty.clone());
+        return Ok(expected_ty);
+    }
+    match ctx.get(target).cloned() {
+        Some(ty) => {
             check_term(ctx, body, &ty)?;
             Ok(ty)
         }
-        Err(TypeError::Unbound(_, _)) if expected.is_some() => {
-            let expected_ty = Rc::new(resolve_type(ctx, expected.as_ref().unwrap()));
-            println!(
-                "Variable `{}` is unbound, expected type: {}",
-                target,
-                expected.clone().unwrap_or_default()
-            );
-            // If the variable is unbound but we have an expected type, we can insert it
-            ctx.insert(target.to_string(), expected_ty.clone());
-            check_term(ctx, body, &expected_ty)?;
-            Ok(expected_ty)
-        }
-        Err(TypeError::Unbound(_, _)) => {
-            // If the variable is unbound and no expected type, we can infer it
+        None => {
             let inferred_ty = infer_term(ctx, body)?;
-            println!(
-                "Variable `{}` is unbound, inferred type: {}",
-                target, inferred_ty
-            );
+            if trace() {
+                println!(
+                    "Variable `{}` is unbound, inferred type: {}",
+                    target, inferred_ty
+                );
+            }
             ctx.insert(target.to_string(), inferred_ty.clone());
             Ok(inferred_ty)
         }
-        Err(err) => Err(err),
     }
 }
 
+/// Run `f` with `name : ty` in the context, then restore the binding `name` had before, even if `f` fails
+fn with_binding<T>(ctx: &mut Ctx, name: &str, ty: Rc<Type>, f: impl FnOnce(&mut Ctx) -> T) -> T {
+    let shadowed = ctx.insert(name.to_string(), ty);
+    let result = f(ctx);
+    match shadowed {
+        Some(ty) => ctx.insert(name.to_string(), ty),
+        None => ctx.remove(name),
+    };
+    result
+}
+
+/// Check that the uses of a parameter in `body`, like `λx: A. (x : B)`, are not annotated with a type contradicting its annotation `A`
+fn check_annotations(
+    ctx: &Ctx,
+    param: &str,
+    ty: &Option<Type>,
+    body: &Term,
+) -> Result<(), TypeError> {
+    let Some(ty) = ty else {
+        return Ok(());
+    };
+    let first = resolve_type(ctx, ty);
+    let mut terms = vec![body];
+    while let Some(term) = terms.pop() {
+        let annotation = match term {
+            Term::Variable(x, Some(ann), info) if x == param => Some((ann, info)),
+            Term::Ascription(e, ann, info) => match e.as_ref() {
+                Term::Variable(x, _, _) if x == param => Some((ann, info)),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some((ann, info)) = annotation {
+            let second = resolve_type(ctx, ann);
+            if !compare_types(&second, &first) {
+                return Err(TypeError::AnnotationConflict {
+                    name: param.to_string(),
+                    first,
+                    second,
+                    info: info.clone(),
+                });
+            }
+        }
+        match term {
+            // A binder of the same name shadows the parameter
+            Term::Abstraction(x, _, _, _) if x == param => {}
+            Term::Abstraction(_, _, body, _) => terms.push(body),
+            Term::Application(f, x, _) => terms.extend([f.as_ref(), x.as_ref()]),
+            Term::Fix(e, _) | Term::Ascription(e, _, _) => terms.push(e),
+            Term::Variable(_, _, _) | Term::Unit(_) => {}
+        }
+    }
+    Ok(())
+}
+
 /// Checking: Γ ⊢ e ⇐ T   (returns () on success)
 pub fn check_term(ctx: &mut Ctx, e: &Term, expected: &Rc<Type>) -> Result<(), TypeError> {
-    println!("Checking term: {}, expected: {}", e, expected);
+    if trace() {
+        println!("Checking term: {}, expected: {}", e, expected);
+    }
     match (e, expected.as_ref()) {
-        (Term::Abstraction(x, _, body, _), Type::Abstraction(param, ret)) => {
-            ctx.insert(x.clone(), param.clone());
-            let res = check_term(ctx, body, ret);
-            ctx.remove(x);
-            res
+        // Γ, x : A ⊢ e ⇐ *   implies   Γ ⊢ λx : A. e ⇐ *, without an annotation A is the wildcard too.
+        // Other terms are checked as usual, every type they synthesize matches the wildcard
+        (Term::Abstraction(x, ty, body, _), Type::Any) => {
+            check_annotations(ctx, x, ty, body)?;
+            let param = match ty {
+                Some(ty) => Rc::new(resolve_type(ctx, ty)),
+                None => expected.clone(),
+            };
+            with_binding(ctx, x, param, |ctx| check_term(ctx, body, expected))
         }
+        (Term::Abstraction(x, ty, body, _), Type::Abstraction(param, ret)) => {
+            check_annotations(ctx, x, ty, body)?;
+            // An annotated parameter keeps its own type when the expected one is compatible,
+            // so a wildcard like `* -> B` doesn't erase what the abstraction says about `x`
+            let param = match ty {
+                Some(ty) => {
+                    let ty = resolve_type(ctx, ty);
+                    if !compare_types(&ty, param) {
+                        return Err(TypeError::Mismatch {
+                            expected: (**param).clone(),
+                            found: ty,
+                            info: e.info().clone(),
+                        });
+                    }
+                    Rc::new(ty)
+                }
+                None => param.clone(),
+            };
+            with_binding(ctx, x, param, |ctx| check_term(ctx, body, ret))
+        }
+        // Γ ⊢ e ⇐ T → T   implies   Γ ⊢ fix e ⇐ T
+        (Term::Fix(f, _), _) => {
+            let fn_ty = Rc::new(Type::Abstraction(expected.clone(), expected.clone()));
+            check_term(ctx, f, &fn_ty)
+        }
+        // Γ ⊢ f a1 … an ⇐ T, checked along the spine of the application
+        (Term::Application(_, _, _), _) => check_spine(ctx, e, expected),
         // fall back to synthesis + equality
         _ => {
             let inferred = infer_term(ctx, e)?;
@@ -144,6 +246,50 @@ pub fn check_term(ctx: &mut Ctx, e: &Term, expected: &Rc<Type>) -> Result<(), Ty
     }
 }
 
+/// Checking an application against the type of its result: Γ ⊢ f a1 … an ⇐ T.
+/// If the function synthesizes Γ ⊢ f ⇒ A1 → … → An → T', each argument is checked Γ ⊢ ai ⇐ Ai and T' compared with T.
+/// An unannotated abstraction is instead checked against the types of its arguments: Γ ⊢ ai ⇒ Ai and Γ ⊢ f ⇐ A1 → … → An → T.
+fn check_spine(ctx: &mut Ctx, e: &Term, expected: &Rc<Type>) -> Result<(), TypeError> {
+    // Arguments with the application they are passed in, outermost first
+    let mut args = Vec::new();
+    let mut head = e;
+    while let Term::Application(f, arg, info) = head {
+        args.push((arg.as_ref(), info));
+        head = f;
+    }
+    if let Term::Abstraction(_, _, _, _) = head {
+        let mut fn_ty = expected.clone();
+        for (arg, _) in &args {
+            fn_ty = Rc::new(Type::Abstraction(infer_term(ctx, arg)?, fn_ty));
+        }
+        return check_term(ctx, head, &fn_ty);
+    }
+    let mut ty = infer_term(ctx, head)?;
+    for (arg, info) in args.into_iter().rev() {
+        ty = match ty.as_ref() {
+            Type::Abstraction(param, ret) => {
+                check_term(ctx, arg, param)?;
+                ret.clone()
+            }
+            // Any type can be a function of any type, applying a term that never returns never returns either
+            Type::Any | Type::Never => {
+                infer_term(ctx, arg)?;
+                ty.clone()
+            }
+            other => return Err(TypeError::NotAFunction((*other).clone(), info.clone())),
+        };
+    }
+    if compare_types(expected, &ty) {
+        Ok(())
+    } else {
+        Err(TypeError::Mismatch {
+            expected: (*expected.as_ref()).clone(),
+            found: (*ty).clone(),
+            info: e.info().clone(),
+        })
+    }
+}
+
 /// Synthesis: Γ ⊢ e ⇒ T
 fn infer_term(ctx: &mut Ctx, e: &Term) -> Result<Rc<Type>, TypeError> {
     match e {
@@ -174,26 +320,75 @@ fn infer_term(ctx: &mut Ctx, e: &Term) -> Result<Rc<Type>, TypeError> {
             // ctx.get(x)
             //     .cloned()
             //     .ok_or(TypeError::Unbound(x.clone(), e.info().clone()))
-            println!(
-                "Inferring variable: {}, expected: {}",
-                x,
-                expected.clone().unwrap_or_default()
-            );
+            if trace() {
+                println!(
+                    "Inferring variable: {}, expected: {}",
+                    x,
+                    expected.clone().unwrap_or_default()
+                );
+            }
             infer_var(ctx, x, expected, e.info())
         }
-        Term::Abstraction(param, _, body, _) => {
-            let param_ty = Rc::new(Type::Variable(param.to_string()));
-            ctx.insert(param.clone(), param_ty.clone());
-            let ret_ty = infer_term(ctx, body)?;
-            ctx.remove(param);
+        Term::Abstraction(param, ty, body, _) => {
+            check_annotations(ctx, param, ty, body)?;
+            // An annotated parameter has its annotated type.
+            // Nothing constrains a parameter the body doesn't use, so it can have any type
+            let param_ty = match ty {
+                Some(ty) => Rc::new(resolve_type(ctx, ty)),
+                None if free_vars(body).contains(param) => {
+                    Rc::new(Type::Variable(param.to_string()))
+                }
+                None => Rc::new(Type::Any),
+            };
+            let ret_ty = with_binding(ctx, param, param_ty.clone(), |ctx| infer_term(ctx, body))?;
             Ok(Rc::new(Type::Abstraction(param_ty, ret_ty)))
         }
-        Term::Application(lhs, rhs, _) => match infer_term(ctx, lhs)?.as_ref() {
-            Type::Abstraction(param, ret) => {
-                check_term(ctx, rhs, param)?;
-                Ok(ret.clone())
+        // Γ ⊢ a ⇒ A and Γ, x: A ⊢ e ⇒ T   implies   Γ ⊢ (λx. e) a ⇒ T, binding `x` like a let, as `letrec` does
+        Term::Application(lhs, rhs, _) => match lhs.as_ref() {
+            Term::Abstraction(param, ty, body, _) => {
+                check_annotations(ctx, param, ty, body)?;
+                // An annotated binder has its annotated type, the argument is checked against it
+                let arg_ty = match ty {
+                    Some(ty) => {
+                        let ty = Rc::new(resolve_type(ctx, ty));
+                        check_term(ctx, rhs, &ty)?;
+                        ty
+                    }
+                    None => infer_term(ctx, rhs)?,
+                };
+                with_binding(ctx, param, arg_ty, |ctx| infer_term(ctx, body))
             }
-            other => Err(TypeError::NotAFunction((*other).clone(), e.info().clone())),
+            _ => match infer_term(ctx, lhs)?.as_ref() {
+                Type::Abstraction(param, ret) => {
+                    check_term(ctx, rhs, param)?;
+                    Ok(ret.clone())
+                }
+                // Any type can be a function of any type, applying a term that never returns never returns either
+                ty @ (Type::Any | Type::Never) => {
+                    infer_term(ctx, rhs)?;
+                    Ok(Rc::new(ty.clone()))
+                }
+                other => Err(TypeError::NotAFunction((*other).clone(), e.info().clone())),
+            },
+        },
+        // () : ()
+        Term::Unit(_) => Ok(Rc::new(Type::Unit)),
+        // Γ ⊢ e ⇐ T   implies   Γ ⊢ (e : T) ⇒ T
+        Term::Ascription(term, ty, _) => {
+            let ty = Rc::new(resolve_type(ctx, ty));
+            check_term(ctx, term, &ty)?;
+            Ok(ty)
+        }
+        // fix : (A -> A) -> A
+        Term::Fix(f, _) => match infer_term(ctx, f)?.as_ref() {
+            Type::Abstraction(param, ret) if compare_types(param, ret) => Ok(param.clone()),
+            Type::Abstraction(param, ret) => Err(TypeError::Mismatch {
+                expected: Type::Abstraction(param.clone(), param.clone()),
+                found: Type::Abstraction(param.clone(), ret.clone()),
+                info: f.info().clone(),
+            }),
+            ty @ (Type::Any | Type::Never) => Ok(Rc::new(ty.clone())),
+            other => Err(TypeError::NotAFunction((*other).clone(), f.info().clone())),
         },
     }
 }
@@ -223,30 +418,67 @@ fn infer_var(
         .ok_or(TypeError::Unbound(name.to_string(), info.clone())) // Placeholder for line info
 }
 
-// Lookup type names in context
-fn resolve_type(ctx: &Ctx, ty: &Type) -> Type {
-    match ty {
-        Type::Any => Type::Any, // Represents any type
-        Type::Variable(name) => {
-            if let Some(resolved) = ctx.get(name) {
-                resolved.as_ref().clone()
-            } else {
-                ty.clone()
+// Lookup type names in context, expanding nested aliases fully
+pub fn resolve_type(ctx: &Ctx, ty: &Type) -> Type {
+    fn resolve(ctx: &Ctx, ty: &Type, expanding: &mut Vec<String>) -> Type {
+        match ty {
+            Type::Any => Type::Any, // Represents any type
+            Type::Unit => Type::Unit,
+            Type::Never => Type::Never,
+            Type::Variable(name) => match ctx.get(name) {
+                // Occurs check: don't expand an alias inside its own definition
+                Some(resolved) if !expanding.contains(name) => {
+                    expanding.push(name.clone());
+                    let resolved = resolve(ctx, resolved, expanding);
+                    expanding.pop();
+                    resolved
+                }
+                _ => ty.clone(),
+            },
+            Type::Abstraction(param, ret) => Type::Abstraction(
+                Rc::new(resolve(ctx, param, expanding)),
+                Rc::new(resolve(ctx, ret, expanding)),
+            ),
+            // The bound variable is not an alias in the body, even if one has the same name
+            Type::Forall(var, body) => {
+                expanding.push(var.clone());
+                let body = resolve(ctx, body, expanding);
+                expanding.pop();
+                Type::Forall(var.clone(), Rc::new(body))
             }
         }
-        Type::Abstraction(param, ret) => Type::Abstraction(
-            Rc::new(resolve_type(ctx, param)),
-            Rc::new(resolve_type(ctx, ret)),
-        ),
     }
+    resolve(ctx, ty, &mut Vec::new())
+}
+
+/// Check if a term of type `b` can be used where `a` is expected.
+/// Quantified types are compared up to renaming, `forall a. a -> a` is the same type as `forall b. b -> b`.
+pub fn compare_types(a: &Type, b: &Type) -> bool {
+    compare_bound(a, b, &[])
 }
 
-fn compare_types(a: &Type, b: &Type) -> bool {
+/// Compare types under the quantifiers enclosing them, paired up as the variables bound in `a` and in `b`
+fn compare_bound(a: &Type, b: &Type, bound: &[(&str, &str)]) -> bool {
     match (a, b) {
         (Type::Any, _) | (_, Type::Any) => true, // Any type matches with any type
-        (Type::Variable(name_a), Type::Variable(name_b)) => name_a == name_b,
+        (_, Type::Never) => true,                // Never is a subtype of every type
+        // Bound variables match if the same quantifier binds them, free ones by name
+        (Type::Variable(name_a), Type::Variable(name_b)) => {
+            match bound.iter().rev().find(|(a, b)| a == name_a || b == name_b) {
+                Some((a, b)) => a == name_a && b == name_b,
+                None => name_a == name_b,
+            }
+        }
+        (Type::Unit, Type::Unit) => true,
+        // Parameters are contravariant, a function taking any argument can be used where one taking `!` is expected
         (Type::Abstraction(param_a, ret_a), Type::Abstraction(param_b, ret_b)) => {
-            compare_types(param_a, param_b) && compare_types(ret_a, ret_b)
+            let flipped: Vec<(&str, &str)> = bound.iter().map(|(a, b)| (*b, *a)).collect();
+            compare_bound(param_b, param_a, &flipped) && compare_bound(ret_a, ret_b, bound)
+        }
+        (Type::Forall(var_a, body_a), Type::Forall(var_b, body_b)) => {
+            let mut bound = bound.to_vec();
+            bound.push((var_a, var_b));
+            compare_bound(body_a, body_b, &bound)
         }
         _ => false,
     }
@@ -0,0 +1,117 @@
+//! Bindings for running the interpreter in a browser, built with the `wasm` feature.
+//! Results are returned as JS objects, terms in the same shape as `--emit json`.
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    emit::ErrorReport,
+    eval::{self, Env},
+    parser::{self, Term},
+    print, Config, Error, RunResult,
+};
+
+/// What running a program produced, like `RunResult` with printed terms and error reports
+#[derive(Debug, Serialize)]
+pub struct RunOutput {
+    pub terms: Vec<Term>,
+    /// The terms printed without colors
+    pub printed: Vec<String>,
+    pub steps: Vec<usize>,
+    pub warnings: Vec<String>,
+    pub errors: Vec<ErrorReport>,
+}
+
+/// The result of a single reduction step, `term` is `None` if the term is in normal form
+#[derive(Debug, Serialize)]
+pub struct StepOutput {
+    pub term: Option<Term>,
+    pub printed: Option<String>,
+    pub errors: Vec<ErrorReport>,
+}
+
+/// Reports for the errors of a run, errors without a position are reported at line 0
+fn reports(err: &Error) -> Vec<ErrorReport> {
+    let report = |code, message: String| ErrorReport {
+        code,
+        message,
+        line: 0,
+        col: 0,
+        expected: None,
+        found: None,
+    };
+    match err {
+        Error::Parse(errors) => errors.iter().map(ErrorReport::from).collect(),
+        Error::Type(err) => vec![ErrorReport::from(err)],
+        Error::Import(message) => vec![report("import", message.clone())],
+        Error::Eval(err) => vec![report("eval", err.to_string())],
+        Error::Only(message) => vec![report("only", message.clone())],
+    }
+}
+
+/// Run a program like `crate::run`, keeping everything it produced
+pub fn run_output(source: &str, config: &Config) -> RunOutput {
+    let RunResult {
+        terms,
+        steps,
+        warnings,
+        error,
+        ..
+    } = crate::run(source, config);
+    RunOutput {
+        printed: terms.iter().map(print::plain_term).collect(),
+        terms,
+        steps,
+        warnings,
+        errors: error.as_ref().map(reports).unwrap_or_default(),
+    }
+}
+
+/// Reduce a term by a single step, with the standard library in scope if `use_std` is set
+pub fn step_output(source: &str, use_std: bool) -> StepOutput {
+    let mut env = Env::new();
+    if use_std {
+        if let Err(err) = eval::load_std(&mut env) {
+            return StepOutput {
+                term: None,
+                printed: None,
+                errors: reports(&Error::Import(err)),
+            };
+        }
+    }
+    match parser::parse_term_str(source) {
+        Ok(term) => {
+            let term = eval::step(&term, &env);
+            StepOutput {
+                printed: term.as_ref().map(print::plain_term),
+                term,
+                errors: Vec::new(),
+            }
+        }
+        Err(err) => StepOutput {
+            term: None,
+            printed: None,
+            errors: vec![ErrorReport::from(&err)],
+        },
+    }
+}
+
+fn to_js<T: Serialize>(value: &T) -> JsValue {
+    serde_wasm_bindgen::to_value(value).unwrap_or_else(|err| JsValue::from_str(&err.to_string()))
+}
+
+/// Type check and evaluate a program, returning a `RunOutput` object
+#[wasm_bindgen]
+pub fn run(source: &str, use_std: bool) -> JsValue {
+    let config = Config {
+        use_std,
+        ..Config::default()
+    };
+    to_js(&run_output(source, &config))
+}
+
+/// Reduce a term by a single step, returning a `StepOutput` object
+#[wasm_bindgen]
+pub fn step(source: &str, use_std: bool) -> JsValue {
+    to_js(&step_output(source, use_std))
+}